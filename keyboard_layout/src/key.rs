@@ -1,7 +1,7 @@
 //! This module provides structs for representing physical properties of keys in a keyboard
 
 use ahash::AHashMap;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::slice;
 
@@ -39,7 +39,7 @@ impl Default for Position {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Deserialize, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, Debug)]
 #[repr(u8)]
 pub enum Finger {
     Thumb,  // 0
@@ -63,7 +63,7 @@ impl Finger {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Deserialize, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, Debug)]
 #[repr(u8)]
 pub enum Hand {
     Left,  // 0
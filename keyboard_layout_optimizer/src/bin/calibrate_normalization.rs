@@ -0,0 +1,129 @@
+use keyboard_layout_optimizer::{common, statistics::mean_std};
+use layout_optimization_common::LayoutPermutator;
+
+use ahash::AHashMap;
+use clap::Parser;
+use rayon::prelude::*;
+use std::{env, fs};
+
+#[derive(Parser, Debug)]
+#[clap(name = "Metric normalization calibration")]
+/// Calibrate the normalization of the enabled metrics using their distribution over random layouts.
+///
+/// Each metric's normalization value is scaled such that the (normalized) metric costs have a
+/// standard deviation of one over the sampled random layouts. Consequently, one unit of weight
+/// corresponds to one standard deviation of the metric and weights of different metrics become
+/// comparable. The normalization type (fixed, weight_found, weight_all) remains unchanged.
+struct Options {
+    /// Number of random layouts to evaluate
+    #[clap(long, default_value = "1000")]
+    samples: usize,
+
+    /// Do not permute those keys of the base layout
+    #[clap(short, long)]
+    fix: Option<String>,
+
+    /// General parameters
+    #[clap(flatten)]
+    general_parameters: common::Options,
+
+    /// Write the calibrated evaluation config to this file (otherwise print it to stdout)
+    #[clap(short, long)]
+    out: Option<String>,
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+    env_logger::init();
+    let options = Options::parse();
+
+    // Disable storing worst ngrams for speed boost
+    if env::var("SHOW_WORST").is_err() {
+        env::set_var("SHOW_WORST", "false");
+    };
+
+    if options.samples < 2 {
+        panic!("At least two samples are required for estimating standard deviations.");
+    }
+
+    let (layout_generator, evaluator) = common::init(&options.general_parameters);
+    let base_layout_str = layout_generator.base_layout_string();
+    let base_layout = layout_generator
+        .generate(&base_layout_str)
+        .unwrap_or_else(|e| panic!("Could not generate base layout: {:?}", e));
+
+    let mut eval_params_yaml =
+        common::read_evaluation_parameters_yaml(&options.general_parameters.eval_parameters);
+    let metric_names = common::enabled_metric_names(&eval_params_yaml);
+    let result_names = common::metric_result_names(&eval_params_yaml, &metric_names, &base_layout);
+
+    // evaluate random layouts and collect the (normalized but unweighted) cost of each metric
+    let pm = LayoutPermutator::new(&base_layout_str, &options.fix.clone().unwrap_or_default());
+    log::info!("Evaluating {} random layouts", options.samples);
+    let samples: Vec<Vec<f64>> = (0..options.samples)
+        .into_par_iter()
+        .map(|_| {
+            let layout_str = pm.generate_string(&pm.generate_random());
+            let layout = layout_generator
+                .generate(&layout_str)
+                .unwrap_or_else(|e| panic!("Could not generate layout: {:?}", e));
+            let costs: AHashMap<String, f64> = evaluator
+                .evaluate_layout(&layout)
+                .iter()
+                .flat_map(|results| results.metric_costs.iter())
+                .map(|metric_cost| (metric_cost.core.name.clone(), metric_cost.unweighted_cost))
+                .collect();
+
+            result_names
+                .iter()
+                .map(|name| *costs.get(name).unwrap_or(&0.0))
+                .collect()
+        })
+        .collect();
+
+    println!(
+        "{:<40} {:>12} {:>12} {:>14} {:>14}",
+        "Metric", "Mean", "Std", "Old norm.", "New norm."
+    );
+    for (m, name) in metric_names.iter().enumerate() {
+        let values: Vec<f64> = samples.iter().map(|costs| costs[m]).collect();
+        let (mean, std) = mean_std(&values);
+
+        let normalization =
+            &mut common::metric_entry_mut(&mut eval_params_yaml, name).unwrap()["normalization"];
+        let old_value = normalization["value"].as_f64().unwrap_or(1.0);
+        if std < 1e-12 {
+            log::warn!(
+                "Metric '{}' is constant for all random layouts. Its normalization remains unchanged.",
+                name
+            );
+            println!(
+                "{:<40} {:>12.4} {:>12.4} {:>14.6} {:>14}",
+                name, mean, std, old_value, "-"
+            );
+            continue;
+        }
+
+        // the normalized cost is divided by the normalization value
+        let new_value = old_value * std;
+        normalization["value"] = serde_yaml::Value::from(new_value);
+        println!(
+            "{:<40} {:>12.4} {:>12.4} {:>14.6} {:>14.6}",
+            name, mean, std, old_value, new_value
+        );
+    }
+
+    let calibrated = format!(
+        "# Normalizations calibrated from {} random layouts (1 unit of weight = 1 standard deviation)\n{}",
+        options.samples,
+        serde_yaml::to_string(&eval_params_yaml).unwrap(),
+    );
+    match &options.out {
+        Some(filename) => {
+            fs::write(filename, calibrated)
+                .unwrap_or_else(|e| panic!("Could not write to {}: {}", filename, e));
+            log::info!("Wrote calibrated evaluation config to '{}'", filename);
+        }
+        None => println!("\n{}", calibrated),
+    }
+}
@@ -0,0 +1,434 @@
+use keyboard_layout_optimizer::common;
+use layout_evaluation::{
+    evaluation::Evaluator,
+    ngram_mapper::on_demand_ngram_mapper::{NgramMapperConfig, OnDemandNgramMapper},
+    ngrams::{Bigrams, Trigrams, Unigrams},
+};
+
+use ahash::AHashMap;
+use clap::Parser;
+use serde::Deserialize;
+use std::{env, fs};
+
+#[derive(Parser, Debug)]
+#[clap(name = "Metric weight calibration")]
+/// Fit the weights of the enabled metrics to typing times recorded in a keystroke log.
+///
+/// The keystroke log is a CSV file with columns `key` and `timestamp` (in ms) that has been
+/// recorded on the given layout. The `key` column contains the typed symbol or one of the names
+/// `space`, `enter`, and `tab`. Any other multi-character key name (e.g. `backspace`) interrupts
+/// the ngram sequence.
+///
+/// The resulting weights are given in milliseconds per unit of (normalized) metric cost, i.e.
+/// the total cost of an ngram approximates its typing time (up to a constant offset). Each weight
+/// keeps the sign of the configured weight: metrics whose fitted weights have the opposite sign
+/// (e.g. a cost that appears to speed up typing) get a weight of zero.
+struct Options {
+    /// Filename of the keystroke log (CSV with columns `key` and `timestamp`)
+    keystroke_log: String,
+
+    /// Layout on which the keystroke log was recorded (keys from left to right, top to bottom)
+    layout_str: String,
+
+    /// Do not remove whitespace from layout strings
+    #[clap(long)]
+    do_not_remove_whitespace: bool,
+
+    /// Filename of evaluation configuration file whose enabled metrics shall be calibrated
+    #[clap(short, long, default_value = "config/evaluation/default.yml")]
+    eval_parameters: String,
+
+    /// Filename of layout configuration file to use
+    #[clap(short, long, default_value = "config/keyboard/standard.yml")]
+    layout_config: String,
+
+    /// Interpred given layout string using the "grouped" logic
+    #[clap(long)]
+    grouped_layout_generator: bool,
+
+    /// Fit the typing times of bigrams (2) or of trigrams (3)
+    #[clap(long, default_value = "2")]
+    ngram_order: usize,
+
+    /// Break ngrams if the time between two keystrokes exceeds this value (in ms)
+    #[clap(long, default_value = "1000")]
+    max_interval: f64,
+
+    /// Ignore ngrams that occur less often in the keystroke log
+    #[clap(long, default_value = "1")]
+    min_count: usize,
+
+    /// Ridge regularization added to the regression (0.0 corresponds to ordinary least squares)
+    #[clap(long, default_value = "0.0")]
+    ridge: f64,
+
+    /// Write the calibrated evaluation config to this file (otherwise print it to stdout)
+    #[clap(short, long)]
+    out: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KeystrokeRecord {
+    key: String,
+    timestamp: f64,
+}
+
+/// Interpret a key name of the keystroke log as a symbol.
+fn parse_key(key: &str) -> Option<char> {
+    let mut chars = key.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => match key.to_lowercase().as_str() {
+            "space" => Some(' '),
+            "enter" | "return" => Some('\n'),
+            "tab" => Some('\t'),
+            _ => None,
+        },
+    }
+}
+
+/// Read the keystroke log and collect the total typing time and the number of occurrences
+/// for each ngram of given order.
+fn read_ngram_times(
+    filename: &str,
+    ngram_order: usize,
+    max_interval: f64,
+) -> AHashMap<Vec<char>, (f64, usize)> {
+    let mut reader = csv::Reader::from_path(filename)
+        .unwrap_or_else(|e| panic!("Could not read keystroke log {}: {}", filename, e));
+
+    let mut ngram_times: AHashMap<Vec<char>, (f64, usize)> = AHashMap::default();
+    let mut window: Vec<(char, f64)> = Vec::new();
+    for record in reader.deserialize() {
+        let record: KeystrokeRecord = record.expect("Could not read keystroke record!");
+
+        let c = match parse_key(&record.key) {
+            Some(c) => c,
+            None => {
+                window.clear();
+                continue;
+            }
+        };
+
+        if let Some((_, last_timestamp)) = window.last() {
+            if record.timestamp - last_timestamp > max_interval {
+                window.clear();
+            }
+        }
+
+        window.push((c, record.timestamp));
+        if window.len() > ngram_order {
+            window.remove(0);
+        }
+
+        if window.len() == ngram_order {
+            let ngram: Vec<char> = window.iter().map(|(c, _)| *c).collect();
+            let duration = window[ngram_order - 1].1 - window[0].1;
+            let entry = ngram_times.entry(ngram).or_insert((0.0, 0));
+            entry.0 += duration;
+            entry.1 += 1;
+        }
+    }
+
+    ngram_times
+}
+
+/// Generate an ngram mapper for a single ngram. A bigram `ab` results in the unigram `b`
+/// and the bigram `ab`, a trigram `abc` in the unigrams `b`, `c`, the bigrams `ab`, `bc`,
+/// and the trigram `abc`.
+fn ngram_mapper_for(ngram: &[char], config: &NgramMapperConfig) -> OnDemandNgramMapper {
+    let mut unigrams = AHashMap::default();
+    ngram.iter().skip(1).for_each(|c| {
+        *unigrams.entry(*c).or_insert(0.0) += 1.0;
+    });
+
+    let mut bigrams = AHashMap::default();
+    ngram.windows(2).for_each(|w| {
+        *bigrams.entry((w[0], w[1])).or_insert(0.0) += 1.0;
+    });
+
+    let mut trigrams = AHashMap::default();
+    ngram.windows(3).for_each(|w| {
+        *trigrams.entry((w[0], w[1], w[2])).or_insert(0.0) += 1.0;
+    });
+
+    OnDemandNgramMapper::with_ngrams(
+        Unigrams { grams: unigrams },
+        Bigrams { grams: bigrams },
+        Trigrams { grams: trigrams },
+        config.clone(),
+    )
+}
+
+/// Solve the linear system `a * x = b` using gaussian elimination with partial pivoting.
+fn solve_linear_system(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot =
+            (col..n).max_by(|i, j| a[*i][col].abs().partial_cmp(&a[*j][col].abs()).unwrap())?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        let pivot_row = a[col].clone();
+        for row in (col + 1)..n {
+            let factor = a[row][col] / pivot_row[col];
+            a[row]
+                .iter_mut()
+                .zip(pivot_row.iter())
+                .skip(col)
+                .for_each(|(v, p)| *v -= factor * p);
+            b[row] -= factor * b[col];
+        }
+    }
+
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = ((row + 1)..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+
+    Some(x)
+}
+
+/// Weighted least squares fit of the typing times (weighted with the ngrams' counts) by the
+/// costs of the given metrics. Returns the design matrix (with an intercept in the first
+/// column) and the coefficients (the intercept followed by the metrics' weights).
+fn fit(
+    samples: &[(Vec<char>, f64, usize)],
+    features: &[Vec<f64>],
+    metrics: &[usize],
+    ridge: f64,
+) -> (Vec<Vec<f64>>, Vec<f64>) {
+    let design: Vec<Vec<f64>> = features
+        .iter()
+        .map(|x| {
+            let mut row = vec![1.0];
+            row.extend(metrics.iter().map(|m| x[*m]));
+            row
+        })
+        .collect();
+
+    // weighted least squares via the normal equations
+    let n_params = metrics.len() + 1;
+    let mut a = vec![vec![0.0; n_params]; n_params];
+    let mut b = vec![0.0; n_params];
+    design
+        .iter()
+        .zip(samples.iter())
+        .for_each(|(row, (_, time, count))| {
+            let w = *count as f64;
+            for i in 0..n_params {
+                for j in 0..n_params {
+                    a[i][j] += w * row[i] * row[j];
+                }
+                b[i] += w * row[i] * time;
+            }
+        });
+    (1..n_params).for_each(|i| a[i][i] += ridge);
+
+    let coefficients = solve_linear_system(a, b)
+        .expect("Could not fit weights: The metric costs are linearly dependent.");
+
+    (design, coefficients)
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+    env_logger::init();
+    let options = Options::parse();
+
+    // Disable storing worst ngrams for speed boost
+    if env::var("SHOW_WORST").is_err() {
+        env::set_var("SHOW_WORST", "false");
+    };
+
+    if !(2..=3).contains(&options.ngram_order) {
+        panic!("The ngram order needs to be 2 or 3.");
+    }
+
+    let layout_str: String = options
+        .layout_str
+        .chars()
+        .filter(|c| options.do_not_remove_whitespace || !c.is_whitespace())
+        .collect();
+    let layout_generator =
+        common::init_layout_generator(&options.layout_config, options.grouped_layout_generator);
+    let layout = layout_generator
+        .generate(&layout_str)
+        .unwrap_or_else(|e| panic!("Could not generate layout: {:?}", e));
+
+    let mut eval_params_yaml = common::read_evaluation_parameters_yaml(&options.eval_parameters);
+    let eval_params = common::eval_params_from_yaml(&eval_params_yaml);
+
+    // collect the names of all enabled metrics and the names under which they report their results
+    let metric_names = common::enabled_metric_names(&eval_params_yaml);
+    let result_names = common::metric_result_names(&eval_params_yaml, &metric_names, &layout);
+
+    let empty_mapper = ngram_mapper_for(&[], &eval_params.ngram_mapper);
+    let mut evaluator =
+        Evaluator::default(Box::new(empty_mapper)).default_metrics(&eval_params.metrics);
+
+    let ngram_times = read_ngram_times(
+        &options.keystroke_log,
+        options.ngram_order,
+        options.max_interval,
+    );
+    let samples: Vec<(Vec<char>, f64, usize)> = ngram_times
+        .into_iter()
+        .filter(|(_, (_, count))| *count >= options.min_count)
+        .map(|(ngram, (total_time, count))| (ngram, total_time / count as f64, count))
+        .collect();
+    log::info!("Found {} different ngrams in keystroke log.", samples.len());
+
+    // compute the unweighted costs of each metric for each ngram
+    let features: Vec<Vec<f64>> = samples
+        .iter()
+        .map(|(ngram, _, _)| {
+            evaluator
+                .set_ngram_mapper(Box::new(ngram_mapper_for(ngram, &eval_params.ngram_mapper)));
+            let costs: AHashMap<String, f64> = evaluator
+                .evaluate_layout(&layout)
+                .iter()
+                .flat_map(|results| results.metric_costs.iter())
+                .map(|metric_cost| (metric_cost.core.name.clone(), metric_cost.unweighted_cost))
+                .collect();
+
+            result_names
+                .iter()
+                .map(|name| *costs.get(name).unwrap_or(&0.0))
+                .collect()
+        })
+        .collect();
+
+    let total_count: f64 = samples.iter().map(|(_, _, count)| *count as f64).sum();
+
+    // metrics without any variation across the ngrams can not be fitted
+    let mut fitted_metrics: Vec<usize> = (0..metric_names.len())
+        .filter(|m| {
+            let mean: f64 = samples
+                .iter()
+                .zip(features.iter())
+                .map(|((_, _, count), x)| *count as f64 * x[*m])
+                .sum::<f64>()
+                / total_count;
+            let var: f64 = samples
+                .iter()
+                .zip(features.iter())
+                .map(|((_, _, count), x)| *count as f64 * (x[*m] - mean).powi(2))
+                .sum::<f64>()
+                / total_count;
+
+            if var < 1e-12 {
+                log::warn!(
+                    "Metric '{}' is constant for all ngrams. Its weight remains unchanged.",
+                    metric_names[*m]
+                );
+            }
+            var >= 1e-12
+        })
+        .collect();
+
+    // the fitted weights keep the signs of the configured weights (bonuses remain bonuses)
+    let old_weights: Vec<f64> = metric_names
+        .iter()
+        .map(|name| {
+            common::metric_entry_mut(&mut eval_params_yaml, name)
+                .and_then(|entry| entry["weight"].as_f64())
+                .unwrap_or(0.0)
+        })
+        .collect();
+
+    // metrics whose fitted weights have the wrong sign are set to zero and the others are fitted
+    // again (one metric at a time, the one with the largest violation first)
+    let mut zeroed_metrics: Vec<usize> = Vec::new();
+    let (design, coefficients) = loop {
+        let (design, coefficients) = fit(&samples, &features, &fitted_metrics, options.ridge);
+        let violation = |m: usize, coefficient: f64| match old_weights[m] < 0.0 {
+            true => coefficient,
+            false => -coefficient,
+        };
+        let worst = fitted_metrics
+            .iter()
+            .zip(coefficients.iter().skip(1))
+            .enumerate()
+            .map(|(i, (m, c))| (i, violation(*m, *c)))
+            .filter(|(_, v)| *v > 0.0)
+            .max_by(|(_, v1), (_, v2)| v1.partial_cmp(v2).unwrap());
+
+        match worst {
+            Some((i, _)) => {
+                let m = fitted_metrics.remove(i);
+                log::warn!(
+                    "The fitted weight of metric '{}' ({:.4}) has the opposite sign of its \
+                     configured weight. It is set to zero.",
+                    metric_names[m],
+                    coefficients[i + 1]
+                );
+                zeroed_metrics.push(m);
+            }
+            None => break (design, coefficients),
+        }
+    };
+
+    // goodness of fit
+    let mean_time: f64 = samples
+        .iter()
+        .map(|(_, time, count)| *count as f64 * time)
+        .sum::<f64>()
+        / total_count;
+    let (ss_res, ss_tot) = design.iter().zip(samples.iter()).fold(
+        (0.0, 0.0),
+        |(ss_res, ss_tot), (row, (_, time, count))| {
+            let predicted: f64 = row
+                .iter()
+                .zip(coefficients.iter())
+                .map(|(x, c)| x * c)
+                .sum();
+            let w = *count as f64;
+            (
+                ss_res + w * (time - predicted).powi(2),
+                ss_tot + w * (time - mean_time).powi(2),
+            )
+        },
+    );
+    let r_squared = 1.0 - ss_res / ss_tot;
+    let rmse = (ss_res / total_count).sqrt();
+
+    println!("Ngrams: {} ({} occurrences)", samples.len(), total_count);
+    println!("Mean typing time: {:.1}ms", mean_time);
+    println!("Intercept: {:.2}ms", coefficients[0]);
+    println!("R²: {:.4}", r_squared);
+    println!("RMSE: {:.2}ms", rmse);
+    println!("{:<40} {:>12} {:>12}", "Metric", "Old weight", "New weight");
+    let new_weights = fitted_metrics
+        .iter()
+        .zip(coefficients.iter().skip(1).cloned())
+        .chain(zeroed_metrics.iter().map(|m| (m, 0.0)));
+    for (m, coefficient) in new_weights {
+        let name = &metric_names[*m];
+        let entry = common::metric_entry_mut(&mut eval_params_yaml, name).unwrap();
+        let old_weight = entry["weight"].as_f64().unwrap_or(0.0);
+        println!("{:<40} {:>12.4} {:>12.4}", name, old_weight, coefficient);
+
+        entry["weight"] = serde_yaml::Value::from(coefficient);
+    }
+
+    let calibrated = format!(
+        "# Weights calibrated from keystroke log '{}' (R² = {:.4}, RMSE = {:.2}ms)\n{}",
+        options.keystroke_log,
+        r_squared,
+        rmse,
+        serde_yaml::to_string(&eval_params_yaml).unwrap(),
+    );
+    match &options.out {
+        Some(filename) => {
+            fs::write(filename, calibrated)
+                .unwrap_or_else(|e| panic!("Could not write to {}: {}", filename, e));
+            log::info!("Wrote calibrated evaluation config to '{}'", filename);
+        }
+        None => println!("\n{}", calibrated),
+    }
+}
@@ -1,8 +1,16 @@
 use keyboard_layout::layout::Layout;
-use keyboard_layout_optimizer::common;
-use layout_evaluation::{cache::Cache, results::EvaluationResult};
+use keyboard_layout_optimizer::{
+    bootstrap::{probability_lower, BootstrapSummary, NgramResampler},
+    common,
+};
+use layout_evaluation::{
+    cache::Cache,
+    config::ReferenceLayoutConfig,
+    results::{EvaluationResult, RelativeEvaluationResult},
+};
 
 use clap::Parser;
+use rand::{rngs::StdRng, SeedableRng};
 use rayon::prelude::*;
 use serde::Serialize;
 use std::{
@@ -14,6 +22,10 @@ use std::{
 struct LayoutEvaluation {
     details: EvaluationResult,
     total_cost: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    relative: Option<RelativeEvaluationResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bootstrap: Option<BootstrapEvaluation>,
 }
 
 impl From<EvaluationResult> for LayoutEvaluation {
@@ -22,10 +34,20 @@ impl From<EvaluationResult> for LayoutEvaluation {
         Self {
             details,
             total_cost,
+            relative: None,
+            bootstrap: None,
         }
     }
 }
 
+#[derive(Serialize)]
+struct BootstrapEvaluation {
+    #[serde(flatten)]
+    summary: BootstrapSummary,
+    /// Probability of this layout having lower costs than each of the evaluated layouts
+    probability_lower: Vec<f64>,
+}
+
 #[derive(Parser, Debug)]
 #[clap(name = "Keyboard layout evaluation")]
 struct Options {
@@ -55,6 +77,81 @@ struct Options {
     /// Sort results by total costs
     #[clap(long)]
     sort: bool,
+
+    /// Express costs relative to this layout (instead of the reference layout from the evaluation config)
+    #[clap(long)]
+    reference_layout: Option<String>,
+
+    /// Name of the layout given with --reference-layout
+    #[clap(long, default_value = "reference")]
+    reference_name: String,
+
+    /// Do not express costs relative to a reference layout
+    #[clap(long)]
+    no_reference: bool,
+
+    /// Estimate the uncertainty of the total costs from this number of resamples of the corpus
+    #[clap(long)]
+    bootstrap: Option<usize>,
+
+    /// Number of chunks the corpus is split into for resampling
+    #[clap(long, default_value = "100")]
+    bootstrap_chunks: usize,
+
+    /// Confidence level of the bootstrap confidence intervals
+    #[clap(long, default_value = "0.95")]
+    confidence: f64,
+
+    /// Seed of the random number generator used for resampling
+    #[clap(long)]
+    seed: Option<u64>,
+}
+
+/// Evaluate the layouts on resampled ngrams. Returns the total costs for each layout and resample.
+fn bootstrap_costs(options: &Options, n_resamples: usize, layouts: &[&Layout]) -> Vec<Vec<f64>> {
+    let general_parameters = &options.general_parameters;
+    let eval_params = common::read_evaluation_parameters(&general_parameters.eval_parameters);
+    if general_parameters.corpora.is_some() {
+        log::warn!("Bootstrapping ignores the corpora config and resamples the ngrams given by the ngrams, corpus, or text options.");
+    }
+    let resampler = match common::read_text(general_parameters) {
+        Some(text) => NgramResampler::from_text(&text, options.bootstrap_chunks),
+        None => {
+            log::warn!("No corpus given. Resampling the weights of the ngram frequencies (conservative estimate).");
+            let (unigrams, bigrams, trigrams) = common::read_ngrams(general_parameters);
+            NgramResampler::from_ngrams(unigrams, bigrams, trigrams)
+        }
+    };
+
+    let seed = options.seed.unwrap_or_else(rand::random);
+    let costs_per_resample: Vec<Vec<f64>> = (0..n_resamples)
+        .into_par_iter()
+        .map(|i| {
+            let mut rng = StdRng::seed_from_u64(seed.wrapping_add(i as u64));
+            let (unigrams, bigrams, trigrams) = resampler.resample(&mut rng);
+            let evaluator = common::init_evaluator_with_ngrams(
+                general_parameters,
+                &eval_params,
+                unigrams,
+                bigrams,
+                trigrams,
+            );
+
+            layouts
+                .iter()
+                .map(|layout| evaluator.evaluate_layout(layout).total_cost())
+                .collect()
+        })
+        .collect();
+
+    (0..layouts.len())
+        .map(|layout_idx| {
+            costs_per_resample
+                .iter()
+                .map(|costs| costs[layout_idx])
+                .collect()
+        })
+        .collect()
 }
 
 fn main() {
@@ -111,19 +208,143 @@ fn main() {
         });
     }
 
+    // evaluate the reference layout for expressing costs relatively
+    let reference = if options.no_reference {
+        None
+    } else if let Some(layout) = &options.reference_layout {
+        Some(ReferenceLayoutConfig {
+            name: options.reference_name.clone(),
+            layout: layout.clone(),
+            value: 100.0,
+        })
+    } else {
+        common::read_evaluation_parameters(&options.general_parameters.eval_parameters)
+            .reference_layout
+    };
+    let relative: Option<Vec<RelativeEvaluationResult>> = reference.and_then(|reference| {
+        let layout_str: String = reference
+            .layout
+            .chars()
+            .filter(|c| options.do_not_remove_whitespace || !c.is_whitespace())
+            .collect();
+        match layout_generator.generate(&layout_str) {
+            Ok(layout) => {
+                let reference_result = evaluator.evaluate_layout(&layout);
+                Some(
+                    results
+                        .iter()
+                        .map(|(_, _, res)| {
+                            res.relative_to(&reference_result, &reference.name, reference.value)
+                        })
+                        .collect(),
+                )
+            }
+            Err(e) => {
+                log::warn!(
+                    "Could not generate reference layout '{}': {:?}",
+                    reference.name,
+                    e
+                );
+                None
+            }
+        }
+    });
+
+    // estimate uncertainties using resampled ngrams
+    let bootstrap: Option<Vec<BootstrapEvaluation>> = options.bootstrap.map(|n_resamples| {
+        let layouts: Vec<&Layout> = results.iter().map(|(_, layout, _)| layout).collect();
+        let costs = bootstrap_costs(&options, n_resamples, &layouts);
+
+        costs
+            .iter()
+            .map(|layout_costs| BootstrapEvaluation {
+                summary: BootstrapSummary::from_samples(layout_costs, options.confidence),
+                probability_lower: costs
+                    .iter()
+                    .map(|other_costs| probability_lower(layout_costs, other_costs))
+                    .collect(),
+            })
+            .collect()
+    });
+
     // print results
     if options.json {
-        let results: Vec<LayoutEvaluation> =
-            results.into_iter().map(|(_, _, res)| res.into()).collect();
+        let mut relative = relative.map(|r| r.into_iter());
+        let mut bootstrap = bootstrap.map(|b| b.into_iter());
+        let results: Vec<LayoutEvaluation> = results
+            .into_iter()
+            .map(|(_, _, res)| {
+                let mut layout_evaluation: LayoutEvaluation = res.into();
+                layout_evaluation.relative = relative.as_mut().and_then(|r| r.next());
+                layout_evaluation.bootstrap = bootstrap.as_mut().and_then(|b| b.next());
+                layout_evaluation
+            })
+            .collect();
         println!("{}", serde_json::to_string(&results).unwrap());
     } else {
-        for (layout_str, layout, evaluation_result) in results {
+        for (i, (layout_str, layout, evaluation_result)) in results.iter().enumerate() {
+            let relative_result = relative.as_ref().map(|r| &r[i]);
+            let summary = bootstrap.as_ref().map(|b| &b[i].summary);
             if !options.only_total_costs {
                 println!("Layout (layer 1):\n{}", layout.plot_layer(0));
                 println!("Layout string (layer 1):\n{}\n", layout);
                 println!("{}", evaluation_result);
+                if let Some(relative_result) = relative_result {
+                    println!("{}", relative_result);
+                }
+                if let Some(summary) = summary {
+                    println!(
+                        "Bootstrap: mean {:.2}, std {:.2}, {:.0}% CI [{:.2}, {:.2}]\n",
+                        summary.mean,
+                        summary.std,
+                        100.0 * summary.confidence,
+                        summary.ci_lower,
+                        summary.ci_upper
+                    );
+                }
             } else {
-                println!("{} {:4.2}", layout_str, evaluation_result.total_cost());
+                let mut line = format!("{} {:4.2}", layout_str, evaluation_result.total_cost());
+                if let Some(relative_result) = relative_result {
+                    line.push_str(&format!(
+                        " ({} = {}: {:.1})",
+                        relative_result.reference_name,
+                        relative_result.reference_value,
+                        relative_result.total_cost
+                    ));
+                }
+                if let Some(summary) = summary {
+                    line.push_str(&format!(
+                        " (mean {:.2}, std {:.2}, {:.0}% CI [{:.2}, {:.2}])",
+                        summary.mean,
+                        summary.std,
+                        100.0 * summary.confidence,
+                        summary.ci_lower,
+                        summary.ci_upper
+                    ));
+                }
+                println!("{}", line);
+            }
+        }
+
+        // paired comparison with the layout having the lowest mean costs
+        if let Some(bootstrap) = &bootstrap {
+            if let Some((best_idx, _)) = bootstrap
+                .iter()
+                .enumerate()
+                .min_by(|(_, b1), (_, b2)| b1.summary.mean.partial_cmp(&b2.summary.mean).unwrap())
+            {
+                println!(
+                    "\nProbability of lower costs than best layout '{}':",
+                    results[best_idx].0
+                );
+                for (i, (layout_str, _, _)) in results.iter().enumerate() {
+                    if i != best_idx {
+                        println!(
+                            "{} {:.3}",
+                            layout_str, bootstrap[i].probability_lower[best_idx]
+                        );
+                    }
+                }
             }
         }
     }
@@ -0,0 +1,123 @@
+use clap::Parser;
+use std::path::Path;
+
+use keyboard_layout_optimizer::{
+    common,
+    keystrokes::{
+        ngrams_from_sequences, BackspaceHandling, KeystrokeConfig, KeystrokeConverter,
+        KeystrokeEvent,
+    },
+};
+
+#[derive(Parser, Debug)]
+#[clap(name = "Keystroke ngram frequency generator")]
+/// Generate ngram-frequency files from a keystroke log.
+///
+/// The keystroke log is a CSV file with columns `timestamp` (in ms), `event` (`down` or `up`),
+/// `key`, and optionally `layout`. The `key` column contains the symbol of the key on the base
+/// layer of the active layout (layer modifiers by their symbol, e.g. `⇧`) or one of the names
+/// `space`, `enter`, `tab`, `backspace`, `ctrl`, `alt`, and `meta`. Any other key name (e.g. arrow
+/// keys) interrupts the ngram sequence. The `layout` column may specify a different layout that
+/// was active during the event.
+struct Options {
+    /// Filename of the keystroke log (CSV with columns `timestamp`, `event`, `key`, and `layout`)
+    keystroke_log: String,
+
+    /// Name for resulting ngram frequencies (a directory at that path will be generated)
+    out: String,
+
+    /// Layout that was active while recording the keystroke log (keys from left to right, top to bottom)
+    layout_str: String,
+
+    /// Do not remove whitespace from layout strings
+    #[clap(long)]
+    do_not_remove_whitespace: bool,
+
+    /// Filename of layout configuration file to use
+    #[clap(short, long, default_value = "config/keyboard/standard.yml")]
+    layout_config: String,
+
+    /// Interpred given layout string using the "grouped" logic
+    #[clap(long)]
+    grouped_layout_generator: bool,
+
+    /// Break ngrams if the time between two keystrokes exceeds this value (in ms)
+    #[clap(long, default_value = "1000")]
+    max_pause: f64,
+
+    /// How to treat the backspace key
+    #[clap(long, arg_enum, default_value = "keep")]
+    backspace: BackspaceHandling,
+
+    /// Symbol representing backspace in the ngrams (if it is kept)
+    #[clap(long, default_value = "←")]
+    backspace_symbol: char,
+
+    /// Count keys that are typed as part of shortcuts (e.g. ctrl+c)
+    #[clap(long)]
+    include_shortcuts: bool,
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+    let options = Options::parse();
+    env_logger::init();
+
+    let remove_whitespace = |s: &str| -> String {
+        s.chars()
+            .filter(|c| options.do_not_remove_whitespace || !c.is_whitespace())
+            .collect()
+    };
+
+    let mut reader = csv::Reader::from_path(&options.keystroke_log).unwrap_or_else(|e| {
+        panic!(
+            "Could not read keystroke log {}: {}",
+            options.keystroke_log, e
+        )
+    });
+    let events: Vec<KeystrokeEvent> = reader
+        .deserialize()
+        .map(|record| {
+            let mut event: KeystrokeEvent = record.expect("Could not read keystroke record!");
+            event.layout = event.layout.as_deref().map(remove_whitespace);
+            event
+        })
+        .collect();
+
+    let layout_generator =
+        common::init_layout_generator(&options.layout_config, options.grouped_layout_generator);
+    let config = KeystrokeConfig {
+        max_pause: options.max_pause,
+        backspace: options.backspace,
+        backspace_symbol: options.backspace_symbol,
+        include_shortcuts: options.include_shortcuts,
+    };
+    let mut converter = KeystrokeConverter::new(
+        layout_generator.as_ref(),
+        &remove_whitespace(&options.layout_str),
+        config,
+    );
+    let sequences = converter
+        .sequences(&events)
+        .expect("Could not convert keystroke log.");
+
+    let (unigrams, bigrams, trigrams) = ngrams_from_sequences(&sequences);
+
+    log::info!(
+        "Converted {} keystroke events into {} sequences with {} keystrokes",
+        events.len(),
+        sequences.len(),
+        unigrams.total_weight()
+    );
+
+    let d = Path::new(&options.out);
+
+    let p = d.join("1-grams.txt");
+    unigrams.save_frequencies(p).unwrap();
+
+    let p = d.join("2-grams.txt");
+    bigrams.save_frequencies(p).unwrap();
+
+    let p = d.join("3-grams.txt");
+    trigrams.save_frequencies(p).unwrap();
+}
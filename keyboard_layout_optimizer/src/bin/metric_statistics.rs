@@ -0,0 +1,317 @@
+use keyboard_layout_optimizer::{
+    common,
+    statistics::{mean_std, quantile, sorted},
+};
+use layout_optimization_common::LayoutPermutator;
+
+use clap::Parser;
+use colored::Colorize;
+use rayon::prelude::*;
+use std::{
+    env,
+    fs::File,
+    io::{BufRead, BufReader},
+    ops::Range,
+};
+
+#[derive(Parser, Debug)]
+#[clap(name = "Metric statistics")]
+/// Compute distribution statistics of the (normalized, unweighted) metric costs and their
+/// pairwise correlations across random layouts and given (e.g. optimized) layouts.
+///
+/// Strongly correlated metrics measure nearly the same thing and are candidates for pruning
+/// or for reducing their weights.
+struct Options {
+    /// Layouts (e.g. optimized ones) to include in addition to the random layouts
+    layout_str: Vec<String>,
+
+    /// Do not remove whitespace from layout strings
+    #[clap(long)]
+    do_not_remove_whitespace: bool,
+
+    /// Read layouts from file and append to command line layouts
+    #[clap(long)]
+    from_file: Option<String>,
+
+    /// Number of random layouts to evaluate
+    #[clap(long, default_value = "1000")]
+    samples: usize,
+
+    /// Do not permute those keys of the base layout for random layouts
+    #[clap(short, long)]
+    fix: Option<String>,
+
+    /// General parameters
+    #[clap(flatten)]
+    general_parameters: common::Options,
+
+    /// List metric pairs with an absolute correlation above this value
+    #[clap(long, default_value = "0.8")]
+    highlight: f64,
+
+    /// Write the statistics as CSV to this file
+    #[clap(long)]
+    statistics_csv: Option<String>,
+
+    /// Write the correlation matrix as CSV to this file
+    #[clap(long)]
+    correlation_csv: Option<String>,
+}
+
+/// Distribution statistics of a metric's costs.
+#[derive(Clone, Debug)]
+struct Statistics {
+    count: usize,
+    mean: f64,
+    std: f64,
+    min: f64,
+    median: f64,
+    max: f64,
+}
+
+impl Statistics {
+    fn from_values(values: &[f64]) -> Self {
+        let (mean, std) = mean_std(values);
+        let sorted = sorted(values);
+
+        Self {
+            count: values.len(),
+            mean,
+            std,
+            min: sorted.first().cloned().unwrap_or(f64::NAN),
+            median: quantile(&sorted, 0.5),
+            max: sorted.last().cloned().unwrap_or(f64::NAN),
+        }
+    }
+}
+
+/// Pearson correlation coefficient of two series (NaN if one of them is constant).
+fn correlation(x: &[f64], y: &[f64]) -> f64 {
+    let n = x.len() as f64;
+    let mean_x = x.iter().sum::<f64>() / n;
+    let mean_y = y.iter().sum::<f64>() / n;
+
+    let (cov, var_x, var_y) =
+        x.iter()
+            .zip(y.iter())
+            .fold((0.0, 0.0, 0.0), |(cov, var_x, var_y), (xi, yi)| {
+                let (dx, dy) = (xi - mean_x, yi - mean_y);
+                (cov + dx * dy, var_x + dx * dx, var_y + dy * dy)
+            });
+
+    if var_x < 1e-24 || var_y < 1e-24 {
+        return f64::NAN;
+    }
+
+    cov / (var_x * var_y).sqrt()
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+    env_logger::init();
+    let options = Options::parse();
+
+    // Disable storing worst ngrams for speed boost
+    if env::var("SHOW_WORST").is_err() {
+        env::set_var("SHOW_WORST", "false");
+    };
+
+    let (layout_generator, evaluator) = common::init(&options.general_parameters);
+
+    // collect given layout strings to a vec
+    let mut layout_strings = options.layout_str.to_vec();
+    if let Some(filename) = &options.from_file {
+        match File::open(filename) {
+            Ok(file) => {
+                layout_strings
+                    .append(&mut BufReader::new(file).lines().map_while(Result::ok).collect());
+            }
+            Err(e) => {
+                log::error!("Error reading layouts file {}: {:?}", filename, e);
+                panic!("{:?}", e);
+            }
+        }
+    }
+    let given_layouts: Vec<String> = layout_strings
+        .iter()
+        .map(|layout_str| {
+            layout_str
+                .chars()
+                .filter(|c| options.do_not_remove_whitespace || !c.is_whitespace())
+                .collect()
+        })
+        .filter(|layout_str: &String| !layout_str.is_empty())
+        .collect();
+
+    let pm = LayoutPermutator::new(
+        &layout_generator.base_layout_string(),
+        &options.fix.clone().unwrap_or_default(),
+    );
+    let random_layouts: Vec<String> = (0..options.samples)
+        .map(|_| pm.generate_string(&pm.generate_random()))
+        .collect();
+
+    if random_layouts.len() + given_layouts.len() < 2 {
+        log::error!("At least two layouts are required for computing statistics.");
+        return;
+    }
+
+    // evaluate all layouts and collect the (normalized but unweighted) metric costs
+    log::info!(
+        "Evaluating {} random and {} given layouts",
+        random_layouts.len(),
+        given_layouts.len()
+    );
+    let evaluations: Vec<Vec<(String, f64)>> = random_layouts
+        .par_iter()
+        .chain(given_layouts.par_iter())
+        .map(|layout_str| {
+            let layout = match layout_generator.generate(layout_str) {
+                Ok(layout) => layout,
+                Err(e) => {
+                    log::error!("Error in generating layout: {:?}", e);
+                    panic!("{:?}", e);
+                }
+            };
+            evaluator
+                .evaluate_layout(&layout)
+                .iter()
+                .flat_map(|metric_results| metric_results.metric_costs.iter())
+                .map(|metric_cost| (metric_cost.core.name.clone(), metric_cost.unweighted_cost))
+                .collect()
+        })
+        .collect();
+
+    let metric_names: Vec<String> = evaluations[0]
+        .iter()
+        .map(|(name, _)| name.clone())
+        .collect();
+    // costs per metric (outer) and layout (inner)
+    let costs: Vec<Vec<f64>> = (0..metric_names.len())
+        .map(|m| evaluations.iter().map(|costs| costs[m].1).collect())
+        .collect();
+
+    let n_random = random_layouts.len();
+    let groups: Vec<(&str, Range<usize>)> = vec![
+        ("random", 0..n_random),
+        ("given", n_random..evaluations.len()),
+    ]
+    .into_iter()
+    .filter(|(_, range)| !range.is_empty())
+    .collect();
+
+    // statistics
+    let statistics: Vec<(String, &str, Statistics)> = metric_names
+        .iter()
+        .zip(costs.iter())
+        .flat_map(|(name, metric_costs)| {
+            groups.iter().map(move |(group, range)| {
+                (
+                    name.clone(),
+                    *group,
+                    Statistics::from_values(&metric_costs[range.clone()]),
+                )
+            })
+        })
+        .collect();
+
+    println!("{}", "Statistics of normalized metric costs:".bold());
+    println!(
+        "{:<40} {:>7} {:>6} {:>12} {:>12} {:>12} {:>12} {:>12}",
+        "Metric", "Layouts", "Count", "Mean", "Std", "Min", "Median", "Max"
+    );
+    for (name, group, s) in statistics.iter() {
+        println!(
+            "{:<40} {:>7} {:>6} {:>12.4} {:>12.4} {:>12.4} {:>12.4} {:>12.4}",
+            name, group, s.count, s.mean, s.std, s.min, s.median, s.max
+        );
+    }
+
+    // correlations across all layouts
+    let correlations: Vec<Vec<f64>> = costs
+        .iter()
+        .map(|x| costs.iter().map(|y| correlation(x, y)).collect())
+        .collect();
+
+    println!(
+        "\n{} ({} layouts)",
+        "Correlation matrix:".bold(),
+        evaluations.len()
+    );
+    let header: String = (0..metric_names.len())
+        .map(|j| format!("{:>6}", j + 1))
+        .collect();
+    println!("{:<44}{}", "", header);
+    for (i, (name, row)) in metric_names.iter().zip(correlations.iter()).enumerate() {
+        let row: String = row
+            .iter()
+            .map(|r| {
+                if r.is_nan() {
+                    format!("{:>6}", "-")
+                } else {
+                    format!("{:>6.2}", r)
+                }
+            })
+            .collect();
+        println!("{:>3} {:<40}{}", i + 1, name, row);
+    }
+
+    let mut high_correlations: Vec<(usize, usize, f64)> = (0..metric_names.len())
+        .flat_map(|i| ((i + 1)..metric_names.len()).map(move |j| (i, j)))
+        .map(|(i, j)| (i, j, correlations[i][j]))
+        .filter(|(_, _, r)| r.abs() > options.highlight)
+        .collect();
+    high_correlations.sort_by(|(_, _, r1), (_, _, r2)| r2.abs().partial_cmp(&r1.abs()).unwrap());
+    if !high_correlations.is_empty() {
+        println!(
+            "\n{} (|r| > {}):",
+            "Strongly correlated metrics".bold(),
+            options.highlight
+        );
+        for (i, j, r) in high_correlations {
+            println!("{:>6.3} {} / {}", r, metric_names[i], metric_names[j]);
+        }
+    }
+
+    // CSV outputs
+    if let Some(filename) = &options.statistics_csv {
+        let mut writer = csv::Writer::from_path(filename)
+            .unwrap_or_else(|e| panic!("Could not write to {}: {}", filename, e));
+        writer
+            .write_record([
+                "metric", "layouts", "count", "mean", "std", "min", "median", "max",
+            ])
+            .unwrap();
+        for (name, group, s) in statistics.iter() {
+            writer
+                .write_record(&[
+                    name.clone(),
+                    group.to_string(),
+                    s.count.to_string(),
+                    s.mean.to_string(),
+                    s.std.to_string(),
+                    s.min.to_string(),
+                    s.median.to_string(),
+                    s.max.to_string(),
+                ])
+                .unwrap();
+        }
+        writer.flush().unwrap();
+        log::info!("Wrote statistics to '{}'", filename);
+    }
+
+    if let Some(filename) = &options.correlation_csv {
+        let mut writer = csv::Writer::from_path(filename)
+            .unwrap_or_else(|e| panic!("Could not write to {}: {}", filename, e));
+        let mut header = vec!["metric".to_string()];
+        header.extend(metric_names.iter().cloned());
+        writer.write_record(&header).unwrap();
+        for (name, row) in metric_names.iter().zip(correlations.iter()) {
+            let mut record = vec![name.clone()];
+            record.extend(row.iter().map(|r| r.to_string()));
+            writer.write_record(&record).unwrap();
+        }
+        writer.flush().unwrap();
+        log::info!("Wrote correlation matrix to '{}'", filename);
+    }
+}
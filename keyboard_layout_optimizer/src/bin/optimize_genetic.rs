@@ -1,5 +1,6 @@
 use keyboard_layout_optimizer::common;
 use layout_evaluation::cache::Cache;
+use layout_optimization_common::checkpoint::Checkpointing;
 use layout_optimization_genetic::optimization;
 
 use clap::Parser;
@@ -48,6 +49,23 @@ struct Options {
     #[clap(long)]
     run_forever: bool,
 
+    /// Periodically save the state of the optimization to this file
+    #[clap(long)]
+    checkpoint: Option<String>,
+
+    /// Save a checkpoint every n generations
+    #[clap(long, default_value = "10")]
+    checkpoint_every: u64,
+
+    /// Resume the optimization from this checkpoint file
+    /// (start layout and fixed keys are taken from the checkpoint)
+    #[clap(long)]
+    resume: Option<String>,
+
+    /// Polishing options
+    #[clap(flatten)]
+    polishing_options: common::PolishingOptions,
+
     /// Publishing options
     #[clap(flatten)]
     publishing_options: common::PublishingOptions,
@@ -84,7 +102,10 @@ fn main() {
 
     let (layout_generator, evaluator) = common::init(&options.evaluation_parameters);
 
-    let fix_from_str = options.fix_from.clone().unwrap_or_else(|| layout_generator.base_layout_string());
+    let fix_from_str = options
+        .fix_from
+        .clone()
+        .unwrap_or_else(|| layout_generator.base_layout_string());
 
     let fix_from_parsed: String = fix_from_str
         .chars()
@@ -107,8 +128,23 @@ fn main() {
 
     let fix_from = start_layout.clone().unwrap_or(fix_from_parsed);
 
+    // Only the first optimization is resumed (subsequent ones start anew).
+    let mut resume = options.resume.clone();
     loop {
-        let (layout_str, layout) = optimization::optimize(
+        let checkpointing = match resume.take() {
+            Some(path) => Some(Checkpointing {
+                path,
+                every: options.checkpoint_every,
+                resume: true,
+            }),
+            None => options.checkpoint.as_ref().map(|path| Checkpointing {
+                path: path.clone(),
+                every: options.checkpoint_every,
+                resume: false,
+            }),
+        };
+
+        let (layout_str, layout, learnability) = optimization::optimize(
             &optimization_params,
             &evaluator,
             &fix_from,
@@ -116,7 +152,25 @@ fn main() {
             &options.fix.clone().unwrap_or_default(),
             start_layout.is_some(),
             !options.no_cache_results,
+            checkpointing,
         );
+        let (layout_str, layout) = match options.polishing_options.enabled() {
+            true => common::polish(
+                &options.polishing_options,
+                "Polishing",
+                &layout_str,
+                &options.fix.clone().unwrap_or_default(),
+                &optimization_params.constraints,
+                &learnability,
+                layout_generator.as_ref(),
+                &evaluator,
+                match options.no_cache_results {
+                    true => None,
+                    false => Some(Cache::new()),
+                },
+            ),
+            false => (layout_str, layout),
+        };
         let evaluation_result = evaluator.evaluate_layout(&layout);
         let cost = evaluation_result.total_cost();
         let _ = final_results.get_or_insert_with(&layout_str, || cost);
@@ -126,6 +180,9 @@ fn main() {
             evaluation_result,
             final_results.highlighted_fmt(Some(&layout_str), 10)
         );
+        if optimization_params.learnability.enabled() {
+            println!("{}\n", learnability.report(&layout_str));
+        }
 
         // Log solution to file.
         if let Some(filename) = &options.append_solutions_to {
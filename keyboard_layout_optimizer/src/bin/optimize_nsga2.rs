@@ -0,0 +1,137 @@
+use keyboard_layout_optimizer::common;
+use layout_evaluation::cache::Cache;
+use layout_optimization_nsga2::optimization;
+
+use clap::Parser;
+use colored::Colorize;
+use serde::Serialize;
+use std::{env, fs};
+
+#[derive(Parser, Debug)]
+#[clap(name = "Keyboard layout optimization - Multi-objective (NSGA-II)")]
+struct Options {
+    /// Evaluation parameters
+    #[clap(flatten)]
+    evaluation_parameters: common::Options,
+
+    /// Do not optimize those keys (wrt. --start-layout or --fix-from)
+    #[clap(short, long)]
+    fix: Option<String>,
+
+    /// Fix the keys from this layout (will be overwritten by --start-layout)
+    #[clap(long)]
+    fix_from: Option<String>,
+
+    /// Filename of optimization configuration file
+    #[clap(short, long, default_value = "config/optimization/nsga2.yml")]
+    optimization_parameters: String,
+
+    /// Include this layout in the initial population (keys from left to right, top to bottom)
+    #[clap(short, long)]
+    start_layout: Option<String>,
+
+    /// Do not remove whitespace from layout strings
+    #[clap(long)]
+    do_not_remove_whitespace: bool,
+
+    /// Do not cache intermediate results
+    #[clap(long)]
+    no_cache_results: bool,
+
+    /// Log the progress every n generations (0 disables logging)
+    #[clap(long, default_value = "10")]
+    log_every: usize,
+
+    /// Write the Pareto front as JSON to this file (otherwise print it to stdout)
+    #[clap(long)]
+    out: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ParetoFront {
+    objectives: Vec<optimization::ObjectiveConfig>,
+    layouts: Vec<optimization::ParetoLayout>,
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+    env_logger::init();
+
+    // Disable storing worst ngrams for speed boost
+    if env::var("SHOW_WORST").is_err() {
+        env::set_var("SHOW_WORST", "false");
+    };
+
+    let options = Options::parse();
+
+    let (layout_generator, evaluator) = common::init(&options.evaluation_parameters);
+
+    let start_layout: Option<String> = options.start_layout.as_ref().map(|s| {
+        s.chars()
+            .filter(|c| options.do_not_remove_whitespace || !c.is_whitespace())
+            .collect()
+    });
+    let fix_from_str = options
+        .fix_from
+        .clone()
+        .unwrap_or_else(|| layout_generator.base_layout_string());
+    let fix_from: String = start_layout.clone().unwrap_or_else(|| {
+        fix_from_str
+            .chars()
+            .filter(|c| options.do_not_remove_whitespace || !c.is_whitespace())
+            .collect()
+    });
+
+    let optimization_params = optimization::Parameters::from_yaml(&options.optimization_parameters)
+        .unwrap_or_else(|_| {
+            panic!(
+                "Could not read optimization parameters from {}.",
+                &options.optimization_parameters
+            )
+        });
+
+    let cache = match !options.no_cache_results {
+        true => Some(Cache::new()),
+        false => None,
+    };
+
+    let front = optimization::optimize(
+        &optimization_params,
+        &fix_from,
+        &options.fix.clone().unwrap_or_default(),
+        layout_generator.as_ref(),
+        start_layout.is_some(),
+        &evaluator,
+        options.log_every,
+        cache,
+    );
+
+    log::info!("{} {} layouts", "Pareto front:".green().bold(), front.len());
+    for pareto_layout in front.iter() {
+        let objectives: Vec<String> = pareto_layout
+            .objectives
+            .iter()
+            .map(|v| format!("{:>8.2}", v))
+            .collect();
+        log::info!(
+            "{} {} ({:.2})",
+            pareto_layout.layout,
+            objectives.join(" "),
+            pareto_layout.total_cost
+        );
+    }
+
+    let pareto_front = ParetoFront {
+        objectives: optimization_params.objectives,
+        layouts: front,
+    };
+    let json = serde_json::to_string_pretty(&pareto_front).unwrap();
+    match &options.out {
+        Some(filename) => {
+            fs::write(filename, json)
+                .unwrap_or_else(|e| panic!("Could not write Pareto front to {}: {}", filename, e));
+            log::info!("Wrote Pareto front to '{}'", filename);
+        }
+        None => println!("{}", json),
+    }
+}
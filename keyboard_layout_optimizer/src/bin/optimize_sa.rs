@@ -1,5 +1,6 @@
 use keyboard_layout_optimizer::common;
 use layout_evaluation::cache::Cache;
+use layout_optimization_common::checkpoint::{self, Checkpointing};
 use layout_optimization_sa::optimization;
 
 use clap::Parser;
@@ -58,6 +59,24 @@ struct Options {
     #[clap(long)]
     run_forever: bool,
 
+    /// Periodically save the state of the optimization to this file
+    /// (for several optimizations, their number is appended to the filename)
+    #[clap(long)]
+    checkpoint: Option<String>,
+
+    /// Save a checkpoint every n iterations
+    #[clap(long, default_value = "1000")]
+    checkpoint_every: u64,
+
+    /// Resume the optimization from this checkpoint file
+    /// (start layout and fixed keys are taken from the checkpoint)
+    #[clap(long)]
+    resume: Vec<String>,
+
+    /// Polishing options
+    #[clap(flatten)]
+    polishing_options: common::PolishingOptions,
+
     /// Publishing options
     #[clap(flatten)]
     publishing_options: common::PublishingOptions,
@@ -141,14 +160,15 @@ fn main() {
 
     let (layout_generator, evaluator) = common::init(&options.evaluation_parameters);
 
-    let fix_from_str = options.fix_from.clone().unwrap_or_else(|| layout_generator.base_layout_string());
+    let fix_from_str = options
+        .fix_from
+        .clone()
+        .unwrap_or_else(|| layout_generator.base_layout_string());
     let fix_from: String = fix_from_str
         .chars()
         .filter(|c| options.do_not_remove_whitespace || !c.is_whitespace())
         .collect();
 
-
-
     let mut optimization_params = optimization::Parameters::from_yaml(
         &options.optimization_parameters,
     )
@@ -169,9 +189,16 @@ fn main() {
     if layouts.is_empty() {
         layouts = vec![fix_from];
     }
-    let layout_iterator = LayoutIterator::new(&layouts, options.run_forever);
+    let several_processes = layouts.len() > 1 || options.run_forever;
     let start_from_layout = !start_layouts.is_empty();
 
+    // When resuming, iterate over the checkpoint files instead of the layouts.
+    let resume = !options.resume.is_empty();
+    let layout_iterator = match resume {
+        true => LayoutIterator::new(&options.resume, false),
+        false => LayoutIterator::new(&layouts, options.run_forever),
+    };
+
     let cache: Option<Cache<f64>> = match !options.no_cache_results {
         true => Some(Cache::new()),
         false => None,
@@ -182,7 +209,28 @@ fn main() {
         .par_bridge()
         .for_each(|(i, fix_from)| {
             let process_id = format!("Process {:>3}", i);
-            if start_from_layout {
+            let checkpointing = match resume {
+                true => Some(Checkpointing {
+                    path: fix_from.clone(),
+                    every: options.checkpoint_every,
+                    resume: true,
+                }),
+                false => options.checkpoint.as_ref().map(|path| Checkpointing {
+                    path: match several_processes {
+                        true => checkpoint::indexed_path(path, i),
+                        false => path.clone(),
+                    },
+                    every: options.checkpoint_every,
+                    resume: false,
+                }),
+            };
+            if resume {
+                log::info!(
+                    "{} Resuming optimization from {}",
+                    format!("{}:", process_id).yellow().bold(),
+                    fix_from
+                );
+            } else if start_from_layout {
                 log::info!(
                     "{} Starting optimization from {}",
                     format!("{}:", process_id).yellow().bold(),
@@ -196,7 +244,7 @@ fn main() {
             }
 
             // Perform the optimization.
-            let (layout_str, layout) = optimization::optimize(
+            let (layout_str, layout, learnability) = optimization::optimize(
                 &process_id,
                 &optimization_params,
                 &fix_from,
@@ -207,7 +255,22 @@ fn main() {
                 options.log_everything,
                 cache.clone(),
                 None,
+                checkpointing,
             );
+            let (layout_str, layout) = match options.polishing_options.enabled() {
+                true => common::polish(
+                    &options.polishing_options,
+                    &process_id,
+                    &layout_str,
+                    &options.fix.clone().unwrap_or_default(),
+                    &optimization_params.constraints,
+                    &learnability,
+                    layout_generator.as_ref(),
+                    &evaluator,
+                    cache.clone(),
+                ),
+                false => (layout_str, layout),
+            };
             let evaluation_result = evaluator.evaluate_layout(&layout);
             let cost = evaluation_result.total_cost();
             let _ = final_results.get_or_insert_with(&layout_str, || cost);
@@ -223,6 +286,13 @@ fn main() {
                 evaluation_result,
                 final_results.highlighted_fmt(Some(&layout_str), 10),
             );
+            if optimization_params.learnability.enabled() {
+                println!(
+                    "{} {}\n",
+                    format!("{}:", process_id).yellow().bold(),
+                    learnability.report(&layout_str)
+                );
+            }
 
             // Log solution to file.
             if let Some(filename) = &options.append_solutions_to {
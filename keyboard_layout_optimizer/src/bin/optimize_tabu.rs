@@ -0,0 +1,139 @@
+use keyboard_layout_optimizer::common;
+use layout_evaluation::cache::Cache;
+use layout_optimization_tabu::optimization;
+
+use clap::Parser;
+use colored::Colorize;
+use std::{env, process};
+
+#[derive(Parser, Debug)]
+#[clap(name = "Keyboard layout optimization - Tabu Search")]
+struct Options {
+    /// Evaluation parameters
+    #[clap(flatten)]
+    evaluation_parameters: common::Options,
+
+    /// Do not optimize those keys (wrt. --start-layout or --fix-from)
+    #[clap(short, long)]
+    fix: Option<String>,
+
+    /// Fix the keys from this layout (will be overwritten by --start-layout)
+    #[clap(long)]
+    fix_from: Option<String>,
+
+    /// Filename of optimization configuration file
+    #[clap(short, long, default_value = "config/optimization/tabu.yml")]
+    optimization_parameters: String,
+
+    /// Start optimization from this layout (keys from left to right, top to bottom)
+    #[clap(short, long)]
+    start_layout: Option<String>,
+
+    /// Do not remove whitespace from layout strings
+    #[clap(long)]
+    do_not_remove_whitespace: bool,
+
+    /// Do not cache intermediate results
+    #[clap(long)]
+    no_cache_results: bool,
+
+    /// Append found layouts to file
+    #[clap(long)]
+    append_solutions_to: Option<String>,
+
+    /// Publishing options
+    #[clap(flatten)]
+    publishing_options: common::PublishingOptions,
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+    env_logger::init();
+
+    // Disable storing worst ngrams for speed boost
+    if env::var("SHOW_WORST").is_err() {
+        env::set_var("SHOW_WORST", "false");
+    };
+
+    let options = Options::parse();
+
+    let cache: Cache<f64> = Cache::new();
+
+    // Handle Ctrl+C
+    let cloned_cache = cache.clone();
+    ctrlc::set_handler(move || {
+        // Display a summary of the evaluated layouts.
+        println!("\n\n{}\n", cloned_cache.highlighted_fmt(None, 10));
+        // Stop execution
+        process::exit(0);
+    })
+    .expect("Error setting Ctrl-C handler");
+
+    let (layout_generator, evaluator) = common::init(&options.evaluation_parameters);
+
+    let layout_str = options
+        .start_layout
+        .clone()
+        .or_else(|| options.fix_from.clone())
+        .unwrap_or_else(|| layout_generator.base_layout_string());
+    let layout_str: String = layout_str
+        .chars()
+        .filter(|c| options.do_not_remove_whitespace || !c.is_whitespace())
+        .collect();
+
+    let optimization_params = optimization::Parameters::from_yaml(&options.optimization_parameters)
+        .unwrap_or_else(|_| {
+            panic!(
+                "Could not read optimization parameters from {}.",
+                &options.optimization_parameters
+            )
+        });
+
+    let process_id = "Tabu Search";
+    let (layout_str, layout) = optimization::optimize(
+        process_id,
+        &optimization_params,
+        &layout_str,
+        &options.fix.clone().unwrap_or_default(),
+        layout_generator.as_ref(),
+        options.start_layout.is_some(),
+        &evaluator,
+        match options.no_cache_results {
+            true => None,
+            false => Some(cache.clone()),
+        },
+    );
+    let evaluation_result = evaluator.evaluate_layout(&layout);
+    let cost = evaluation_result.total_cost();
+    let _ = cache.get_or_insert_with(&layout_str, || cost);
+
+    // Plot some information regarding the layout.
+    println!(
+        "{} {}\n\n{}\n\n{}\n{}\n{}\n\n{}\n",
+        format!("{}:", process_id).yellow().bold(),
+        "Final result:".green().bold(),
+        layout,
+        layout.plot_compact(),
+        layout.plot(),
+        evaluation_result,
+        cache.highlighted_fmt(Some(&layout_str), 10),
+    );
+
+    // Log solution to file.
+    if let Some(filename) = &options.append_solutions_to {
+        common::append_to_file(&layout_str, filename);
+    }
+
+    // Publish to webservice.
+    let o = &options.publishing_options;
+    if let Some(publish_as) = &o.publish_as {
+        if cost < o.publish_if_cost_below.unwrap_or(f64::INFINITY) {
+            common::publish_to_webservice(
+                &layout_str,
+                publish_as,
+                &o.publish_to,
+                &o.publish_layout_config,
+            );
+        }
+    }
+}
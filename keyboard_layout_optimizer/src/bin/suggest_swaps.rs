@@ -0,0 +1,107 @@
+use keyboard_layout_optimizer::common;
+use layout_optimization_common::swap_suggestions::{self, MetricChange};
+
+use clap::Parser;
+use colored::Colorize;
+
+#[derive(Parser, Debug)]
+#[clap(name = "Keyboard layout swap suggestions")]
+/// List the swaps of two keys that improve a layout the most.
+struct Options {
+    /// Layout keys from left to right, top to bottom
+    layout_str: String,
+
+    /// Do not remove whitespace from layout strings
+    #[clap(long)]
+    do_not_remove_whitespace: bool,
+
+    /// General parameters
+    #[clap(flatten)]
+    general_parameters: common::Options,
+
+    /// Do not swap those keys
+    #[clap(short, long)]
+    fix: Option<String>,
+
+    /// Number of swaps to list
+    #[clap(long, default_value = "10")]
+    top: usize,
+
+    /// Number of improving and worsening metrics to list for each swap
+    #[clap(long, default_value = "3")]
+    n_metrics: usize,
+
+    /// If to only output the results as JSON to stdout
+    #[clap(long)]
+    json: bool,
+}
+
+fn format_changes(changes: &[MetricChange], n: usize) -> String {
+    changes
+        .iter()
+        .take(n)
+        .map(|change| format!("{} ({:+.2})", change.metric, change.delta))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+    let options = Options::parse();
+    env_logger::init();
+
+    let (layout_generator, evaluator) = common::init(&options.general_parameters);
+
+    let layout_str: String = options
+        .layout_str
+        .chars()
+        .filter(|c| options.do_not_remove_whitespace || !c.is_whitespace())
+        .collect();
+
+    let suggestions = swap_suggestions::suggest_swaps(
+        &layout_str,
+        &options.fix.clone().unwrap_or_default(),
+        layout_generator.as_ref(),
+        &evaluator,
+        options.top,
+    )
+    .unwrap_or_else(|e| panic!("Could not evaluate the swaps of {}: {:?}", layout_str, e));
+
+    if options.json {
+        println!("{}", serde_json::to_string(&suggestions).unwrap());
+        return;
+    }
+
+    println!("{} {}\n", "Best swaps for".bold(), layout_str);
+    for (i, suggestion) in suggestions.iter().enumerate() {
+        let delta = format!("{:+.2}", suggestion.cost_delta);
+        let delta = if suggestion.cost_delta < 0.0 {
+            delta.green()
+        } else {
+            delta.red()
+        };
+        println!(
+            "{:>3}. {} ↔ {}  {} ({:.2})  {}",
+            i + 1,
+            suggestion.keys.0,
+            suggestion.keys.1,
+            delta,
+            suggestion.cost,
+            suggestion.layout,
+        );
+        if !suggestion.improved_metrics.is_empty() {
+            println!(
+                "     {} {}",
+                "improves:".green(),
+                format_changes(&suggestion.improved_metrics, options.n_metrics)
+            );
+        }
+        if !suggestion.worsened_metrics.is_empty() {
+            println!(
+                "     {} {}",
+                "worsens:".red(),
+                format_changes(&suggestion.worsened_metrics, options.n_metrics)
+            );
+        }
+    }
+}
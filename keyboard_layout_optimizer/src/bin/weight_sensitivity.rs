@@ -0,0 +1,341 @@
+use keyboard_layout_optimizer::common;
+use layout_evaluation::results::EvaluationResult;
+
+use clap::Parser;
+use colored::Colorize;
+use rayon::prelude::*;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+};
+
+#[derive(Parser, Debug)]
+#[clap(name = "Metric weight sensitivity analysis")]
+/// Analyse how the ranking of given layouts changes if the weight of a single metric is varied.
+///
+/// As the total cost is a weighted sum of the (normalized) metric costs, it depends linearly on
+/// each weight. The thresholds at which the best layout changes are therefore computed exactly.
+struct Options {
+    /// List of Layout keys from left to right, top to bottom
+    layout_str: Vec<String>,
+
+    /// Do not remove whitespace from layout strings
+    #[clap(long)]
+    do_not_remove_whitespace: bool,
+
+    /// Read layouts from file and append to command line layouts
+    #[clap(long)]
+    from_file: Option<String>,
+
+    /// General parameters
+    #[clap(flatten)]
+    general_parameters: common::Options,
+
+    /// Vary each weight by up to this fraction of its value in both directions
+    #[clap(long, default_value = "0.5")]
+    range: f64,
+
+    /// Number of steps in each direction at which to compare the rankings
+    #[clap(long, default_value = "5")]
+    steps: usize,
+
+    /// Only analyse the metrics with these names (as shown in the evaluation output)
+    #[clap(long)]
+    metrics: Vec<String>,
+}
+
+/// The total cost of a layout as a function of one metric's weight: `offset + weight * slope`.
+#[derive(Clone, Copy, Debug)]
+struct CostLine {
+    offset: f64,
+    slope: f64,
+}
+
+impl CostLine {
+    fn cost(&self, weight: f64) -> f64 {
+        self.offset + weight * self.slope
+    }
+}
+
+/// Index of the layout with the lowest cost at the given weight. For (almost) identical costs,
+/// the layout whose cost increases slower with the weight is chosen.
+fn winner(lines: &[CostLine], weight: f64, increasing: bool) -> usize {
+    let direction = if increasing { 1.0 } else { -1.0 };
+    (0..lines.len())
+        .min_by(|i, j| {
+            let (ci, cj) = (lines[*i].cost(weight), lines[*j].cost(weight));
+            if (ci - cj).abs() > 1e-9 * ci.abs().max(cj.abs()).max(1.0) {
+                ci.partial_cmp(&cj).unwrap()
+            } else {
+                (direction * lines[*i].slope)
+                    .partial_cmp(&(direction * lines[*j].slope))
+                    .unwrap()
+            }
+        })
+        .unwrap()
+}
+
+/// The next weight (in the given direction) at which the winner changes together with the new winner.
+fn next_winner_change(lines: &[CostLine], weight: f64, increasing: bool) -> Option<(f64, usize)> {
+    let current = winner(lines, weight, increasing);
+    let direction = if increasing { 1.0 } else { -1.0 };
+
+    let crossing = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| direction * (line.slope - lines[current].slope) < 0.0)
+        .map(|(_, line)| {
+            (line.offset - lines[current].offset) / (lines[current].slope - line.slope)
+        })
+        .filter(|w| direction * (w - weight) > 0.0)
+        .min_by(|w1, w2| (direction * w1).partial_cmp(&(direction * w2)).unwrap())?;
+
+    Some((crossing, winner(lines, crossing, increasing)))
+}
+
+/// All winner changes when moving from `start` to `end`.
+fn winner_changes(lines: &[CostLine], start: f64, end: f64) -> Vec<(f64, usize, usize)> {
+    let increasing = end > start;
+    let mut changes = Vec::new();
+    let mut weight = start;
+    while let Some((w, new_winner)) = next_winner_change(lines, weight, increasing) {
+        if (increasing && w > end) || (!increasing && w < end) {
+            break;
+        }
+        changes.push((w, winner(lines, weight, increasing), new_winner));
+        weight = w;
+    }
+
+    changes
+}
+
+/// Ranking (list of layout indices sorted by cost) at the given weight.
+fn ranking(lines: &[CostLine], weight: f64) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..lines.len()).collect();
+    indices.sort_by(|i, j| {
+        lines[*i]
+            .cost(weight)
+            .partial_cmp(&lines[*j].cost(weight))
+            .unwrap()
+    });
+
+    indices
+}
+
+/// Number of layout pairs whose order differs between two rankings.
+fn swapped_pairs(ranking1: &[usize], ranking2: &[usize]) -> usize {
+    let mut position = vec![0; ranking2.len()];
+    ranking2
+        .iter()
+        .enumerate()
+        .for_each(|(pos, idx)| position[*idx] = pos);
+
+    let mut swapped = 0;
+    for (i, idx1) in ranking1.iter().enumerate() {
+        for idx2 in ranking1.iter().skip(i + 1) {
+            if position[*idx1] > position[*idx2] {
+                swapped += 1;
+            }
+        }
+    }
+
+    swapped
+}
+
+fn format_weight(weight: f64, base_weight: f64) -> String {
+    if base_weight != 0.0 {
+        format!(
+            "{:.4} ({:+.1}%)",
+            weight,
+            100.0 * (weight - base_weight) / base_weight.abs()
+        )
+    } else {
+        format!("{:.4}", weight)
+    }
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+    let options = Options::parse();
+    env_logger::init();
+
+    let (layout_generator, evaluator) = common::init(&options.general_parameters);
+    if !common::read_evaluation_parameters(&options.general_parameters.eval_parameters)
+        .aggregation
+        .is_weighted_sum()
+    {
+        log::warn!("The analysis assumes the total cost to be a weighted sum of the metric costs. The configured aggregation is ignored for the thresholds.");
+    }
+
+    // collect layout strings to a vec
+    let mut layout_strings = options.layout_str.to_vec();
+    if let Some(filename) = &options.from_file {
+        match File::open(filename) {
+            Ok(file) => {
+                layout_strings
+                    .append(&mut BufReader::new(file).lines().map_while(Result::ok).collect());
+            }
+            Err(e) => {
+                log::error!("Error reading layouts file {}: {:?}", filename, e);
+                panic!("{:?}", e);
+            }
+        }
+    }
+    let layout_strings: Vec<String> = layout_strings
+        .iter()
+        .map(|layout_str| {
+            layout_str
+                .chars()
+                .filter(|c| options.do_not_remove_whitespace || !c.is_whitespace())
+                .collect()
+        })
+        .collect();
+
+    if layout_strings.len() < 2 {
+        log::error!("At least two layouts are required for a sensitivity analysis.");
+        return;
+    }
+
+    // evaluate layouts (once)
+    let results: Vec<EvaluationResult> = layout_strings
+        .par_iter()
+        .map(|layout_str| {
+            let layout = match layout_generator.generate(layout_str) {
+                Ok(layout) => layout,
+                Err(e) => {
+                    log::error!("Error in generating layout: {:?}", e);
+                    panic!("{:?}", e);
+                }
+            };
+            evaluator.evaluate_layout(&layout)
+        })
+        .collect();
+
+    let total_costs: Vec<f64> = results.iter().map(|r| r.total_cost()).collect();
+
+    // collect metric names and weights in order of appearance
+    let metrics: Vec<(String, f64)> = results[0]
+        .iter()
+        .flat_map(|metric_results| metric_results.metric_costs.iter())
+        .map(|metric_cost| (metric_cost.core.name.clone(), metric_cost.core.weight))
+        .filter(|(name, _)| options.metrics.is_empty() || options.metrics.contains(name))
+        .collect();
+
+    let mut base_ranking: Vec<usize> = (0..total_costs.len()).collect();
+    base_ranking.sort_by(|i, j| total_costs[*i].partial_cmp(&total_costs[*j]).unwrap());
+    let base_winner = base_ranking[0];
+
+    println!("{}", "Ranking:".bold());
+    for (rank, idx) in base_ranking.iter().enumerate() {
+        println!(
+            "  {:>3}. {} {:.4}",
+            rank + 1,
+            layout_strings[*idx],
+            total_costs[*idx]
+        );
+    }
+    println!();
+
+    for (name, base_weight) in metrics {
+        // unweighted (but normalized) costs of the metric for each layout
+        let unweighted_costs: Vec<f64> = results
+            .iter()
+            .map(|result| {
+                result
+                    .iter()
+                    .flat_map(|metric_results| metric_results.metric_costs.iter())
+                    .filter(|metric_cost| metric_cost.core.name == name)
+                    .map(|metric_cost| metric_cost.unweighted_cost)
+                    .sum()
+            })
+            .collect();
+
+        let lines: Vec<CostLine> = total_costs
+            .iter()
+            .zip(unweighted_costs.iter())
+            .map(|(total_cost, unweighted_cost)| CostLine {
+                offset: total_cost - base_weight * unweighted_cost,
+                slope: *unweighted_cost,
+            })
+            .collect();
+
+        println!("{} (weight {:.4})", name.bold(), base_weight);
+
+        let min_cost = unweighted_costs
+            .iter()
+            .cloned()
+            .fold(f64::INFINITY, f64::min);
+        let max_cost = unweighted_costs
+            .iter()
+            .cloned()
+            .fold(f64::NEG_INFINITY, f64::max);
+        if (max_cost - min_cost).abs() < 1e-12 {
+            println!(
+                "  Identical costs for all layouts. The weight does not influence the ranking.\n"
+            );
+            continue;
+        }
+
+        // nearest changes of the winner in both directions (also outside the range)
+        for (direction, increasing) in [("Lower", false), ("Higher", true)] {
+            match next_winner_change(&lines, base_weight, increasing) {
+                Some((w, new_winner)) => println!(
+                    "  {} weight at which the winner changes: {} -> {}",
+                    direction,
+                    format_weight(w, base_weight),
+                    layout_strings[new_winner]
+                ),
+                None => println!(
+                    "  {} weight at which the winner changes: none ({} stays best)",
+                    direction, layout_strings[base_winner]
+                ),
+            }
+        }
+
+        // changes of the ranking within the range
+        let delta = options.range * base_weight.abs();
+        if delta == 0.0 {
+            println!();
+            continue;
+        }
+
+        let changes: Vec<(f64, usize, usize)> =
+            winner_changes(&lines, base_weight, base_weight - delta)
+                .into_iter()
+                .rev()
+                .chain(winner_changes(&lines, base_weight, base_weight + delta))
+                .collect();
+        if !changes.is_empty() {
+            println!(
+                "  Winner changes within ±{:.0}% (best layout below | above the threshold):",
+                100.0 * options.range
+            );
+            for (w, old_winner, new_winner) in changes {
+                let (left, right) = if w < base_weight {
+                    (new_winner, old_winner)
+                } else {
+                    (old_winner, new_winner)
+                };
+                println!(
+                    "    {}: {} | {}",
+                    format_weight(w, base_weight),
+                    layout_strings[left],
+                    layout_strings[right]
+                );
+            }
+        }
+
+        let swaps: Vec<String> = (1..=options.steps)
+            .rev()
+            .map(|step| -(step as f64))
+            .chain((1..=options.steps).map(|step| step as f64))
+            .map(|step| {
+                let fraction = step * options.range / options.steps as f64;
+                let weight = base_weight + fraction * base_weight.abs();
+                let swapped = swapped_pairs(&base_ranking, &ranking(&lines, weight));
+                format!("{:+.0}%: {}", 100.0 * fraction, swapped)
+            })
+            .collect();
+        println!("  Swapped layout pairs: {}\n", swaps.join(", "));
+    }
+}
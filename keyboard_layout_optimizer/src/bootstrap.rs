@@ -0,0 +1,151 @@
+//! Bootstrap estimation of the uncertainty of layout costs that stems from the finite corpus.
+//!
+//! If a text corpus is available, it is split into chunks and each resample draws the same
+//! number of chunks with replacement. If only ngram frequencies are available, each ngram's
+//! weight is multiplied with a random weight drawn from an exponential distribution
+//! ("bayesian bootstrap"). As this treats each distinct ngram as an independent observation,
+//! the resulting uncertainties are rather conservative compared to resampling corpus chunks.
+
+use crate::statistics::{mean_std, quantile, sorted};
+use layout_evaluation::ngrams::{Bigrams, Trigrams, Unigrams};
+
+use ahash::AHashMap;
+use rand::Rng;
+use serde::Serialize;
+use std::hash::Hash;
+
+/// Source of resampled ngram frequencies.
+pub enum NgramResampler {
+    /// Ngrams of the chunks of a text corpus
+    Chunks(Vec<(Unigrams, Bigrams, Trigrams)>),
+    /// Ngram frequencies without the underlying text
+    Frequencies(Unigrams, Bigrams, Trigrams),
+}
+
+fn add_weights<T: Clone + Eq + Hash>(res: &mut AHashMap<T, f64>, ngrams: &AHashMap<T, f64>) {
+    ngrams.iter().for_each(|(ngram, weight)| {
+        *res.entry(ngram.clone()).or_insert(0.0) += weight;
+    });
+}
+
+fn random_weights<T: Clone + Eq + Hash, R: Rng>(
+    ngrams: &AHashMap<T, f64>,
+    rng: &mut R,
+) -> AHashMap<T, f64> {
+    ngrams
+        .iter()
+        .map(|(ngram, weight)| {
+            // exponentially distributed weight with mean 1
+            let factor = -(1.0 - rng.gen::<f64>()).ln();
+            (ngram.clone(), factor * weight)
+        })
+        .collect()
+}
+
+impl NgramResampler {
+    /// Split the text into `n_chunks` chunks of (roughly) equal length.
+    pub fn from_text(text: &str, n_chunks: usize) -> Self {
+        let chars: Vec<char> = text.chars().collect();
+        let n_chunks = n_chunks.max(1);
+        let chunk_size = (chars.len() + n_chunks - 1) / n_chunks;
+
+        let chunks = chars
+            .chunks(chunk_size.max(1))
+            .map(|chunk| {
+                let chunk: String = chunk.iter().collect();
+                (
+                    Unigrams::from_text(&chunk).expect("Could not generate unigrams from text."),
+                    Bigrams::from_text(&chunk).expect("Could not generate bigrams from text."),
+                    Trigrams::from_text(&chunk).expect("Could not generate trigrams from text."),
+                )
+            })
+            .collect();
+
+        Self::Chunks(chunks)
+    }
+
+    pub fn from_ngrams(unigrams: Unigrams, bigrams: Bigrams, trigrams: Trigrams) -> Self {
+        Self::Frequencies(unigrams, bigrams, trigrams)
+    }
+
+    /// Generate a resampled set of ngrams.
+    pub fn resample<R: Rng>(&self, rng: &mut R) -> (Unigrams, Bigrams, Trigrams) {
+        match self {
+            Self::Chunks(chunks) => {
+                let mut unigrams = AHashMap::default();
+                let mut bigrams = AHashMap::default();
+                let mut trigrams = AHashMap::default();
+                for _ in 0..chunks.len() {
+                    let (u, b, t) = &chunks[rng.gen_range(0..chunks.len())];
+                    add_weights(&mut unigrams, &u.grams);
+                    add_weights(&mut bigrams, &b.grams);
+                    add_weights(&mut trigrams, &t.grams);
+                }
+
+                (
+                    Unigrams { grams: unigrams },
+                    Bigrams { grams: bigrams },
+                    Trigrams { grams: trigrams },
+                )
+            }
+            Self::Frequencies(unigrams, bigrams, trigrams) => (
+                Unigrams {
+                    grams: random_weights(&unigrams.grams, rng),
+                },
+                Bigrams {
+                    grams: random_weights(&bigrams.grams, rng),
+                },
+                Trigrams {
+                    grams: random_weights(&trigrams.grams, rng),
+                },
+            ),
+        }
+    }
+}
+
+/// Summary statistics of the costs of a layout over all resamples.
+#[derive(Clone, Debug, Serialize)]
+pub struct BootstrapSummary {
+    pub mean: f64,
+    pub std: f64,
+    pub ci_lower: f64,
+    pub ci_upper: f64,
+    pub confidence: f64,
+}
+
+impl BootstrapSummary {
+    /// Compute mean, standard deviation and the (percentile) confidence interval of the samples.
+    pub fn from_samples(samples: &[f64], confidence: f64) -> Self {
+        let (mean, std) = mean_std(samples);
+        let sorted = sorted(samples);
+        let alpha = 0.5 * (1.0 - confidence);
+
+        Self {
+            mean,
+            std,
+            ci_lower: quantile(&sorted, alpha),
+            ci_upper: quantile(&sorted, 1.0 - alpha),
+            confidence,
+        }
+    }
+}
+
+/// Fraction of (paired) resamples in which the cost `a` is lower than the cost `b`
+/// (ties count half).
+pub fn probability_lower(a: &[f64], b: &[f64]) -> f64 {
+    let wins: f64 = a
+        .iter()
+        .zip(b.iter())
+        .map(|(a, b)| {
+            if a < b {
+                1.0
+            } else if a == b {
+                0.5
+            } else {
+                0.0
+            }
+        })
+        .sum();
+
+    wins / a.len().min(b.len()) as f64
+}
@@ -1,17 +1,25 @@
 use keyboard_layout::{
     config::LayoutConfig, grouped_layout_generator::GroupedLayoutGenerator, keyboard::Keyboard,
-    layout_generator::LayoutGenerator, neo_layout_generator::NeoLayoutGenerator,
+    layout::Layout, layout_generator::LayoutGenerator, neo_layout_generator::NeoLayoutGenerator,
 };
 use layout_evaluation::{
+    aggregation::CombinationMethod,
+    cache::Cache,
     config::EvaluationParameters,
     evaluation::Evaluator,
-    ngram_mapper::on_demand_ngram_mapper::OnDemandNgramMapper,
+    metrics::registry::parse_params,
+    ngram_mapper::on_demand_ngram_mapper::{NgramMapperConfig, OnDemandNgramMapper},
     ngrams::{Bigrams, Trigrams, Unigrams},
 };
+use layout_optimization_common::{
+    constraints::Constraint, learnability::Learnability, local_search,
+};
 
 use ahash::AHashMap;
 use clap::Parser;
+use colored::Colorize;
 use itertools::Itertools;
+use serde::Deserialize;
 use std::{
     fs::{self, OpenOptions},
     io::prelude::*,
@@ -65,6 +73,94 @@ pub struct Options {
     /// Interpred given layout string using the "grouped" logic
     #[clap(long)]
     pub grouped_layout_generator: bool,
+
+    /// Filename of a corpora configuration file. Layouts are evaluated on each of the
+    /// configured (named and weighted) corpora instead of the ngrams, corpus, or text
+    #[clap(long)]
+    pub corpora: Option<String>,
+
+    /// Filename of a keyboards configuration file. Layouts are evaluated on each of the
+    /// configured (named and weighted) keyboards instead of the layout config
+    #[clap(long)]
+    pub keyboards: Option<String>,
+}
+
+fn default_corpus_weight() -> f64 {
+    1.0
+}
+
+/// A named and weighted corpus given as ngram files or as text file.
+#[derive(Clone, Deserialize, Debug)]
+pub struct CorpusConfig {
+    /// Name of the corpus used in outputs
+    pub name: String,
+    /// Weight of the corpus when combining the costs of all corpora
+    #[serde(default = "default_corpus_weight")]
+    pub weight: f64,
+    /// Path to ngram files
+    #[serde(default)]
+    pub ngrams: Option<String>,
+    /// Filename of corpus file to use instead of ngram files
+    #[serde(default)]
+    pub corpus: Option<String>,
+}
+
+/// Several corpora that layouts are evaluated on and how their costs are combined.
+#[derive(Clone, Deserialize, Debug)]
+pub struct CorporaConfig {
+    /// How the total costs of the corpora are combined
+    #[serde(default)]
+    pub combination: CombinationMethod,
+    /// The corpora
+    pub corpora: Vec<CorpusConfig>,
+}
+
+impl CorporaConfig {
+    pub fn from_yaml(filename: &str) -> anyhow::Result<Self> {
+        let f = fs::File::open(filename)?;
+        let cfg: CorporaConfig = serde_yaml::from_reader(f)?;
+
+        Ok(cfg)
+    }
+}
+
+fn default_keyboard_weight() -> f64 {
+    1.0
+}
+
+/// A named and weighted keyboard given by its layout config and (optionally) its own
+/// evaluation config.
+#[derive(Clone, Deserialize, Debug)]
+pub struct KeyboardConfig {
+    /// Name of the keyboard used in outputs
+    pub name: String,
+    /// Weight of the keyboard when combining the costs of all keyboards
+    #[serde(default = "default_keyboard_weight")]
+    pub weight: f64,
+    /// Filename of layout configuration file
+    pub layout_config: String,
+    /// Filename of evaluation configuration file (defaults to the one given as option)
+    #[serde(default)]
+    pub eval_parameters: Option<String>,
+}
+
+/// Several keyboards that layouts are evaluated on and how their costs are combined.
+#[derive(Clone, Deserialize, Debug)]
+pub struct KeyboardsConfig {
+    /// How the total costs of the keyboards are combined
+    #[serde(default)]
+    pub combination: CombinationMethod,
+    /// The keyboards (the first one determines the layout generator used for the layout strings)
+    pub keyboards: Vec<KeyboardConfig>,
+}
+
+impl KeyboardsConfig {
+    pub fn from_yaml(filename: &str) -> anyhow::Result<Self> {
+        let f = fs::File::open(filename)?;
+        let cfg: KeyboardsConfig = serde_yaml::from_reader(f)?;
+
+        Ok(cfg)
+    }
 }
 
 #[derive(Parser, Debug)]
@@ -88,13 +184,119 @@ pub struct PublishingOptions {
     pub publish_to: String,
 }
 
+#[derive(Parser, Debug)]
+#[clap(name = "Keyboard layout polishing")]
+pub struct PolishingOptions {
+    /// Polish found layouts with a local search until no swap of two keys improves them
+    #[clap(long)]
+    pub polish: bool,
+
+    /// Also consider rotations of three keys when polishing (implies --polish)
+    #[clap(long)]
+    pub polish_with_rotations: bool,
+}
+
+impl PolishingOptions {
+    pub fn enabled(&self) -> bool {
+        self.polish || self.polish_with_rotations
+    }
+}
+
+/// Polish a layout with a steepest-descent local search and print the certificate of the
+/// resulting local optimum.
+#[allow(clippy::too_many_arguments)]
+pub fn polish(
+    options: &PolishingOptions,
+    process_name: &str,
+    layout_str: &str,
+    fixed_characters: &str,
+    constraints: &[Constraint],
+    learnability: &Learnability,
+    layout_generator: &dyn LayoutGenerator,
+    evaluator: &Evaluator,
+    result_cache: Option<Cache<f64>>,
+) -> (String, Layout) {
+    let (layout_str, layout, certificate) = local_search::polish(
+        process_name,
+        layout_str,
+        fixed_characters,
+        constraints,
+        learnability,
+        layout_generator,
+        evaluator,
+        options.polish_with_rotations,
+        result_cache,
+    );
+    println!(
+        "{} {}",
+        format!("{}:", process_name).yellow().bold(),
+        certificate
+    );
+
+    (layout_str, layout)
+}
+
 pub fn init(options: &Options) -> (Box<dyn LayoutGenerator>, Evaluator) {
+    if let Some(keyboards_file) = &options.keyboards {
+        return init_with_keyboards(options, keyboards_file);
+    }
+
     (
         init_layout_generator(&options.layout_config, options.grouped_layout_generator),
         init_evaluator(options),
     )
 }
 
+/// Generates the layout generator of the first keyboard of the keyboards configuration file and
+/// an evaluator evaluating layouts on each of the keyboards.
+pub fn init_with_keyboards(
+    options: &Options,
+    keyboards_file: &str,
+) -> (Box<dyn LayoutGenerator>, Evaluator) {
+    let keyboards_config = KeyboardsConfig::from_yaml(keyboards_file).unwrap_or_else(|e| {
+        panic!(
+            "Could not read keyboards config file {}: {:?}",
+            keyboards_file, e
+        )
+    });
+    if keyboards_config.keyboards.is_empty() {
+        panic!("No keyboards given in {}.", keyboards_file);
+    }
+
+    let eval_params = read_evaluation_parameters(&options.eval_parameters);
+    let mut evaluator = Evaluator::default(Box::new(empty_ngram_mapper(eval_params.ngram_mapper)))
+        .with_keyboard_combination(keyboards_config.combination);
+
+    let mut layout_generators = Vec::new();
+    for keyboard in keyboards_config.keyboards.iter() {
+        log::info!(
+            "Initializing keyboard '{}' from '{}'",
+            keyboard.name,
+            keyboard.layout_config
+        );
+        let layout_generator =
+            init_layout_generator(&keyboard.layout_config, options.grouped_layout_generator);
+        let keyboard_evaluator = init_evaluator_from(
+            options,
+            keyboard
+                .eval_parameters
+                .as_ref()
+                .unwrap_or(&options.eval_parameters),
+        );
+        evaluator
+            .add_keyboard(
+                &keyboard.name,
+                keyboard.weight,
+                layout_generator.clone(),
+                keyboard_evaluator,
+            )
+            .unwrap_or_else(|e| panic!("Invalid keyboards config {}: {:?}", keyboards_file, e));
+        layout_generators.push(layout_generator);
+    }
+
+    (layout_generators.remove(0), evaluator)
+}
+
 pub fn init_layout_generator(
     layout_config: &str,
     grouped_layout_generator: bool,
@@ -134,21 +336,258 @@ pub fn init_layout_generator(
 }
 
 pub fn init_evaluator(options: &Options) -> Evaluator {
-    let eval_params =
-        EvaluationParameters::from_yaml(&options.eval_parameters).unwrap_or_else(|e| {
-            panic!(
-                "Could not read evaluation yaml file {}: {:?}",
-                options.eval_parameters, e
-            )
-        });
+    init_evaluator_from(options, &options.eval_parameters)
+}
+
+/// Generates an evaluator using the given evaluation configuration file (instead of the one
+/// given in the options).
+fn init_evaluator_from(options: &Options, eval_parameters: &str) -> Evaluator {
+    let eval_params = read_evaluation_parameters(eval_parameters);
+    if let Some(corpora_file) = &options.corpora {
+        return init_evaluator_with_corpora(options, &eval_params, corpora_file);
+    }
+    let (unigrams, bigrams, trigrams) = read_ngrams(options);
+
+    init_evaluator_with_ngrams(options, &eval_params, unigrams, bigrams, trigrams)
+}
+
+/// Generates an evaluator evaluating layouts on each corpus of the corpora configuration file.
+pub fn init_evaluator_with_corpora(
+    options: &Options,
+    eval_params: &EvaluationParameters,
+    corpora_file: &str,
+) -> Evaluator {
+    let corpora_config = CorporaConfig::from_yaml(corpora_file).unwrap_or_else(|e| {
+        panic!(
+            "Could not read corpora config file {}: {:?}",
+            corpora_file, e
+        )
+    });
+    if corpora_config.corpora.is_empty() {
+        panic!("No corpora given in {}.", corpora_file);
+    }
+
+    let mut evaluator = Evaluator::default(Box::new(empty_ngram_mapper(
+        eval_params.ngram_mapper.clone(),
+    )))
+    .default_metrics(&eval_params.metrics)
+    .with_aggregation(&eval_params.aggregation)
+    .with_corpus_combination(corpora_config.combination);
+
+    for corpus in corpora_config.corpora.iter() {
+        let (unigrams, bigrams, trigrams) = match (&corpus.corpus, &corpus.ngrams) {
+            (Some(corpus_file), _) => ngrams_from_text(
+                &fs::read_to_string(corpus_file)
+                    .unwrap_or_else(|_| panic!("Could not read corpus file from {}.", corpus_file)),
+            ),
+            (None, Some(ngrams)) => read_ngram_files(ngrams),
+            (None, None) => panic!(
+                "Neither ngrams nor corpus given for corpus '{}'.",
+                corpus.name
+            ),
+        };
+        let ngram_mapper =
+            ngram_mapper_with_ngrams(options, eval_params, unigrams, bigrams, trigrams);
+        evaluator.add_corpus(&corpus.name, corpus.weight, Box::new(ngram_mapper));
+    }
+
+    evaluator
+}
+
+/// An ngram mapper without any ngrams.
+fn empty_ngram_mapper(config: NgramMapperConfig) -> OnDemandNgramMapper {
+    OnDemandNgramMapper::with_ngrams(
+        Unigrams {
+            grams: AHashMap::default(),
+        },
+        Bigrams {
+            grams: AHashMap::default(),
+        },
+        Trigrams {
+            grams: AHashMap::default(),
+        },
+        config,
+    )
+}
+
+/// Reads the evaluation parameters from a yaml file.
+pub fn read_evaluation_parameters(filename: &str) -> EvaluationParameters {
+    EvaluationParameters::from_yaml(filename)
+        .unwrap_or_else(|e| panic!("Could not read evaluation yaml file {}: {:?}", filename, e))
+}
+
+/// Reads the evaluation parameters from a yaml file as generic YAML value (e.g. for modifying
+/// and writing them back).
+pub fn read_evaluation_parameters_yaml(filename: &str) -> serde_yaml::Value {
+    let eval_params_str = fs::read_to_string(filename)
+        .unwrap_or_else(|e| panic!("Could not read evaluation yaml file {}: {:?}", filename, e));
+    serde_yaml::from_str(&eval_params_str).expect("Could not parse evaluation parameters.")
+}
+
+/// Reads [`EvaluationParameters`] from a (possibly modified) YAML value.
+pub fn eval_params_from_yaml(yaml: &serde_yaml::Value) -> EvaluationParameters {
+    parse_params(yaml).expect("Could not read evaluation parameters.")
+}
+
+/// Labels and configs of all metric entries in the evaluation parameters YAML value. The label
+/// is the key in the mapping format of the metrics config, and the name (or the metric)
+/// in the list format.
+pub fn metric_entries_mut(
+    eval_params_yaml: &mut serde_yaml::Value,
+) -> Vec<(String, &mut serde_yaml::Value)> {
+    match &mut eval_params_yaml["metrics"] {
+        serde_yaml::Value::Mapping(entries) => entries
+            .iter_mut()
+            .filter_map(|(key, entry)| key.as_str().map(|key| (key.to_string(), entry)))
+            .collect(),
+        serde_yaml::Value::Sequence(entries) => entries
+            .iter_mut()
+            .map(|entry| {
+                let label = entry["name"]
+                    .as_str()
+                    .or_else(|| entry["metric"].as_str())
+                    .unwrap_or_default()
+                    .to_string();
+                (label, entry)
+            })
+            .collect(),
+        _ => panic!("Could not find metrics in evaluation parameters."),
+    }
+}
 
-    let text = options.text.as_ref().cloned().or_else(|| {
+/// The config of the metric entry with the given label (see [`metric_entries_mut`]).
+pub fn metric_entry_mut<'a>(
+    eval_params_yaml: &'a mut serde_yaml::Value,
+    label: &str,
+) -> Option<&'a mut serde_yaml::Value> {
+    metric_entries_mut(eval_params_yaml)
+        .into_iter()
+        .find(|(l, _)| l == label)
+        .map(|(_, entry)| entry)
+}
+
+/// Whether a metric entry is enabled (metrics in the list format are enabled by default).
+fn is_enabled(entry: &serde_yaml::Value, list_format: bool) -> bool {
+    entry["enabled"].as_bool().unwrap_or(list_format)
+}
+
+/// Labels (see [`metric_entries_mut`]) of all enabled metrics in the evaluation parameters
+/// YAML value.
+pub fn enabled_metric_names(eval_params_yaml: &serde_yaml::Value) -> Vec<String> {
+    let list_format = eval_params_yaml["metrics"].is_sequence();
+    let mut eval_params_yaml = eval_params_yaml.clone();
+    metric_entries_mut(&mut eval_params_yaml)
+        .into_iter()
+        .filter(|(_, entry)| is_enabled(entry, list_format))
+        .map(|(label, _)| label)
+        .collect()
+}
+
+/// Determines the name under which each of the given metrics (labels, see
+/// [`metric_entries_mut`]) reports its results by evaluating the metric alone once
+/// (without any ngrams).
+pub fn metric_result_names(
+    eval_params_yaml: &serde_yaml::Value,
+    metric_names: &[String],
+    layout: &Layout,
+) -> Vec<String> {
+    let eval_params = eval_params_from_yaml(eval_params_yaml);
+    let empty_mapper = empty_ngram_mapper(eval_params.ngram_mapper);
+
+    metric_names
+        .iter()
+        .map(|name| {
+            let mut metric_params = eval_params_yaml.clone();
+            metric_entries_mut(&mut metric_params)
+                .into_iter()
+                .for_each(|(label, entry)| {
+                    entry["enabled"] = serde_yaml::Value::from(&label == name);
+                });
+            let metric_params = eval_params_from_yaml(&metric_params);
+
+            Evaluator::default(Box::new(empty_mapper.clone()))
+                .default_metrics(&metric_params.metrics)
+                .evaluate_layout(layout)
+                .iter()
+                .flat_map(|results| results.metric_costs.iter())
+                .map(|metric_cost| metric_cost.core.name.clone())
+                .next()
+                .unwrap_or_else(|| panic!("Metric '{}' did not produce a result.", name))
+        })
+        .collect()
+}
+
+/// Reads the text to evaluate from the commandline or from the corpus file (if any is given).
+pub fn read_text(options: &Options) -> Option<String> {
+    options.text.as_ref().cloned().or_else(|| {
         options.corpus.as_ref().map(|corpus_file| {
             fs::read_to_string(corpus_file)
                 .unwrap_or_else(|_| panic!("Could not read corpus file from {}.", corpus_file))
         })
-    });
+    })
+}
 
+/// Reads the (unprocessed) ngrams from the given text, corpus file, or ngram files.
+pub fn read_ngrams(options: &Options) -> (Unigrams, Bigrams, Trigrams) {
+    match read_text(options) {
+        Some(txt) => ngrams_from_text(&txt),
+        None => read_ngram_files(&options.ngrams),
+    }
+}
+
+/// Generates the (unprocessed) ngrams of a text.
+fn ngrams_from_text(txt: &str) -> (Unigrams, Bigrams, Trigrams) {
+    let unigrams = Unigrams::from_text(txt).expect("Could not generate unigrams from text.");
+    let bigrams = Bigrams::from_text(txt).expect("Could not generate bigrams from text.");
+    let trigrams = Trigrams::from_text(txt).expect("Could not generate trigrams from text.");
+
+    (unigrams, bigrams, trigrams)
+}
+
+/// Reads the (unprocessed) ngrams from the ngram files in the given directory.
+fn read_ngram_files(ngrams: &str) -> (Unigrams, Bigrams, Trigrams) {
+    let p = Path::new(ngrams).join("1-grams.txt");
+    log::info!("Reading unigram file: '{:?}'", p);
+    let unigrams = Unigrams::from_file(p.to_str().unwrap())
+        .unwrap_or_else(|_| panic!("Could not read 1-gramme file from '{:?}'.", &p));
+    let p = Path::new(ngrams).join("2-grams.txt");
+    log::info!("Reading bigram file: '{:?}'", p);
+    let bigrams = Bigrams::from_file(p.to_str().unwrap())
+        .unwrap_or_else(|_| panic!("Could not read 2-gramme file from '{:?}'.", &p));
+    let p = Path::new(ngrams).join("3-grams.txt");
+    log::info!("Reading trigram file: '{:?}'", p);
+    let trigrams = Trigrams::from_file(p.to_str().unwrap())
+        .unwrap_or_else(|_| panic!("Could not read 3-gramme file from '{:?}'.", &p));
+
+    (unigrams, bigrams, trigrams)
+}
+
+/// Generates an evaluator for the given ngrams. The ngrams are processed according to the
+/// options (excluded chars, increase of common ngrams, tops) before use.
+pub fn init_evaluator_with_ngrams(
+    options: &Options,
+    eval_params: &EvaluationParameters,
+    unigrams: Unigrams,
+    bigrams: Bigrams,
+    trigrams: Trigrams,
+) -> Evaluator {
+    let ngram_provider =
+        ngram_mapper_with_ngrams(options, eval_params, unigrams, bigrams, trigrams);
+
+    Evaluator::default(Box::new(ngram_provider))
+        .default_metrics(&eval_params.metrics)
+        .with_aggregation(&eval_params.aggregation)
+}
+
+/// Generates an ngram mapper for the given ngrams. The ngrams are processed according to the
+/// options (excluded chars, increase of common ngrams, tops) before use.
+fn ngram_mapper_with_ngrams(
+    options: &Options,
+    eval_params: &EvaluationParameters,
+    mut unigrams: Unigrams,
+    mut bigrams: Bigrams,
+    mut trigrams: Trigrams,
+) -> OnDemandNgramMapper {
     let mut ngram_mapper_config = eval_params.ngram_mapper.clone();
     if options.no_split_modifiers {
         ngram_mapper_config.split_modifiers.enabled = false;
@@ -159,34 +598,6 @@ pub fn init_evaluator(options: &Options) -> Evaluator {
         ngrams_config.increase_common_ngrams.enabled = false;
     }
 
-    let (mut unigrams, mut bigrams, mut trigrams) = match text {
-        Some(txt) => {
-            let unigrams =
-                Unigrams::from_text(&txt).expect("Could not generate unigrams from text.");
-            let bigrams = Bigrams::from_text(&txt).expect("Could not generate bigrams from text.");
-            let trigrams =
-                Trigrams::from_text(&txt).expect("Could not generate trigrams from text.");
-
-            (unigrams, bigrams, trigrams)
-        }
-        None => {
-            let p = Path::new(&options.ngrams).join("1-grams.txt");
-            log::info!("Reading unigram file: '{:?}'", p);
-            let unigrams = Unigrams::from_file(p.to_str().unwrap())
-                .unwrap_or_else(|_| panic!("Could not read 1-gramme file from '{:?}'.", &p));
-            let p = Path::new(&options.ngrams).join("2-grams.txt");
-            log::info!("Reading bigram file: '{:?}'", p);
-            let bigrams = Bigrams::from_file(p.to_str().unwrap())
-                .unwrap_or_else(|_| panic!("Could not read 2-gramme file from '{:?}'.", &p));
-            let p = Path::new(&options.ngrams).join("3-grams.txt");
-            log::info!("Reading trigram file: '{:?}'", p);
-            let trigrams = Trigrams::from_file(p.to_str().unwrap())
-                .unwrap_or_else(|_| panic!("Could not read 3-gramme file from '{:?}'.", &p));
-
-            (unigrams, bigrams, trigrams)
-        }
-    };
-
     if let Some(exclude_chars) = &options.exclude_chars {
         for exclude_char in exclude_chars.chars() {
             unigrams = unigrams.exclude_char(&exclude_char);
@@ -207,10 +618,7 @@ pub fn init_evaluator(options: &Options) -> Evaluator {
         trigrams = trigrams.tops(tops);
     }
 
-    let ngram_provider =
-        OnDemandNgramMapper::with_ngrams(unigrams, bigrams, trigrams, ngram_mapper_config);
-
-    Evaluator::default(Box::new(ngram_provider)).default_metrics(&eval_params.metrics)
+    OnDemandNgramMapper::with_ngrams(unigrams, bigrams, trigrams, ngram_mapper_config)
 }
 
 /// Appends a layout-string to a file.
@@ -0,0 +1,563 @@
+//! Conversion of keystroke event logs into ngram frequencies.
+//!
+//! A keystroke log consists of key down/up events of physical keys. Each key is named by the
+//! symbol it generates on the base layer of the layout that was active while typing (e.g. `a`,
+//! or `⇧` for a layer modifier), or by one of the names `space`, `enter`, `tab`, `backspace`,
+//! `ctrl`, `alt`, `meta` (optionally prefixed with `left`/`right`). The layout determines which
+//! symbol results from a key that is pressed while holding layer modifiers.
+//!
+//! Keystrokes are collected into sequences. A sequence is interrupted whenever the pause between
+//! two keystrokes exceeds a threshold, when a key is pressed that does not generate a symbol
+//! (e.g. arrow keys), or when a shortcut (a key pressed while holding `ctrl`, `alt`, or `meta`)
+//! is typed. Ngrams are only counted within sequences.
+
+use keyboard_layout::{layout::Layout, layout_generator::LayoutGenerator};
+use layout_evaluation::ngrams::{Bigrams, Trigrams, Unigrams};
+
+use ahash::AHashMap;
+use anyhow::Result;
+use clap::ArgEnum;
+use serde::Deserialize;
+
+/// Type of a keystroke event.
+#[derive(Clone, Copy, Deserialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyEventType {
+    Down,
+    Up,
+}
+
+/// A single entry of a keystroke log.
+#[derive(Clone, Deserialize, Debug)]
+pub struct KeystrokeEvent {
+    /// Time of the event (in ms)
+    pub timestamp: f64,
+    /// Whether the key was pressed or released
+    pub event: KeyEventType,
+    /// Symbol of the key on the base layer of the active layout or name of a special key
+    pub key: String,
+    /// The layout that was active during the event (if it differs from the default layout)
+    #[serde(default)]
+    pub layout: Option<String>,
+}
+
+/// How to treat the backspace key.
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BackspaceHandling {
+    /// Count backspace as a symbol of its own (as it is actually typed)
+    Keep,
+    /// Remove the previous symbol from the sequence (as in the resulting text)
+    Erase,
+    /// Interrupt the sequence
+    Ignore,
+}
+
+#[derive(Clone, Debug)]
+pub struct KeystrokeConfig {
+    /// Interrupt sequences if the time between two keystrokes exceeds this value (in ms)
+    pub max_pause: f64,
+    /// How to treat the backspace key
+    pub backspace: BackspaceHandling,
+    /// Symbol to use for backspace if it is kept in the sequences
+    pub backspace_symbol: char,
+    /// Count keys typed as part of shortcuts (as sequences of their own)
+    pub include_shortcuts: bool,
+}
+
+impl Default for KeystrokeConfig {
+    fn default() -> Self {
+        Self {
+            max_pause: 1000.0,
+            backspace: BackspaceHandling::Keep,
+            backspace_symbol: '←',
+            include_shortcuts: false,
+        }
+    }
+}
+
+/// Meaning of a key from the keystroke log.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum KeyAction {
+    /// A key generating a symbol (given as the symbol on the base layer)
+    Symbol(char),
+    /// A modifier activating a layer of the layout
+    LayerModifier(char),
+    /// A modifier used for shortcuts (ctrl, alt, meta)
+    ShortcutModifier,
+    Backspace,
+    /// Any other key (e.g. navigation)
+    Other,
+}
+
+/// Resolves the keys of the keystroke log to the symbols generated with a layout.
+#[derive(Clone, Debug)]
+struct KeyResolver {
+    /// Symbols of all layer modifiers
+    layer_modifiers: Vec<char>,
+    /// Symbols for each (base layer symbol, sorted symbols of held layer modifiers)
+    symbols: AHashMap<(char, Vec<char>), char>,
+}
+
+impl KeyResolver {
+    fn new(layout: &Layout) -> Self {
+        let mut layer_modifiers: Vec<char> = layout
+            .layerkeys
+            .iter()
+            .filter(|lk| lk.is_modifier.is_some())
+            .map(|lk| lk.symbol)
+            .collect();
+        layer_modifiers.sort_unstable();
+        layer_modifiers.dedup();
+
+        let base_symbols: AHashMap<_, char> = layout
+            .layerkeys
+            .iter()
+            .filter(|lk| lk.layer == 0 && lk.is_modifier.is_none())
+            .map(|lk| (lk.key.matrix_position, lk.symbol))
+            .collect();
+
+        // the layerkeys only carry the modifiers of the opposite hand, but typists may use
+        // the modifiers of either hand -> collect all modifier combinations for each layer
+        let layer_modifier_sets: AHashMap<u8, Vec<Vec<char>>> = layout
+            .layerkeys
+            .iter()
+            .filter(|lk| lk.layer > 0)
+            .fold(AHashMap::default(), |mut sets, lk| {
+                let mut modifiers: Vec<char> = lk
+                    .modifiers
+                    .layerkey_indices()
+                    .iter()
+                    .map(|idx| layout.get_layerkey(idx).symbol)
+                    .collect();
+                modifiers.sort_unstable();
+                modifiers.dedup();
+
+                let layer_sets: &mut Vec<Vec<char>> = sets.entry(lk.layer).or_default();
+                if !modifiers.is_empty() && !layer_sets.contains(&modifiers) {
+                    layer_sets.push(modifiers);
+                }
+                sets
+            });
+        let mut symbols = AHashMap::default();
+        layout
+            .layerkeys
+            .iter()
+            .filter(|lk| lk.layer > 0 && lk.is_modifier.is_none())
+            .for_each(|lk| {
+                let base_symbol = match base_symbols.get(&lk.key.matrix_position) {
+                    Some(c) => *c,
+                    None => return,
+                };
+                layer_modifier_sets
+                    .get(&lk.layer)
+                    .into_iter()
+                    .flatten()
+                    .for_each(|modifiers| {
+                        symbols
+                            .entry((base_symbol, modifiers.clone()))
+                            .or_insert(lk.symbol);
+                    });
+            });
+
+        Self {
+            layer_modifiers,
+            symbols,
+        }
+    }
+
+    /// Determine the meaning of a key name from the keystroke log.
+    fn action(&self, key: &str) -> KeyAction {
+        let mut chars = key.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            if self.layer_modifiers.contains(&c) {
+                return KeyAction::LayerModifier(c);
+            }
+            return KeyAction::Symbol(c);
+        }
+
+        let name = key.to_lowercase();
+        let name = name
+            .trim_start_matches("left")
+            .trim_start_matches("right")
+            .trim_start_matches('_');
+        match name {
+            "space" => KeyAction::Symbol(' '),
+            "enter" | "return" => KeyAction::Symbol('\n'),
+            "tab" => KeyAction::Symbol('\t'),
+            "backspace" => KeyAction::Backspace,
+            "ctrl" | "control" | "alt" | "meta" | "super" | "cmd" | "win" => {
+                KeyAction::ShortcutModifier
+            }
+            _ => KeyAction::Other,
+        }
+    }
+
+    /// The symbol generated by a key (given by its base layer symbol) with held layer modifiers.
+    /// Symbols that are not part of the base layer are assumed to be already resolved.
+    fn symbol(&self, base_symbol: char, held_modifiers: &[char]) -> Option<char> {
+        if held_modifiers.is_empty() {
+            return Some(base_symbol);
+        }
+
+        self.symbols
+            .get(&(base_symbol, held_modifiers.to_vec()))
+            .cloned()
+    }
+}
+
+/// Converts keystroke events into sequences of typed symbols.
+pub struct KeystrokeConverter<'a> {
+    layout_generator: &'a dyn LayoutGenerator,
+    default_layout: String,
+    config: KeystrokeConfig,
+    resolvers: AHashMap<String, KeyResolver>,
+}
+
+impl<'a> KeystrokeConverter<'a> {
+    pub fn new(
+        layout_generator: &'a dyn LayoutGenerator,
+        default_layout: &str,
+        config: KeystrokeConfig,
+    ) -> Self {
+        Self {
+            layout_generator,
+            default_layout: default_layout.to_string(),
+            config,
+            resolvers: AHashMap::default(),
+        }
+    }
+
+    fn resolver(&mut self, layout_str: &str) -> Result<&KeyResolver> {
+        if !self.resolvers.contains_key(layout_str) {
+            let layout = self.layout_generator.generate(layout_str)?;
+            self.resolvers
+                .insert(layout_str.to_string(), KeyResolver::new(&layout));
+        }
+
+        Ok(self.resolvers.get(layout_str).unwrap())
+    }
+
+    /// Convert a list of keystroke events (sorted by time) into sequences of typed symbols.
+    pub fn sequences(&mut self, events: &[KeystrokeEvent]) -> Result<Vec<Vec<char>>> {
+        let config = self.config.clone();
+        let default_layout = self.default_layout.clone();
+
+        let mut sequences = Vec::new();
+        let mut current: Vec<char> = Vec::new();
+        let mut held_layer_modifiers: Vec<char> = Vec::new();
+        let mut held_shortcut_modifiers: Vec<String> = Vec::new();
+        let mut last_keystroke: Option<f64> = None;
+
+        for event in events {
+            let layout_str = event.layout.as_deref().unwrap_or(&default_layout);
+            let resolver = self.resolver(layout_str)?;
+            let action = resolver.action(&event.key);
+
+            if event.event == KeyEventType::Up {
+                match action {
+                    KeyAction::LayerModifier(c) => held_layer_modifiers.retain(|m| *m != c),
+                    KeyAction::ShortcutModifier => {
+                        held_shortcut_modifiers.retain(|m| *m != event.key)
+                    }
+                    _ => (),
+                }
+                continue;
+            }
+
+            if let Some(last) = last_keystroke {
+                if event.timestamp - last > config.max_pause {
+                    sequences.push(std::mem::take(&mut current));
+                }
+            }
+            last_keystroke = Some(event.timestamp);
+
+            match action {
+                KeyAction::LayerModifier(c) => {
+                    if !held_layer_modifiers.contains(&c) {
+                        held_layer_modifiers.push(c);
+                        held_layer_modifiers.sort_unstable();
+                    }
+                }
+                KeyAction::ShortcutModifier => {
+                    if !held_shortcut_modifiers.contains(&event.key) {
+                        held_shortcut_modifiers.push(event.key.clone());
+                    }
+                }
+                KeyAction::Symbol(c) if !held_shortcut_modifiers.is_empty() => {
+                    sequences.push(std::mem::take(&mut current));
+                    if config.include_shortcuts {
+                        sequences.push(vec![c]);
+                    }
+                }
+                KeyAction::Symbol(c) => match resolver.symbol(c, &held_layer_modifiers) {
+                    Some(symbol) => current.push(symbol),
+                    None => {
+                        log::warn!(
+                            "Key '{}' with modifiers {:?} does not generate a symbol",
+                            c,
+                            held_layer_modifiers
+                        );
+                        sequences.push(std::mem::take(&mut current));
+                    }
+                },
+                KeyAction::Backspace => match config.backspace {
+                    BackspaceHandling::Keep => current.push(config.backspace_symbol),
+                    BackspaceHandling::Erase => {
+                        current.pop();
+                    }
+                    BackspaceHandling::Ignore => sequences.push(std::mem::take(&mut current)),
+                },
+                KeyAction::Other => sequences.push(std::mem::take(&mut current)),
+            }
+        }
+        sequences.push(current);
+        sequences.retain(|s| !s.is_empty());
+
+        Ok(sequences)
+    }
+}
+
+/// Count the ngrams within each of the given sequences.
+pub fn ngrams_from_sequences(sequences: &[Vec<char>]) -> (Unigrams, Bigrams, Trigrams) {
+    let mut unigrams = AHashMap::default();
+    let mut bigrams = AHashMap::default();
+    let mut trigrams = AHashMap::default();
+
+    for sequence in sequences {
+        sequence.iter().for_each(|c| {
+            *unigrams.entry(*c).or_insert(0.0) += 1.0;
+        });
+        sequence.windows(2).for_each(|w| {
+            *bigrams.entry((w[0], w[1])).or_insert(0.0) += 1.0;
+        });
+        sequence.windows(3).for_each(|w| {
+            *trigrams.entry((w[0], w[1], w[2])).or_insert(0.0) += 1.0;
+        });
+    }
+
+    (
+        Unigrams { grams: unigrams },
+        Bigrams { grams: bigrams },
+        Trigrams { grams: trigrams },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::init_layout_generator;
+
+    const LAYOUT: &str = "xvlcwkhgfqyßuiaeosnrtdüöäpzbm,.j";
+
+    fn events(log: &[(f64, &str, &str)]) -> Vec<KeystrokeEvent> {
+        log.iter()
+            .map(|(timestamp, event, key)| KeystrokeEvent {
+                timestamp: *timestamp,
+                event: if *event == "down" {
+                    KeyEventType::Down
+                } else {
+                    KeyEventType::Up
+                },
+                key: key.to_string(),
+                layout: None,
+            })
+            .collect()
+    }
+
+    /// Type each char of the text with a key down and a key up event.
+    fn typed(text: &str, start: f64) -> Vec<(f64, &'static str, String)> {
+        text.chars()
+            .enumerate()
+            .flat_map(|(i, c)| {
+                let t = start + 100.0 * i as f64;
+                vec![(t, "down", c.to_string()), (t + 50.0, "up", c.to_string())]
+            })
+            .collect()
+    }
+
+    fn convert(log: &[(f64, &str, &str)], config: KeystrokeConfig) -> Vec<String> {
+        let layout_generator = init_layout_generator("../config/keyboard/standard.yml", false);
+        let mut converter = KeystrokeConverter::new(layout_generator.as_ref(), LAYOUT, config);
+        converter
+            .sequences(&events(log))
+            .unwrap()
+            .iter()
+            .map(|s| s.iter().collect())
+            .collect()
+    }
+
+    fn convert_text(text: &str) -> Vec<String> {
+        let log = typed(text, 0.0);
+        let log: Vec<(f64, &str, &str)> =
+            log.iter().map(|(t, e, k)| (*t, *e, k.as_str())).collect();
+        convert(&log, KeystrokeConfig::default())
+    }
+
+    #[test]
+    fn plain_text() {
+        assert_eq!(convert_text("hallo"), vec!["hallo"]);
+    }
+
+    #[test]
+    fn idle_gap_breaks_sequence() {
+        let log = vec![
+            (0.0, "down", "a"),
+            (50.0, "up", "a"),
+            (100.0, "down", "b"),
+            (150.0, "up", "b"),
+            (5000.0, "down", "c"),
+            (5050.0, "up", "c"),
+        ];
+        assert_eq!(convert(&log, KeystrokeConfig::default()), vec!["ab", "c"]);
+
+        let config = KeystrokeConfig {
+            max_pause: 10000.0,
+            ..Default::default()
+        };
+        assert_eq!(convert(&log, config), vec!["abc"]);
+    }
+
+    #[test]
+    fn layer_modifiers() {
+        // shift (⇧) + a -> A, mod3 (⇩) + e -> }
+        let log = vec![
+            (0.0, "down", "⇧"),
+            (50.0, "down", "a"),
+            (80.0, "up", "a"),
+            (90.0, "up", "⇧"),
+            (150.0, "down", "b"),
+            (180.0, "up", "b"),
+            (200.0, "down", "⇩"),
+            (250.0, "down", "e"),
+            (280.0, "up", "e"),
+            (290.0, "up", "⇩"),
+        ];
+        assert_eq!(convert(&log, KeystrokeConfig::default()), vec!["Ab}"]);
+    }
+
+    #[test]
+    fn layer_modifier_of_same_hand() {
+        // the right shift (⇗) also activates the shift layer for keys of the right hand
+        let log = vec![
+            (0.0, "down", "⇗"),
+            (50.0, "down", "n"),
+            (80.0, "up", "n"),
+            (90.0, "up", "⇗"),
+        ];
+        assert_eq!(convert(&log, KeystrokeConfig::default()), vec!["N"]);
+    }
+
+    #[test]
+    fn backspace() {
+        let log = vec![
+            (0.0, "down", "a"),
+            (50.0, "up", "a"),
+            (100.0, "down", "x"),
+            (150.0, "up", "x"),
+            (200.0, "down", "backspace"),
+            (250.0, "up", "backspace"),
+            (300.0, "down", "b"),
+            (350.0, "up", "b"),
+        ];
+
+        assert_eq!(convert(&log, KeystrokeConfig::default()), vec!["ax←b"]);
+
+        let config = KeystrokeConfig {
+            backspace: BackspaceHandling::Erase,
+            ..Default::default()
+        };
+        assert_eq!(convert(&log, config), vec!["ab"]);
+
+        let config = KeystrokeConfig {
+            backspace: BackspaceHandling::Ignore,
+            ..Default::default()
+        };
+        assert_eq!(convert(&log, config), vec!["ax", "b"]);
+    }
+
+    #[test]
+    fn shortcuts() {
+        // "ab", ctrl+c, "de"
+        let log = vec![
+            (0.0, "down", "a"),
+            (50.0, "up", "a"),
+            (100.0, "down", "b"),
+            (150.0, "up", "b"),
+            (200.0, "down", "left_ctrl"),
+            (250.0, "down", "c"),
+            (280.0, "up", "c"),
+            (290.0, "up", "left_ctrl"),
+            (300.0, "down", "d"),
+            (350.0, "up", "d"),
+            (400.0, "down", "e"),
+            (450.0, "up", "e"),
+        ];
+        assert_eq!(convert(&log, KeystrokeConfig::default()), vec!["ab", "de"]);
+
+        let config = KeystrokeConfig {
+            include_shortcuts: true,
+            ..Default::default()
+        };
+        assert_eq!(convert(&log, config), vec!["ab", "c", "de"]);
+    }
+
+    #[test]
+    fn navigation_keys_break_sequence() {
+        let log = vec![
+            (0.0, "down", "a"),
+            (50.0, "up", "a"),
+            (100.0, "down", "arrow_left"),
+            (150.0, "up", "arrow_left"),
+            (200.0, "down", "space"),
+            (250.0, "up", "space"),
+            (300.0, "down", "b"),
+            (350.0, "up", "b"),
+        ];
+        assert_eq!(convert(&log, KeystrokeConfig::default()), vec!["a", " b"]);
+    }
+
+    #[test]
+    fn layout_switch() {
+        // mod3 (⇩) + a on the default layout, then on a layout with "a" and "b" swapped
+        // (the mod3 layer is not permutated and stays with the physical key)
+        let switched = LAYOUT.replace('a', "_").replace('b', "a").replace('_', "b");
+        let mut events = events(&[
+            (0.0, "down", "⇩"),
+            (50.0, "down", "a"),
+            (80.0, "up", "a"),
+            (100.0, "down", "a"),
+            (130.0, "up", "a"),
+            (140.0, "up", "⇩"),
+        ]);
+        events[3].layout = Some(switched.clone());
+        events[4].layout = Some(switched.clone());
+        events[5].layout = Some(switched);
+
+        let layout_generator = init_layout_generator("../config/keyboard/standard.yml", false);
+        let mut converter = KeystrokeConverter::new(
+            layout_generator.as_ref(),
+            LAYOUT,
+            KeystrokeConfig::default(),
+        );
+        let sequences = converter.sequences(&events).unwrap();
+        assert_eq!(sequences, vec![vec!['{', '+']]);
+    }
+
+    #[test]
+    fn ngram_counts() {
+        let sequences = vec![vec!['a', 'b', 'a', 'b'], vec!['b', 'a']];
+        let (unigrams, bigrams, trigrams) = ngrams_from_sequences(&sequences);
+
+        assert_eq!(unigrams.grams.len(), 2);
+        assert_eq!(unigrams.grams[&'a'], 3.0);
+        assert_eq!(unigrams.grams[&'b'], 3.0);
+
+        assert_eq!(bigrams.grams.len(), 2);
+        assert_eq!(bigrams.grams[&('a', 'b')], 2.0);
+        assert_eq!(bigrams.grams[&('b', 'a')], 2.0);
+
+        assert_eq!(trigrams.grams.len(), 2);
+        assert_eq!(trigrams.grams[&('a', 'b', 'a')], 1.0);
+        assert_eq!(trigrams.grams[&('b', 'a', 'b')], 1.0);
+    }
+}
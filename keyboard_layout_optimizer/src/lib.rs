@@ -1 +1,4 @@
+pub mod bootstrap;
 pub mod common;
+pub mod keystrokes;
+pub mod statistics;
@@ -0,0 +1,49 @@
+//! Basic descriptive statistics of series of costs.
+
+/// Mean and (sample) standard deviation of the values.
+pub fn mean_std(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let var = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0).max(1.0);
+
+    (mean, var.sqrt())
+}
+
+/// The values sorted in ascending order.
+pub fn sorted(values: &[f64]) -> Vec<f64> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    sorted
+}
+
+/// Linearly interpolated quantile of sorted values (NaN if there are none).
+pub fn quantile(sorted: &[f64], q: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    let pos = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let (lower, upper) = (pos.floor() as usize, pos.ceil() as usize);
+    let frac = pos - lower as f64;
+
+    sorted[lower] * (1.0 - frac) + sorted[upper] * frac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statistics_of_values() {
+        let (mean, std) = mean_std(&[2.0, 4.0, 4.0, 6.0]);
+        assert!((mean - 4.0).abs() < 1e-12);
+        assert!((std - (8.0f64 / 3.0).sqrt()).abs() < 1e-12);
+
+        let values = sorted(&[3.0, 1.0, 4.0, 2.0]);
+        assert_eq!(values, vec![1.0, 2.0, 3.0, 4.0]);
+        assert!((quantile(&values, 0.5) - 2.5).abs() < 1e-12);
+        assert!((quantile(&values[..3], 0.5) - 2.0).abs() < 1e-12);
+        assert!((quantile(&values, 1.0) - 4.0).abs() < 1e-12);
+        assert!(quantile(&[], 0.5).is_nan());
+    }
+}
@@ -63,8 +63,9 @@ pub fn evaluate_bench(c: &mut Criterion) {
     let ngram_provider =
         OnDemandNgramMapper::with_ngrams(unigrams, bigrams, trigrams, ngram_mapper_config);
 
-    let evaluator =
-        Evaluator::default(Box::new(ngram_provider)).default_metrics(&eval_params.metrics);
+    let evaluator = Evaluator::default(Box::new(ngram_provider))
+        .default_metrics(&eval_params.metrics)
+        .with_aggregation(&eval_params.aggregation);
 
     let layout = match layout_generator.generate("jduaxphlmwqßctieobnrsgfvüäöyz,.k") {
         Ok(layout) => layout,
@@ -0,0 +1,362 @@
+//! The `aggregation` module specifies how the individual metrics' costs are aggregated to
+//! the total cost of a layout.
+//!
+//! By default, the total cost is the sum of the weighted (and normalized) metric costs.
+//! Alternatively, a weighted power mean emphasizes metrics with high costs. Additionally,
+//! penalties for metrics exceeding a threshold and hard caps can be specified, e.g. in order
+//! to express that no single metric may be bad.
+//!
+//! If a layout is evaluated on several corpora or keyboards, their total costs are combined
+//! according to a [`CombinationMethod`].
+
+use crate::results::{MetricResults, NormalizedMetricResult};
+
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+/// The method used for combining the metric costs.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AggregationMethod {
+    /// Sum of the weighted metric costs.
+    WeightedSum,
+    /// Weighted power mean of the (normalized, unweighted) metric costs, multiplied with the
+    /// sum of the weights. An exponent of 1 corresponds to the weighted sum, larger exponents
+    /// emphasize metrics with high costs. Metrics with negative costs or weights (bonuses)
+    /// are added linearly. The exponent needs to be positive.
+    PowerMean {
+        #[serde(deserialize_with = "positive_exponent")]
+        exponent: f64,
+    },
+}
+
+fn positive_exponent<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    let exponent = f64::deserialize(deserializer)?;
+    if exponent > 0.0 {
+        Ok(exponent)
+    } else {
+        Err(de::Error::custom(format!(
+            "the exponent of a power mean needs to be positive, got {}",
+            exponent
+        )))
+    }
+}
+
+impl Default for AggregationMethod {
+    fn default() -> Self {
+        Self::WeightedSum
+    }
+}
+
+/// A penalty that is added to the total cost if a metric's (normalized, unweighted) cost
+/// exceeds a threshold. The penalty amounts to `factor * softplus(cost - threshold)` where the
+/// softplus function is a smooth approximation of `max(0, cost - threshold)`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Penalty {
+    /// Name of the metric (as it appears in the evaluation results)
+    pub metric: String,
+    /// Costs above this value are penalized
+    pub threshold: f64,
+    /// Factor of the penalty
+    pub factor: f64,
+    /// Width of the transition region around the threshold. A value of 0 results in a hard
+    /// `max(0, cost - threshold)`.
+    #[serde(default)]
+    pub softness: f64,
+}
+
+impl Penalty {
+    fn cost(&self, metric_cost: f64) -> f64 {
+        let excess = metric_cost - self.threshold;
+        let hinge = if self.softness > 0.0 {
+            let x = excess / self.softness;
+            // avoid an overflow of the exponential function for large values
+            let softplus = if x > 30.0 { x } else { x.exp().ln_1p() };
+            self.softness * softplus
+        } else {
+            excess.max(0.0)
+        };
+
+        self.factor * hinge
+    }
+}
+
+fn default_violation_cost() -> f64 {
+    1e6
+}
+
+/// A hard upper limit for a metric's (normalized, unweighted) cost. Exceeding it adds
+/// `violation_cost * (1 + cost - max)` to the total cost, such that a layout violating the cap
+/// is worse than any layout respecting it while the optimizer still sees a gradient.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Cap {
+    /// Name of the metric (as it appears in the evaluation results)
+    pub metric: String,
+    /// Maximal allowed cost
+    pub max: f64,
+    /// Cost added for violating the cap
+    #[serde(default = "default_violation_cost")]
+    pub violation_cost: f64,
+}
+
+impl Cap {
+    fn cost(&self, metric_cost: f64) -> f64 {
+        if metric_cost > self.max {
+            self.violation_cost * (1.0 + metric_cost - self.max)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Configuration of the aggregation of metric costs to a total cost.
+/// The default corresponds to the plain weighted sum.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct AggregationConfig {
+    /// Method for combining the metric costs
+    #[serde(default)]
+    pub method: AggregationMethod,
+    /// Penalties for metrics exceeding thresholds
+    #[serde(default)]
+    pub penalties: Vec<Penalty>,
+    /// Hard upper limits for metric costs
+    #[serde(default)]
+    pub caps: Vec<Cap>,
+}
+
+impl AggregationConfig {
+    /// Whether the aggregation is a plain weighted sum (without penalties and caps).
+    pub fn is_weighted_sum(&self) -> bool {
+        self.method == AggregationMethod::WeightedSum
+            && self.penalties.is_empty()
+            && self.caps.is_empty()
+    }
+
+    /// Aggregate the metric costs to a total cost.
+    pub fn total_cost(&self, individual_results: &[MetricResults]) -> f64 {
+        let mut cost = match self.method {
+            AggregationMethod::WeightedSum => {
+                let mut cost = 0.0;
+                individual_results
+                    .iter()
+                    .filter(|mc| !mc.metric_costs.is_empty())
+                    .for_each(|mc| cost += mc.total_cost());
+
+                cost
+            }
+            AggregationMethod::PowerMean { exponent } => power_mean(individual_results, exponent),
+        };
+
+        if !self.penalties.is_empty() || !self.caps.is_empty() {
+            individual_results
+                .iter()
+                .flat_map(|mc| mc.metric_costs.iter())
+                .for_each(|metric_cost| {
+                    self.penalties
+                        .iter()
+                        .filter(|p| p.metric == metric_cost.core.name)
+                        .for_each(|p| cost += p.cost(metric_cost.unweighted_cost));
+                    self.caps
+                        .iter()
+                        .filter(|c| c.metric == metric_cost.core.name)
+                        .for_each(|c| cost += c.cost(metric_cost.unweighted_cost));
+                });
+        }
+
+        cost
+    }
+}
+
+/// How the total costs of a layout on several corpora or keyboards are combined.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CombinationMethod {
+    /// Sum of the total costs, weighted with the (normalized) weights of the corpora or keyboards.
+    WeightedSum,
+    /// Highest total cost of all corpora or keyboards.
+    Worst,
+}
+
+impl Default for CombinationMethod {
+    fn default() -> Self {
+        Self::WeightedSum
+    }
+}
+
+impl CombinationMethod {
+    /// Whether the costs are combined by their weighted sum (the default).
+    pub fn is_weighted_sum(&self) -> bool {
+        *self == Self::WeightedSum
+    }
+
+    /// Combine total costs given as `(weight, cost)`. The weights are normalized
+    /// such that they sum up to one.
+    pub fn total_cost(&self, costs: &[(f64, f64)]) -> f64 {
+        match self {
+            Self::WeightedSum => {
+                let weight_sum: f64 = costs.iter().map(|(w, _)| w).sum();
+                costs.iter().map(|(w, c)| w * c).sum::<f64>() / weight_sum
+            }
+            Self::Worst => costs
+                .iter()
+                .map(|(_, c)| *c)
+                .fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+}
+
+/// Weighted power mean of the non-negative metric costs (with positive weights), scaled by the
+/// sum of their weights, plus the weighted costs of all remaining metrics.
+fn power_mean(individual_results: &[MetricResults], exponent: f64) -> f64 {
+    let (mean_terms, linear_terms): (Vec<&NormalizedMetricResult>, Vec<&NormalizedMetricResult>) =
+        individual_results
+            .iter()
+            .flat_map(|mc| mc.metric_costs.iter())
+            .partition(|metric_cost| {
+                metric_cost.core.weight > 0.0 && metric_cost.unweighted_cost >= 0.0
+            });
+
+    let weight_sum: f64 = mean_terms.iter().map(|mc| mc.core.weight).sum();
+    let mean = if weight_sum > 0.0 {
+        let weighted_powers: f64 = mean_terms
+            .iter()
+            .map(|mc| mc.core.weight * mc.unweighted_cost.powf(exponent))
+            .sum();
+        (weighted_powers / weight_sum).powf(1.0 / exponent)
+    } else {
+        0.0
+    };
+
+    weight_sum * mean + linear_terms.iter().map(|mc| mc.weighted_cost).sum::<f64>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EvaluationParameters;
+    use crate::evaluation::Evaluator;
+    use crate::ngram_mapper::on_demand_ngram_mapper::OnDemandNgramMapper;
+    use crate::ngrams::{Bigrams, Trigrams, Unigrams};
+    use crate::results::{MetricResult, MetricType, NormalizationType};
+
+    use keyboard_layout::{
+        config::LayoutConfig, keyboard::Keyboard, layout_generator::LayoutGenerator,
+        neo_layout_generator::NeoLayoutGenerator,
+    };
+    use std::sync::Arc;
+
+    /// Results of metrics given as `(name, weight, unweighted cost)`.
+    fn results(metrics: &[(&str, f64, f64)]) -> Vec<MetricResults> {
+        let metric_costs = metrics
+            .iter()
+            .map(|(name, weight, cost)| NormalizedMetricResult {
+                core: MetricResult {
+                    name: name.to_string(),
+                    cost: *cost,
+                    message: None,
+                    weight: *weight,
+                    normalization: NormalizationType::Fixed(1.0),
+                },
+                weighted_cost: weight * cost,
+                unweighted_cost: *cost,
+            })
+            .collect();
+
+        vec![MetricResults {
+            metric_type: MetricType::Layout,
+            found_weight: 0.0,
+            not_found_weight: 0.0,
+            metric_costs,
+        }]
+    }
+
+    fn aggregation(yaml: &str) -> AggregationConfig {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn default_config_reproduces_the_weighted_sum() {
+        let eval_params =
+            EvaluationParameters::from_yaml("../config/evaluation/default.yml").unwrap();
+        assert!(eval_params.aggregation.is_weighted_sum());
+
+        let text = "Franz jagt im komplett verwahrlosten Taxi quer durch Bayern.";
+        let ngram_mapper = OnDemandNgramMapper::with_ngrams(
+            Unigrams::from_text(text).unwrap(),
+            Bigrams::from_text(text).unwrap(),
+            Trigrams::from_text(text).unwrap(),
+            eval_params.ngram_mapper.clone(),
+        );
+        let evaluator = Evaluator::default(Box::new(ngram_mapper))
+            .default_metrics(&eval_params.metrics)
+            .with_aggregation(&eval_params.aggregation);
+
+        let layout_config = LayoutConfig::from_yaml("../config/keyboard/standard.yml").unwrap();
+        let keyboard = Arc::new(Keyboard::from_yaml_object(layout_config.keyboard));
+        let layout_generator = NeoLayoutGenerator::from_object(layout_config.base_layout, keyboard);
+        let layout = layout_generator
+            .generate("xvlcwkhgfqyßuiaeosnrtdüöäpzbm,.j")
+            .unwrap();
+
+        let evaluation_result = evaluator.evaluate_layout(&layout);
+        let baseline: f64 = evaluation_result
+            .iter()
+            .flat_map(|mc| mc.metric_costs.iter())
+            .map(|metric_cost| metric_cost.weighted_cost)
+            .sum();
+
+        assert!(baseline > 0.0);
+        assert!((evaluation_result.total_cost() - baseline).abs() < 1e-9 * baseline);
+    }
+
+    #[test]
+    fn power_mean_emphasizes_high_costs() {
+        let metrics = results(&[("A", 1.0, 1.0), ("B", 3.0, 3.0), ("Bonus", -1.0, 2.0)]);
+        let weighted_sum = AggregationConfig::default().total_cost(&metrics);
+        assert!((weighted_sum - 8.0).abs() < 1e-9);
+
+        let linear = aggregation("method: {type: power_mean, exponent: 1.0}");
+        assert!((linear.total_cost(&metrics) - weighted_sum).abs() < 1e-9);
+
+        // (0.25 * 1 + 0.75 * 9)^(1/2) * 4 - 2
+        let quadratic = aggregation("method: {type: power_mean, exponent: 2.0}");
+        let expected = 7.0f64.sqrt() * 4.0 - 2.0;
+        assert!((quadratic.total_cost(&metrics) - expected).abs() < 1e-9);
+        assert!(quadratic.total_cost(&metrics) > weighted_sum);
+    }
+
+    #[test]
+    fn non_positive_exponents_are_rejected() {
+        for exponent in ["0.0", "-1.0"] {
+            let yaml = format!("method: {{type: power_mean, exponent: {}}}", exponent);
+            assert!(serde_yaml::from_str::<AggregationConfig>(&yaml).is_err());
+        }
+    }
+
+    #[test]
+    fn penalties_apply_above_the_threshold() {
+        let hard = aggregation("penalties: [{metric: A, threshold: 2.0, factor: 10.0}]");
+        assert!((hard.total_cost(&results(&[("A", 1.0, 1.5)])) - 1.5).abs() < 1e-9);
+        assert!((hard.total_cost(&results(&[("A", 1.0, 2.5)])) - 7.5).abs() < 1e-9);
+        // penalties only apply to the named metric
+        assert!((hard.total_cost(&results(&[("B", 1.0, 2.5)])) - 2.5).abs() < 1e-9);
+
+        let soft =
+            aggregation("penalties: [{metric: A, threshold: 2.0, factor: 10.0, softness: 0.1}]");
+        let below = soft.total_cost(&results(&[("A", 1.0, 1.5)])) - 1.5;
+        let at = soft.total_cost(&results(&[("A", 1.0, 2.0)])) - 2.0;
+        let above = soft.total_cost(&results(&[("A", 1.0, 2.5)])) - 2.5;
+        assert!(below > 0.0 && below < 1e-2);
+        assert!((at - 10.0 * 0.1 * 2.0f64.ln()).abs() < 1e-9);
+        assert!((above - 5.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn caps_dominate_the_total_cost() {
+        let capped = aggregation("caps: [{metric: A, max: 2.0}]");
+        assert!((capped.total_cost(&results(&[("A", 1.0, 2.0)])) - 2.0).abs() < 1e-9);
+
+        let violated = capped.total_cost(&results(&[("A", 1.0, 2.5)]));
+        assert!((violated - (1e6 * 1.5 + 2.5)).abs() < 1e-6);
+        assert!(violated < capped.total_cost(&results(&[("A", 1.0, 3.0)])));
+    }
+}
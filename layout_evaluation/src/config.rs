@@ -1,17 +1,37 @@
 use crate::{
-    evaluation::MetricParameters, ngram_mapper::on_demand_ngram_mapper::NgramMapperConfig,
-    ngrams::NgramsConfig,
+    aggregation::AggregationConfig, evaluation::MetricParameters,
+    ngram_mapper::on_demand_ngram_mapper::NgramMapperConfig, ngrams::NgramsConfig,
 };
 
 use anyhow::Result;
 use serde::Deserialize;
 use std::{fs::File, str::FromStr};
 
+fn default_reference_value() -> f64 {
+    100.0
+}
+
+/// A layout whose costs serve as reference for expressing costs of other layouts relatively.
+#[derive(Clone, Deserialize, Debug)]
+pub struct ReferenceLayoutConfig {
+    /// Name of the reference layout used in outputs
+    pub name: String,
+    /// Layout string of the reference layout (with respect to the layout config in use)
+    pub layout: String,
+    /// Value that the costs of the reference layout correspond to
+    #[serde(default = "default_reference_value")]
+    pub value: f64,
+}
+
 #[derive(Clone, Deserialize, Debug)]
 pub struct EvaluationParameters {
     pub metrics: MetricParameters,
     pub ngrams: NgramsConfig,
     pub ngram_mapper: NgramMapperConfig,
+    #[serde(default)]
+    pub reference_layout: Option<ReferenceLayoutConfig>,
+    #[serde(default)]
+    pub aggregation: AggregationConfig,
 }
 
 impl EvaluationParameters {
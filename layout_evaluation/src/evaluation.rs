@@ -6,17 +6,32 @@
 //!
 //! The ngram mapper is responsible for mapping char-based ngrams (as read from input data)
 //! to singles, pairs, and triplets of [`LayerKey`]s that can then be analysed by the individual metrics.
+//! Instead of a single ngram mapper, the evaluator may hold several named and weighted corpora
+//! (each with its own ngram mapper). Layouts are then evaluated on each corpus and the results
+//! are combined. Similarly, the evaluator may hold several keyboards (each with its own layout
+//! generator and evaluator) for evaluating how well a layout performs on all of them.
 
+use crate::aggregation::{AggregationConfig, CombinationMethod};
 use crate::results::{
-    EvaluationResult, MetricResult, MetricResults, MetricType, NormalizationType,
+    EvaluationResult, MetricResult, MetricResults, MetricType, NormalizationType, PartialResult,
 };
 use crate::{
-    metrics::{bigram_metrics::*, layout_metrics::*, trigram_metrics::*, unigram_metrics::*},
-    ngram_mapper::NgramMapper,
+    metrics::{
+        bigram_metrics::BigramMetric,
+        layout_metrics::LayoutMetric,
+        registry::{parse_params, Metric, MetricRegistry},
+        trigram_metrics::TrigramMetric,
+        unigram_metrics::UnigramMetric,
+    },
+    ngram_mapper::{MappedUnigrams, NgramMapper},
 };
 
-use keyboard_layout::layout::{LayerKey, Layout};
+use keyboard_layout::{
+    layout::{LayerKey, Layout},
+    layout_generator::LayoutGenerator,
+};
 
+use anyhow::{anyhow, Result};
 use serde::Deserialize;
 
 /// A wrapper around individuals metric's parameters (`T`) specifying
@@ -34,52 +49,81 @@ pub struct WeightedParams<T> {
     pub params: T,
 }
 
-/// Compiles configuration parameters for all "default" metrics available.
-/// This is usually read from a config file.
+fn default_enabled() -> bool {
+    true
+}
+
+/// Configuration of a single metric instance in the list format of the metrics config.
 #[derive(Clone, Deserialize, Debug)]
-pub struct MetricParameters {
-    pub shortcut_keys: Option<WeightedParams<shortcut_keys::Parameters>>,
-    pub similar_letters: Option<WeightedParams<similar_letters::Parameters>>,
-    pub similar_letter_groups: Option<WeightedParams<similar_letter_groups::Parameters>>,
-
-    pub finger_balance: Option<WeightedParams<finger_balance::Parameters>>,
-    pub hand_disbalance: Option<WeightedParams<hand_disbalance::Parameters>>,
-    pub row_loads: Option<WeightedParams<row_loads::Parameters>>,
-    pub key_costs: Option<WeightedParams<key_costs::Parameters>>,
-    pub modifier_usage: Option<WeightedParams<modifier_usage::Parameters>>,
-
-    pub symmetric_handswitches: Option<WeightedParams<symmetric_handswitches::Parameters>>,
-    pub finger_repeats: Option<WeightedParams<finger_repeats::Parameters>>,
-    pub manual_bigram_penalty: Option<WeightedParams<manual_bigram_penalty::Parameters>>,
-    pub movement_pattern: Option<WeightedParams<movement_pattern::Parameters>>,
-    pub no_handswitch_after_unbalancing_key:
-        Option<WeightedParams<no_handswitch_after_unbalancing_key::Parameters>>,
-
-    pub irregularity: Option<WeightedParams<irregularity::Parameters>>,
-    pub no_handswitch_in_trigram: Option<WeightedParams<no_handswitch_in_trigram::Parameters>>,
-    pub secondary_bigrams: Option<WeightedParams<secondary_bigrams::Parameters>>,
-    pub trigram_finger_repeats: Option<WeightedParams<trigram_finger_repeats::Parameters>>,
-    pub trigram_rolls: Option<WeightedParams<trigram_rolls::Parameters>>,
-
-    pub kla_home_key_words: Option<WeightedParams<kla_home_key_words::Parameters>>,
-    pub kla_same_finger_words: Option<WeightedParams<kla_same_finger_words::Parameters>>,
-    pub kla_distance: Option<WeightedParams<kla_distance::Parameters>>,
-    pub kla_finger_usage: Option<WeightedParams<kla_finger_usage::Parameters>>,
-    pub kla_same_finger: Option<WeightedParams<kla_same_finger::Parameters>>,
-    pub kla_same_hand: Option<WeightedParams<kla_same_hand::Parameters>>,
-
-    pub oxey_combined_trigram: Option<WeightedParams<oxey_combined_trigram::Parameters>>,
-
-    pub oxey_sfbs: Option<WeightedParams<oxey_sfbs::Parameters>>,
-    pub oxey_lsbs: Option<WeightedParams<oxey_lsbs::Parameters>>,
-    pub oxey_dsfbs: Option<WeightedParams<oxey_dsfbs::Parameters>>,
-    pub oxey_inward_rolls: Option<WeightedParams<oxey_inward_rolls::Parameters>>,
-    pub oxey_outward_rolls: Option<WeightedParams<oxey_outward_rolls::Parameters>>,
-    pub oxey_onehands: Option<WeightedParams<oxey_onehands::Parameters>>,
-    pub oxey_alternates: Option<WeightedParams<oxey_alternates::Parameters>>,
-    pub oxey_alternates_sfs: Option<WeightedParams<oxey_alternates_sfs::Parameters>>,
-    pub oxey_redirects: Option<WeightedParams<oxey_redirects::Parameters>>,
-    pub oxey_bad_redirects: Option<WeightedParams<oxey_bad_redirects::Parameters>>,
+pub struct MetricConfig {
+    /// Name under which the metric is registered in the [`MetricRegistry`].
+    pub metric: String,
+    /// Name under which the results are reported (defaults to the metric's own name).
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Wether the metric is to be evaluated.
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// The weight to use when aggregating all metrics.
+    pub weight: f64,
+    /// The normalization strategy to use.
+    pub normalization: NormalizationType,
+    /// The metric's individual parameters.
+    #[serde(default)]
+    pub params: serde_yaml::Value,
+}
+
+/// Compiles configuration parameters for the metrics to evaluate.
+/// This is usually read from a config file and comes in one of two formats:
+/// - a mapping from registered metric names to their [`WeightedParams`] (each metric at most
+///   once, added in the order of their registration) or
+/// - a list of [`MetricConfig`]s (added in the given order), allowing for multiple instances of
+///   the same metric with different parameters, weights, and names.
+#[derive(Clone, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum MetricParameters {
+    List(Vec<serde_yaml::Value>),
+    Map(serde_yaml::Mapping),
+}
+
+impl MetricParameters {
+    /// The configured metric instances in the order in which they are added to the evaluator.
+    pub fn instances(&self, registry: &MetricRegistry) -> Result<Vec<MetricConfig>> {
+        match self {
+            Self::List(entries) => entries
+                .iter()
+                .enumerate()
+                .map(|(i, entry)| {
+                    parse_params(entry)
+                        .map_err(|e| anyhow!("Invalid config of metric no. {}: {}", i + 1, e))
+                })
+                .collect(),
+            Self::Map(entries) => {
+                entries
+                    .keys()
+                    .filter_map(|key| key.as_str())
+                    .filter(|key| !registry.contains(key))
+                    .for_each(|key| log::warn!("Ignoring unknown metric '{}'", key));
+
+                registry
+                    .names()
+                    .filter_map(|name| entries.get(name).map(|entry| (name, entry)))
+                    .map(|(name, entry)| {
+                        let p: WeightedParams<serde_yaml::Value> = parse_params(entry)
+                            .map_err(|e| anyhow!("Invalid config of metric '{}': {}", name, e))?;
+                        Ok(MetricConfig {
+                            metric: name.to_string(),
+                            name: None,
+                            enabled: p.enabled,
+                            weight: p.weight,
+                            normalization: p.normalization,
+                            params: p.params,
+                        })
+                    })
+                    .collect()
+            }
+        }
+    }
 }
 
 /// The [`Evaluator`] object is responsible for evaluating multiple metrics with respect to given ngram data.
@@ -91,6 +135,11 @@ pub struct Evaluator {
     bigram_metrics: Vec<(f64, NormalizationType, Box<dyn BigramMetric>)>,
     trigram_metrics: Vec<(f64, NormalizationType, Box<dyn TrigramMetric>)>,
     ngram_mapper: Box<dyn NgramMapper>,
+    corpora: Vec<(String, f64, Box<dyn NgramMapper>)>,
+    aggregation: AggregationConfig,
+    corpus_combination: CombinationMethod,
+    keyboards: Vec<(String, f64, Box<dyn LayoutGenerator>, Evaluator)>,
+    keyboard_combination: CombinationMethod,
 }
 
 impl Evaluator {
@@ -102,105 +151,112 @@ impl Evaluator {
             bigram_metrics: Vec::new(),
             trigram_metrics: Vec::new(),
             ngram_mapper,
+            corpora: Vec::new(),
+            aggregation: AggregationConfig::default(),
+            corpus_combination: CombinationMethod::default(),
+            keyboards: Vec::new(),
+            keyboard_combination: CombinationMethod::default(),
         }
     }
 
-    /// Add all "default" metrics to the evaluator.
-    pub fn default_metrics(mut self, params: &MetricParameters) -> Self {
-        macro_rules! add_metric {
-            ($metric_type:ident, $metric_name:ident, $metric_struct:ident) => {
-                if let Some(p) = &params.$metric_name {
-                    if p.enabled {
-                        self.$metric_type(
-                            Box::new($metric_name::$metric_struct::new(&p.params)),
-                            p.weight,
-                            p.normalization.clone(),
-                        );
-                    }
-                }
-            };
-            ($metric_type:ident, $metric_name:ident, $metric_struct:ident, "add_bigram_metrics") => {
-                if let Some(p) = &params.$metric_name {
-                    if p.enabled {
-                        self.$metric_type(
-                            Box::new($metric_name::$metric_struct::new(
-                                self.bigram_metrics.clone(),
-                                &p.params,
-                            )),
-                            p.weight,
-                            p.normalization.clone(),
-                        );
-                    }
-                }
-            };
+    /// Set how the metric costs are aggregated to the total cost (weighted sum by default).
+    pub fn with_aggregation(mut self, aggregation: &AggregationConfig) -> Self {
+        self.aggregation = aggregation.clone();
+        self
+    }
+
+    /// Set how the total costs of several corpora are combined (weighted sum by default).
+    pub fn with_corpus_combination(mut self, corpus_combination: CombinationMethod) -> Self {
+        self.corpus_combination = corpus_combination;
+        self
+    }
+
+    /// Set how the total costs of several keyboards are combined (weighted sum by default).
+    pub fn with_keyboard_combination(mut self, keyboard_combination: CombinationMethod) -> Self {
+        self.keyboard_combination = keyboard_combination;
+        self
+    }
+
+    /// Add all configured metrics to the evaluator using the metrics of this crate.
+    /// Panics if the configuration is invalid (see [`Evaluator::with_metrics`]).
+    pub fn default_metrics(self, params: &MetricParameters) -> Self {
+        self.with_metrics(params, &MetricRegistry::default())
+            .unwrap_or_else(|e| panic!("Could not add metrics: {}", e))
+    }
+
+    /// Add all enabled metrics of the configuration to the evaluator. The metrics are
+    /// constructed by name using the given registry.
+    pub fn with_metrics(
+        mut self,
+        params: &MetricParameters,
+        registry: &MetricRegistry,
+    ) -> Result<Self> {
+        for config in params
+            .instances(registry)?
+            .into_iter()
+            .filter(|c| c.enabled)
+        {
+            let mut metric =
+                registry.construct(&config.metric, &config.params, &self.bigram_metrics)?;
+            if let Some(name) = &config.name {
+                metric = metric.renamed(name);
+            }
+            self.add_metric(metric, config.weight, config.normalization);
         }
 
-        // layout metrics
-        add_metric!(layout_metric, shortcut_keys, ShortcutKeys);
-        add_metric!(layout_metric, similar_letters, SimilarLetters);
-        add_metric!(layout_metric, similar_letter_groups, SimilarLetterGroups);
-
-        // unigram metrics
-        add_metric!(unigram_metric, finger_balance, FingerBalance);
-        add_metric!(unigram_metric, hand_disbalance, HandDisbalance);
-        add_metric!(unigram_metric, row_loads, RowLoads);
-        add_metric!(unigram_metric, modifier_usage, ModifierUsage);
-        add_metric!(unigram_metric, key_costs, KeyCost);
-
-        // bigram metrics
-        add_metric!(bigram_metric, finger_repeats, FingerRepeats);
-        add_metric!(bigram_metric, manual_bigram_penalty, ManualBigramPenalty);
-        add_metric!(bigram_metric, movement_pattern, MovementPattern);
-        add_metric!(
-            bigram_metric,
-            no_handswitch_after_unbalancing_key,
-            NoHandSwitchAfterUnbalancingKey
-        );
-        add_metric!(bigram_metric, symmetric_handswitches, SymmetricHandswitches);
+        Ok(self)
+    }
 
-        // trigram_metrics
-        add_metric!(
-            trigram_metric,
-            no_handswitch_in_trigram,
-            NoHandswitchInTrigram
-        );
-        add_metric!(trigram_metric, trigram_finger_repeats, TrigramFingerRepeats);
-        add_metric!(trigram_metric, trigram_rolls, TrigramRolls);
-        add_metric!(
-            trigram_metric,
-            irregularity,
-            Irregularity,
-            "add_bigram_metrics"
-        );
-        add_metric!(
-            trigram_metric,
-            secondary_bigrams,
-            SecondaryBigrams,
-            "add_bigram_metrics"
-        );
+    /// Replace the ngram mapper providing the ngram data for the metrics.
+    pub fn set_ngram_mapper(&mut self, ngram_mapper: Box<dyn NgramMapper>) {
+        self.ngram_mapper = ngram_mapper;
+    }
 
-        add_metric!(layout_metric, kla_same_finger_words, KLASameFingerWords);
-        add_metric!(layout_metric, kla_home_key_words, KLAHomeKeyWords);
+    /// Add a named corpus with its weight and the ngram mapper providing its ngrams. As soon as
+    /// a corpus has been added, layouts are evaluated on all added corpora instead of the
+    /// evaluator's own ngram mapper.
+    pub fn add_corpus(&mut self, name: &str, weight: f64, ngram_mapper: Box<dyn NgramMapper>) {
+        self.corpora.push((name.to_string(), weight, ngram_mapper));
+    }
 
-        add_metric!(bigram_metric, kla_distance, KLADistance);
-        add_metric!(bigram_metric, kla_finger_usage, KLAFingerUsage);
-        add_metric!(bigram_metric, kla_same_finger, KLASameFinger);
-        add_metric!(bigram_metric, kla_same_hand, KLASameHand);
+    /// Add a named keyboard with its weight, a layout generator for it, and an evaluator. As soon
+    /// as a keyboard has been added, layouts are evaluated on all added keyboards instead of with
+    /// the evaluator's own metrics. For that, each keyboard's layout is generated from the
+    /// layout string of the layout to evaluate (see [`Layout::as_text`]). Fails if the layout
+    /// generator can not generate the base layout of the first added keyboard.
+    pub fn add_keyboard(
+        &mut self,
+        name: &str,
+        weight: f64,
+        layout_generator: Box<dyn LayoutGenerator>,
+        evaluator: Evaluator,
+    ) -> Result<()> {
+        if let Some((first_name, _, first_generator, _)) = self.keyboards.first() {
+            let layout_str = first_generator.base_layout_string();
+            layout_generator.generate(&layout_str).map_err(|e| {
+                anyhow!(
+                    "Keyboard '{}' can not generate layouts of keyboard '{}': {}",
+                    name,
+                    first_name,
+                    e
+                )
+            })?;
+        }
 
-        add_metric!(trigram_metric, oxey_combined_trigram, OxeyCombinedTrigram);
+        self.keyboards
+            .push((name.to_string(), weight, layout_generator, evaluator));
 
-        add_metric!(bigram_metric, oxey_sfbs, OxeySfbs);
-        add_metric!(bigram_metric, oxey_lsbs, OxeyLsbs);
-        add_metric!(trigram_metric, oxey_dsfbs, OxeyDsfbs);
-        add_metric!(trigram_metric, oxey_inward_rolls, OxeyInwardRolls);
-        add_metric!(trigram_metric, oxey_outward_rolls, OxeyOutwardRolls);
-        add_metric!(trigram_metric, oxey_onehands, OxeyOnehands);
-        add_metric!(trigram_metric, oxey_alternates, OxeyAlternates);
-        add_metric!(trigram_metric, oxey_alternates_sfs, OxeyAlternatesSfs);
-        add_metric!(trigram_metric, oxey_redirects, OxeyRedirects);
-        add_metric!(trigram_metric, oxey_bad_redirects, OxeyBadRedirects);
+        Ok(())
+    }
 
-        self
+    /// Add a metric of any type.
+    pub fn add_metric(&mut self, metric: Metric, weight: f64, normalization: NormalizationType) {
+        match metric {
+            Metric::Layout(metric) => self.layout_metric(metric, weight, normalization),
+            Metric::Unigram(metric) => self.unigram_metric(metric, weight, normalization),
+            Metric::Bigram(metric) => self.bigram_metric(metric, weight, normalization),
+            Metric::Trigram(metric) => self.trigram_metric(metric, weight, normalization),
+        }
     }
 
     /// Add a metric that operates only on the layout itself ("layout metric").
@@ -354,8 +410,80 @@ impl Evaluator {
         metric_costs
     }
 
+    /// Map the unigrams to the keys of the layout (using the evaluator of the first keyboard or
+    /// the first corpus if several keyboards or corpora have been added).
+    pub fn map_unigrams<'s>(&self, layout: &'s Layout) -> MappedUnigrams<'s> {
+        if let Some((_, _, _, evaluator)) = self.keyboards.first() {
+            return evaluator.map_unigrams(layout);
+        }
+
+        match self.corpora.first() {
+            Some((_, _, ngram_mapper)) => ngram_mapper.map_unigrams(layout),
+            None => self.ngram_mapper.map_unigrams(layout),
+        }
+    }
+
     /// Evaluate all metrics for a layout.
     pub fn evaluate_layout(&self, layout: &Layout) -> EvaluationResult {
+        if !self.keyboards.is_empty() {
+            return self.evaluate_on_keyboards(layout);
+        }
+
+        if self.corpora.is_empty() {
+            let results = self.evaluate_metrics(layout, self.ngram_mapper.as_ref());
+            return EvaluationResult::new(layout.as_text(), results)
+                .with_aggregation(self.aggregation.clone());
+        }
+
+        let corpora = self
+            .corpora
+            .iter()
+            .map(|(name, weight, ngram_mapper)| PartialResult {
+                name: name.clone(),
+                weight: *weight,
+                result: EvaluationResult::new(
+                    layout.as_text(),
+                    self.evaluate_metrics(layout, ngram_mapper.as_ref()),
+                )
+                .with_aggregation(self.aggregation.clone()),
+            })
+            .collect();
+
+        EvaluationResult::from_corpora(layout.as_text(), corpora, self.corpus_combination)
+            .with_aggregation(self.aggregation.clone())
+    }
+
+    /// Evaluate a layout on all added keyboards.
+    fn evaluate_on_keyboards(&self, layout: &Layout) -> EvaluationResult {
+        let layout_str = layout.as_text();
+        let keyboards = self
+            .keyboards
+            .iter()
+            .map(|(name, weight, layout_generator, evaluator)| {
+                // all keyboards accept the first keyboard's layouts (checked in `add_keyboard`)
+                let keyboard_layout = layout_generator.generate(&layout_str).unwrap_or_else(|e| {
+                    panic!(
+                        "Could not generate layout {} for keyboard '{}': {:?}",
+                        layout_str, name, e
+                    )
+                });
+                PartialResult {
+                    name: name.clone(),
+                    weight: *weight,
+                    result: evaluator.evaluate_layout(&keyboard_layout),
+                }
+            })
+            .collect();
+
+        EvaluationResult::from_keyboards(layout_str, keyboards, self.keyboard_combination)
+    }
+
+    /// Evaluate all metrics for a layout using the ngrams of the given ngram mapper.
+    fn evaluate_metrics(
+        &self,
+        layout: &Layout,
+        ngram_mapper: &dyn NgramMapper,
+    ) -> Vec<MetricResults> {
         let mut results: Vec<MetricResults> = Vec::new();
 
         // Layout metrics
@@ -370,7 +498,7 @@ impl Evaluator {
 
         // Unigram metrics
         if !self.unigram_metrics.is_empty() {
-            let mapped_unigrams = self.ngram_mapper.map_unigrams(layout);
+            let mapped_unigrams = ngram_mapper.map_unigrams(layout);
             let metric_costs = self.evaluate_unigram_metrics(layout, &mapped_unigrams.grams);
             let mut unigram_costs = MetricResults::new(
                 MetricType::Unigram,
@@ -386,7 +514,7 @@ impl Evaluator {
 
         // Bigram metrics
         if !self.bigram_metrics.is_empty() {
-            let mapped_bigrams = self.ngram_mapper.map_bigrams(layout);
+            let mapped_bigrams = ngram_mapper.map_bigrams(layout);
             let metric_costs = self.evaluate_bigram_metrics(layout, &mapped_bigrams.grams);
             let mut bigram_costs = MetricResults::new(
                 MetricType::Bigram,
@@ -402,7 +530,7 @@ impl Evaluator {
 
         // Trigram metrics
         if !self.trigram_metrics.is_empty() {
-            let mapped_trigrams = self.ngram_mapper.map_trigrams(layout);
+            let mapped_trigrams = ngram_mapper.map_trigrams(layout);
             let metric_costs = self.evaluate_trigram_metrics(layout, &mapped_trigrams.grams);
             let mut trigram_costs = MetricResults::new(
                 MetricType::Trigram,
@@ -416,6 +544,71 @@ impl Evaluator {
             results.push(trigram_costs);
         }
 
-        EvaluationResult::new(layout.as_text(), results)
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EvaluationParameters;
+    use crate::ngram_mapper::on_demand_ngram_mapper::OnDemandNgramMapper;
+    use crate::ngrams::{Bigrams, Trigrams, Unigrams};
+
+    use keyboard_layout::{
+        config::LayoutConfig, keyboard::Keyboard, neo_layout_generator::NeoLayoutGenerator,
+    };
+    use std::sync::Arc;
+
+    fn layout_generator(layout_config: &str) -> Box<dyn LayoutGenerator> {
+        let layout_config = LayoutConfig::from_yaml(layout_config).unwrap();
+        let keyboard = Arc::new(Keyboard::from_yaml_object(layout_config.keyboard));
+        Box::new(NeoLayoutGenerator::from_object(
+            layout_config.base_layout,
+            keyboard,
+        ))
+    }
+
+    fn evaluator() -> Evaluator {
+        let eval_params =
+            EvaluationParameters::from_yaml("../config/evaluation/default.yml").unwrap();
+        let ngram_mapper = OnDemandNgramMapper::with_ngrams(
+            Unigrams::from_text("").unwrap(),
+            Bigrams::from_text("").unwrap(),
+            Trigrams::from_text("").unwrap(),
+            eval_params.ngram_mapper,
+        );
+        Evaluator::default(Box::new(ngram_mapper))
+    }
+
+    #[test]
+    fn incompatible_keyboards_are_rejected() {
+        let mut evaluator = evaluator();
+        evaluator
+            .add_keyboard(
+                "standard",
+                1.0,
+                layout_generator("../config/keyboard/standard.yml"),
+                self::evaluator(),
+            )
+            .unwrap();
+        evaluator
+            .add_keyboard(
+                "ortho",
+                1.0,
+                layout_generator("../config/keyboard/ortho.yml"),
+                self::evaluator(),
+            )
+            .unwrap();
+
+        assert!(evaluator
+            .add_keyboard(
+                "qwerty",
+                1.0,
+                layout_generator("../config/keyboard/standard_qwerty_ansi.yml"),
+                self::evaluator(),
+            )
+            .is_err());
+        assert_eq!(evaluator.keyboards.len(), 2);
     }
 }
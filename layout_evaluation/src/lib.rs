@@ -1,3 +1,4 @@
+pub mod aggregation;
 pub mod cache;
 pub mod config;
 pub mod evaluation;
@@ -2,5 +2,9 @@
 
 pub mod bigram_metrics;
 pub mod layout_metrics;
+pub mod registry;
+#[cfg(feature = "script")]
+pub mod script;
+pub mod table;
 pub mod trigram_metrics;
 pub mod unigram_metrics;
@@ -5,6 +5,9 @@ use ordered_float::OrderedFloat;
 use priority_queue::DoublePriorityQueue;
 use std::{env, fmt};
 
+#[cfg(feature = "script")]
+pub mod bigram_script;
+pub mod bigram_table;
 pub mod finger_repeats;
 pub mod kla_distance;
 pub mod kla_finger_usage;
@@ -16,6 +19,7 @@ pub mod no_handswitch_after_unbalancing_key;
 pub mod oxey_lsbs;
 pub mod oxey_sfbs;
 pub mod symmetric_handswitches;
+pub mod typing_speed;
 
 /// BigramMetric is a trait for metrics that iterates over weighted bigrams.
 pub trait BigramMetric: Send + Sync + BigramMetricClone + fmt::Debug {
@@ -0,0 +1,70 @@
+//! The bigram metric [`BigramScript`] computes the cost of each bigram with a user-provided
+//! script (see the [`script`](crate::metrics::script) module for the interface). The script's
+//! result is multiplied with the bigram's weight.
+//!
+//! Example (penalize same-hand bigrams by the vertical distance of the keys):
+//! ```yaml
+//! name: Vertical Distance
+//! script: |
+//!   fn cost(k1, k2) {
+//!     if k1.hand != k2.hand || k1.is_modifier || k2.is_modifier { return (); }
+//!     (k1.y - k2.y).abs()
+//!   }
+//! ```
+
+use super::BigramMetric;
+use crate::metrics::script::{CostScript, ScriptKey, ScriptSource};
+
+use keyboard_layout::layout::{LayerKey, Layout};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+fn default_name() -> String {
+    "Bigram Script".to_string()
+}
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct Parameters {
+    /// Name of the metric in the evaluation results
+    #[serde(default = "default_name")]
+    pub name: String,
+    /// The script (`script`) or the path to a script file (`script_file`)
+    #[serde(flatten)]
+    pub source: ScriptSource,
+}
+
+#[derive(Clone, Debug)]
+pub struct BigramScript {
+    name: String,
+    script: CostScript,
+}
+
+impl BigramScript {
+    pub fn new(params: &Parameters) -> Result<Self> {
+        Ok(Self {
+            name: params.name.clone(),
+            script: CostScript::compile(&params.name, &params.source, 2)?,
+        })
+    }
+}
+
+impl BigramMetric for BigramScript {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline(always)]
+    fn individual_cost(
+        &self,
+        k1: &LayerKey,
+        k2: &LayerKey,
+        weight: f64,
+        _total_weight: f64,
+        _layout: &Layout,
+    ) -> Option<f64> {
+        self.script
+            .cost((ScriptKey::from(k1), ScriptKey::from(k2)))
+            .map(|cost| weight * cost)
+    }
+}
@@ -0,0 +1,123 @@
+//! The bigram metric [`BigramTable`] assigns costs to bigrams according to a table of rules
+//! specified in the evaluation config. Each rule matches the two keys by hand, finger, row, and
+//! column and optionally requires both keys to be on the same hand or finger and a (signed or
+//! absolute) row difference. The cost of the first matching rule is multiplied with the
+//! bigram's weight.
+//!
+//! Example (penalize jumps over the home row with the same hand):
+//! ```yaml
+//! name: Row Jumps
+//! table:
+//!   - same_hand: true
+//!     abs_row_delta: [2, 3]
+//!     cost: 1.0
+//! ```
+
+use super::BigramMetric;
+use crate::metrics::table::{is_modifier, matches, KeyPattern, Matcher};
+
+use keyboard_layout::layout::{LayerKey, Layout};
+
+use serde::Deserialize;
+
+fn default_name() -> String {
+    "Bigram Table".to_string()
+}
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Properties of the first key
+    #[serde(default)]
+    pub key1: KeyPattern,
+    /// Properties of the second key
+    #[serde(default)]
+    pub key2: KeyPattern,
+    /// Whether both keys need to be on the same (or on different) hands
+    #[serde(default)]
+    pub same_hand: Option<bool>,
+    /// Whether both keys need to be typed with the same (or with different) fingers
+    #[serde(default)]
+    pub same_finger: Option<bool>,
+    /// Row of the second key minus the row of the first key
+    #[serde(default)]
+    pub row_delta: Option<Matcher<i16>>,
+    /// Absolute difference of the keys' rows
+    #[serde(default)]
+    pub abs_row_delta: Option<Matcher<i16>>,
+    /// Cost per bigram weight
+    pub cost: f64,
+}
+
+impl Rule {
+    fn matches(&self, k1: &LayerKey, k2: &LayerKey) -> bool {
+        let same_hand = k1.key.hand == k2.key.hand;
+        let same_finger = same_hand && k1.key.finger == k2.key.finger;
+        let row_delta = k2.key.matrix_position.1 as i16 - k1.key.matrix_position.1 as i16;
+
+        self.same_hand.map_or(true, |s| s == same_hand)
+            && self.same_finger.map_or(true, |s| s == same_finger)
+            && matches(&self.row_delta, &row_delta)
+            && matches(&self.abs_row_delta, &row_delta.abs())
+            && self.key1.matches(k1)
+            && self.key2.matches(k2)
+    }
+}
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Parameters {
+    /// Name of the metric in the evaluation results
+    #[serde(default = "default_name")]
+    pub name: String,
+    /// Do not assign costs to bigrams containing modifier keys
+    #[serde(default)]
+    pub ignore_modifiers: bool,
+    /// Rules in order of precedence
+    pub table: Vec<Rule>,
+}
+
+#[derive(Clone, Debug)]
+pub struct BigramTable {
+    name: String,
+    ignore_modifiers: bool,
+    table: Vec<Rule>,
+}
+
+impl BigramTable {
+    pub fn new(params: &Parameters) -> Self {
+        Self {
+            name: params.name.clone(),
+            ignore_modifiers: params.ignore_modifiers,
+            table: params.table.clone(),
+        }
+    }
+}
+
+impl BigramMetric for BigramTable {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline(always)]
+    fn individual_cost(
+        &self,
+        k1: &LayerKey,
+        k2: &LayerKey,
+        weight: f64,
+        _total_weight: f64,
+        _layout: &Layout,
+    ) -> Option<f64> {
+        if self.ignore_modifiers && (is_modifier(k1) || is_modifier(k2)) {
+            return Some(0.0);
+        }
+
+        let cost = self
+            .table
+            .iter()
+            .find(|rule| rule.matches(k1, k2))
+            .map_or(0.0, |rule| rule.cost);
+
+        Some(weight * cost)
+    }
+}
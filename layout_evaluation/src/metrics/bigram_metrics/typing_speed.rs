@@ -0,0 +1,194 @@
+//! The bigram metric [`TypingSpeed`] predicts the time between two consecutive keystrokes
+//! using a model based on Fitts's law. The movement time to a key grows with the logarithm
+//! of the distance the finger has to travel (in units of the key width):
+//!
+//! `time = base_time + distance_time * log2(1 + distance / key_width)`
+//!
+//! If both keys are hit by the same finger, the finger needs to travel from the first to
+//! the second key and an additional `same_finger_penalty` is added. Otherwise, the finger
+//! is assumed to start from its resting position. Bigrams with a hand alternation
+//! are multiplied with a `hand_alternation_factor` (usually < 1.0) as the second hand
+//! can prepare its keystroke while the first one is still in action.
+//!
+//! The cost is the total predicted time (in milliseconds) weighted by the bigram frequencies.
+//! The message contains the mean inter-key time and the resulting words per minute.
+
+use super::BigramMetric;
+
+use ahash::AHashMap;
+use keyboard_layout::{
+    key::{Finger, FingerMap},
+    layout::{LayerKey, Layout},
+};
+
+use serde::Deserialize;
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct Parameters {
+    /// Time (in ms) required for a keystroke without any movement
+    pub base_time: f64,
+    /// Time (in ms) per bit of the Fitts's law index of difficulty
+    pub distance_time: f64,
+    /// The width of a key (in units of the keyboard's key positions)
+    pub key_width: f64,
+    /// Factor multiplied to the time of a finger (e.g. the pinky is slower than the index)
+    pub finger_factors: AHashMap<Finger, f64>,
+    /// Factor multiplied to the time of a bigram with hand alternation
+    pub hand_alternation_factor: f64,
+    /// Additional time (in ms) if both keys are hit by the same finger (but not the same key)
+    pub same_finger_penalty: f64,
+    /// Additional time (in ms) if the same key is hit twice
+    pub same_key_penalty: f64,
+    /// Number of keystrokes that count as one word for the words per minute estimation
+    pub chars_per_word: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct TypingSpeed {
+    base_time: f64,
+    distance_time: f64,
+    key_width: f64,
+    finger_factors: FingerMap<f64>,
+    hand_alternation_factor: f64,
+    same_finger_penalty: f64,
+    same_key_penalty: f64,
+    chars_per_word: f64,
+}
+
+impl TypingSpeed {
+    pub fn new(params: &Parameters) -> Self {
+        Self {
+            base_time: params.base_time,
+            distance_time: params.distance_time,
+            key_width: params.key_width,
+            finger_factors: FingerMap::with_hashmap(&params.finger_factors, 1.0),
+            hand_alternation_factor: params.hand_alternation_factor,
+            same_finger_penalty: params.same_finger_penalty,
+            same_key_penalty: params.same_key_penalty,
+            chars_per_word: params.chars_per_word,
+        }
+    }
+
+    /// Movement time according to Fitts's law for a given distance.
+    #[inline(always)]
+    fn movement_time(&self, distance: f64) -> f64 {
+        self.base_time + self.distance_time * (1.0 + distance / self.key_width).log2()
+    }
+
+    /// Predict the time (in ms) between hitting `k1` and hitting `k2`.
+    #[inline(always)]
+    fn bigram_time(&self, k1: &LayerKey, k2: &LayerKey, layout: &Layout) -> f64 {
+        let finger_factor = self.finger_factors.get(&k2.key.finger);
+
+        if k1.key.hand == k2.key.hand && k1.key.finger == k2.key.finger {
+            let distance = k1.key.position.distance(&k2.key.position);
+            let penalty = if k1.key == k2.key {
+                self.same_key_penalty
+            } else {
+                self.same_finger_penalty
+            };
+
+            return finger_factor * self.movement_time(distance) + penalty;
+        }
+
+        let resting_position = layout
+            .keyboard
+            .finger_resting_positions
+            .get(&k2.key.hand, &k2.key.finger);
+        let distance = resting_position.distance(&k2.key.position);
+        let time = finger_factor * self.movement_time(distance);
+
+        if k1.key.hand != k2.key.hand {
+            self.hand_alternation_factor * time
+        } else {
+            time
+        }
+    }
+}
+
+impl BigramMetric for TypingSpeed {
+    fn name(&self) -> &str {
+        "Typing Speed"
+    }
+
+    fn total_cost(
+        &self,
+        bigrams: &[((&LayerKey, &LayerKey), f64)],
+        _total_weight: Option<f64>,
+        layout: &Layout,
+    ) -> (f64, Option<String>) {
+        let (total_time, total_weight) = bigrams
+            .iter()
+            // holding a modifier does not involve a keystroke
+            .filter(|((k1, k2), _)| !(k1 == k2 && k1.is_modifier.is_some()))
+            .fold((0.0, 0.0), |(time, total_weight), ((k1, k2), weight)| {
+                (
+                    time + weight * self.bigram_time(k1, k2, layout),
+                    total_weight + weight,
+                )
+            });
+
+        if total_weight == 0.0 {
+            return (0.0, None);
+        }
+
+        let mean_time = total_time / total_weight;
+        let wpm = 60_000.0 / (mean_time * self.chars_per_word);
+
+        let message = format!(
+            "Mean time between keystrokes: {:.1}ms; Estimated speed: {:.1} WPM",
+            mean_time, wpm
+        );
+
+        (total_time, Some(message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use keyboard_layout::{
+        config::LayoutConfig, keyboard::Keyboard, layout_generator::LayoutGenerator,
+        neo_layout_generator::NeoLayoutGenerator,
+    };
+    use std::sync::Arc;
+
+    fn layout() -> Layout {
+        let layout_config = LayoutConfig::from_yaml("../config/keyboard/standard.yml").unwrap();
+        let keyboard = Arc::new(Keyboard::from_yaml_object(layout_config.keyboard));
+        NeoLayoutGenerator::from_object(layout_config.base_layout, keyboard)
+            .generate("xvlcwkhgfqyßuiaeosnrtdüöäpzbm,.j")
+            .unwrap()
+    }
+
+    fn metric() -> TypingSpeed {
+        TypingSpeed::new(&Parameters {
+            base_time: 100.0,
+            distance_time: 80.0,
+            key_width: 50.0,
+            finger_factors: AHashMap::default(),
+            hand_alternation_factor: 0.7,
+            same_finger_penalty: 80.0,
+            same_key_penalty: 30.0,
+            chars_per_word: 5.0,
+        })
+    }
+
+    #[test]
+    fn bigrams_without_keystrokes_cost_nothing() {
+        let layout = layout();
+        let metric = metric();
+        assert_eq!(metric.total_cost(&[], None, &layout), (0.0, None));
+
+        let modifier = layout
+            .layerkeys
+            .iter()
+            .find(|k| k.is_modifier.is_some())
+            .unwrap();
+        assert_eq!(
+            metric.total_cost(&[((modifier, modifier), 3.0)], None, &layout),
+            (0.0, None)
+        );
+    }
+}
@@ -0,0 +1,448 @@
+//! The `registry` module provides the [`MetricRegistry`] that constructs metrics by name from
+//! their (YAML) parameters.
+//!
+//! The default registry contains all metrics of this crate. Other crates can register their
+//! own metrics (or replace existing ones) before constructing an [`Evaluator`](crate::evaluation::Evaluator)
+//! with [`Evaluator::with_metrics`](crate::evaluation::Evaluator::with_metrics).
+
+use super::{bigram_metrics::*, layout_metrics::*, trigram_metrics::*, unigram_metrics::*};
+use crate::results::NormalizationType;
+
+use keyboard_layout::layout::{LayerKey, Layout};
+
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use std::{fmt, sync::Arc};
+
+/// A constructed metric of one of the four metric types.
+#[derive(Clone, Debug)]
+pub enum Metric {
+    Layout(Box<dyn LayoutMetric>),
+    Unigram(Box<dyn UnigramMetric>),
+    Bigram(Box<dyn BigramMetric>),
+    Trigram(Box<dyn TrigramMetric>),
+}
+
+impl Metric {
+    /// Report the metric's results under the given name instead of its own one.
+    pub fn renamed(self, name: &str) -> Self {
+        let name = name.to_string();
+        match self {
+            Self::Layout(metric) => Self::Layout(Box::new(RenamedLayoutMetric { name, metric })),
+            Self::Unigram(metric) => Self::Unigram(Box::new(RenamedUnigramMetric { name, metric })),
+            Self::Bigram(metric) => Self::Bigram(Box::new(RenamedBigramMetric { name, metric })),
+            Self::Trigram(metric) => Self::Trigram(Box::new(RenamedTrigramMetric { name, metric })),
+        }
+    }
+}
+
+/// Bigram metrics (with their weights and normalizations) that have been added to the evaluator
+/// before the metric to construct. Some trigram metrics (e.g. irregularity) build upon them.
+pub type BigramMetrics = [(f64, NormalizationType, Box<dyn BigramMetric>)];
+
+/// Function constructing a metric from its parameters.
+pub type MetricConstructor =
+    Arc<dyn Fn(&serde_yaml::Value, &BigramMetrics) -> Result<Metric> + Send + Sync>;
+
+/// Deserialize a metric's parameters (or any other config given as YAML value, e.g. modified
+/// evaluation parameters). Missing parameters are interpreted as an empty mapping.
+pub fn parse_params<P: DeserializeOwned>(params: &serde_yaml::Value) -> Result<P> {
+    let params = match params {
+        serde_yaml::Value::Null => serde_yaml::Value::Mapping(serde_yaml::Mapping::new()),
+        params => params.clone(),
+    };
+
+    // deserializing from a string (instead of directly from the value) supports
+    // the `null: null` parameters of metrics without parameters
+    let params_str = serde_yaml::to_string(&params)?;
+    Ok(serde_yaml::from_str(&params_str)?)
+}
+
+/// A collection of metric constructors, identified by name.
+#[derive(Clone)]
+pub struct MetricRegistry {
+    constructors: Vec<(String, MetricConstructor)>,
+}
+
+impl fmt::Debug for MetricRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+impl Default for MetricRegistry {
+    /// A registry containing all metrics of this crate.
+    fn default() -> Self {
+        let mut registry = Self::empty();
+
+        macro_rules! register {
+            ($register_fn:ident, $metric_name:ident, $metric_struct:ident) => {
+                registry.$register_fn(stringify!($metric_name), $metric_name::$metric_struct::new);
+            };
+        }
+
+        // the order of registration determines the order in which metrics given in the
+        // mapping format of the config are added to the evaluator
+
+        // layout metrics
+        register!(register_layout_metric, shortcut_keys, ShortcutKeys);
+        register!(register_layout_metric, similar_letters, SimilarLetters);
+        register!(
+            register_layout_metric,
+            similar_letter_groups,
+            SimilarLetterGroups
+        );
+
+        // unigram metrics
+        register!(register_unigram_metric, finger_balance, FingerBalance);
+        register!(register_unigram_metric, hand_disbalance, HandDisbalance);
+        register!(register_unigram_metric, row_loads, RowLoads);
+        register!(register_unigram_metric, modifier_usage, ModifierUsage);
+        register!(register_unigram_metric, key_costs, KeyCost);
+
+        // bigram metrics
+        register!(register_bigram_metric, finger_repeats, FingerRepeats);
+        register!(
+            register_bigram_metric,
+            manual_bigram_penalty,
+            ManualBigramPenalty
+        );
+        register!(register_bigram_metric, movement_pattern, MovementPattern);
+        register!(
+            register_bigram_metric,
+            no_handswitch_after_unbalancing_key,
+            NoHandSwitchAfterUnbalancingKey
+        );
+        register!(
+            register_bigram_metric,
+            symmetric_handswitches,
+            SymmetricHandswitches
+        );
+        register!(register_bigram_metric, typing_speed, TypingSpeed);
+
+        // trigram_metrics
+        register!(
+            register_trigram_metric,
+            no_handswitch_in_trigram,
+            NoHandswitchInTrigram
+        );
+        register!(
+            register_trigram_metric,
+            trigram_finger_repeats,
+            TrigramFingerRepeats
+        );
+        register!(register_trigram_metric, trigram_rolls, TrigramRolls);
+        registry.register("irregularity", |params, bigram_metrics| {
+            Ok(Metric::Trigram(Box::new(irregularity::Irregularity::new(
+                bigram_metrics.to_vec(),
+                &parse_params(params)?,
+            ))))
+        });
+        registry.register("secondary_bigrams", |params, bigram_metrics| {
+            Ok(Metric::Trigram(Box::new(
+                secondary_bigrams::SecondaryBigrams::new(
+                    bigram_metrics.to_vec(),
+                    &parse_params(params)?,
+                ),
+            )))
+        });
+
+        register!(
+            register_layout_metric,
+            kla_same_finger_words,
+            KLASameFingerWords
+        );
+        register!(register_layout_metric, kla_home_key_words, KLAHomeKeyWords);
+
+        register!(register_bigram_metric, kla_distance, KLADistance);
+        register!(register_bigram_metric, kla_finger_usage, KLAFingerUsage);
+        register!(register_bigram_metric, kla_same_finger, KLASameFinger);
+        register!(register_bigram_metric, kla_same_hand, KLASameHand);
+
+        register!(
+            register_trigram_metric,
+            oxey_combined_trigram,
+            OxeyCombinedTrigram
+        );
+
+        register!(register_bigram_metric, oxey_sfbs, OxeySfbs);
+        register!(register_bigram_metric, oxey_lsbs, OxeyLsbs);
+        register!(register_trigram_metric, oxey_dsfbs, OxeyDsfbs);
+        register!(register_trigram_metric, oxey_inward_rolls, OxeyInwardRolls);
+        register!(
+            register_trigram_metric,
+            oxey_outward_rolls,
+            OxeyOutwardRolls
+        );
+        register!(register_trigram_metric, oxey_onehands, OxeyOnehands);
+        register!(register_trigram_metric, oxey_alternates, OxeyAlternates);
+        register!(
+            register_trigram_metric,
+            oxey_alternates_sfs,
+            OxeyAlternatesSfs
+        );
+        register!(register_trigram_metric, oxey_redirects, OxeyRedirects);
+        register!(
+            register_trigram_metric,
+            oxey_bad_redirects,
+            OxeyBadRedirects
+        );
+
+        // table-driven metrics
+        register!(register_unigram_metric, unigram_table, UnigramTable);
+        register!(register_bigram_metric, bigram_table, BigramTable);
+        register!(register_trigram_metric, trigram_table, TrigramTable);
+
+        // scriptable metrics
+        #[cfg(feature = "script")]
+        {
+            registry.register("unigram_script", |params, _| {
+                Ok(Metric::Unigram(Box::new(
+                    unigram_script::UnigramScript::new(&parse_params(params)?)?,
+                )))
+            });
+            registry.register("bigram_script", |params, _| {
+                Ok(Metric::Bigram(Box::new(bigram_script::BigramScript::new(
+                    &parse_params(params)?,
+                )?)))
+            });
+            registry.register("trigram_script", |params, _| {
+                Ok(Metric::Trigram(Box::new(
+                    trigram_script::TrigramScript::new(&parse_params(params)?)?,
+                )))
+            });
+        }
+        // without the `script` feature, they are known (such that disabled ones can be configured)
+        // but can not be constructed
+        #[cfg(not(feature = "script"))]
+        for name in ["unigram_script", "bigram_script", "trigram_script"] {
+            registry.register(name, move |_, _| {
+                Err(anyhow!(
+                    "Metric '{}' requires the 'script' feature of layout_evaluation",
+                    name
+                ))
+            });
+        }
+
+        registry
+    }
+}
+
+impl MetricRegistry {
+    /// Generate a registry without any metric.
+    pub fn empty() -> Self {
+        Self {
+            constructors: Vec::new(),
+        }
+    }
+
+    /// Register a metric constructor under the given name. An existing constructor with the
+    /// same name is replaced.
+    pub fn register<F>(&mut self, name: &str, constructor: F)
+    where
+        F: Fn(&serde_yaml::Value, &BigramMetrics) -> Result<Metric> + Send + Sync + 'static,
+    {
+        let constructor: MetricConstructor = Arc::new(constructor);
+        match self.constructors.iter_mut().find(|(n, _)| n == name) {
+            Some((_, c)) => *c = constructor,
+            None => self.constructors.push((name.to_string(), constructor)),
+        }
+    }
+
+    /// Register a layout metric that is constructed from its deserialized parameters.
+    pub fn register_layout_metric<P, M, F>(&mut self, name: &str, new: F)
+    where
+        P: DeserializeOwned,
+        M: LayoutMetric + 'static,
+        F: Fn(&P) -> M + Send + Sync + 'static,
+    {
+        self.register(name, move |params, _| {
+            Ok(Metric::Layout(Box::new(new(&parse_params(params)?))))
+        });
+    }
+
+    /// Register a unigram metric that is constructed from its deserialized parameters.
+    pub fn register_unigram_metric<P, M, F>(&mut self, name: &str, new: F)
+    where
+        P: DeserializeOwned,
+        M: UnigramMetric + 'static,
+        F: Fn(&P) -> M + Send + Sync + 'static,
+    {
+        self.register(name, move |params, _| {
+            Ok(Metric::Unigram(Box::new(new(&parse_params(params)?))))
+        });
+    }
+
+    /// Register a bigram metric that is constructed from its deserialized parameters.
+    pub fn register_bigram_metric<P, M, F>(&mut self, name: &str, new: F)
+    where
+        P: DeserializeOwned,
+        M: BigramMetric + 'static,
+        F: Fn(&P) -> M + Send + Sync + 'static,
+    {
+        self.register(name, move |params, _| {
+            Ok(Metric::Bigram(Box::new(new(&parse_params(params)?))))
+        });
+    }
+
+    /// Register a trigram metric that is constructed from its deserialized parameters.
+    pub fn register_trigram_metric<P, M, F>(&mut self, name: &str, new: F)
+    where
+        P: DeserializeOwned,
+        M: TrigramMetric + 'static,
+        F: Fn(&P) -> M + Send + Sync + 'static,
+    {
+        self.register(name, move |params, _| {
+            Ok(Metric::Trigram(Box::new(new(&parse_params(params)?))))
+        });
+    }
+
+    /// Names of all registered metrics in the order of their registration.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.constructors.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Whether a metric with the given name is registered.
+    pub fn contains(&self, name: &str) -> bool {
+        self.constructors.iter().any(|(n, _)| n == name)
+    }
+
+    /// Construct the metric registered under the given name.
+    pub fn construct(
+        &self,
+        name: &str,
+        params: &serde_yaml::Value,
+        bigram_metrics: &BigramMetrics,
+    ) -> Result<Metric> {
+        let (_, constructor) = self
+            .constructors
+            .iter()
+            .find(|(n, _)| n == name)
+            .ok_or_else(|| anyhow!("Unknown metric '{}'", name))?;
+
+        constructor(params, bigram_metrics)
+            .map_err(|e| anyhow!("Invalid parameters for metric '{}': {}", name, e))
+    }
+}
+
+/// A layout metric reporting its results under a different name.
+#[derive(Clone, Debug)]
+struct RenamedLayoutMetric {
+    name: String,
+    metric: Box<dyn LayoutMetric>,
+}
+
+impl LayoutMetric for RenamedLayoutMetric {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn total_cost(&self, layout: &Layout) -> (f64, Option<String>) {
+        self.metric.total_cost(layout)
+    }
+}
+
+/// A unigram metric reporting its results under a different name.
+#[derive(Clone, Debug)]
+struct RenamedUnigramMetric {
+    name: String,
+    metric: Box<dyn UnigramMetric>,
+}
+
+impl UnigramMetric for RenamedUnigramMetric {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline(always)]
+    fn individual_cost(
+        &self,
+        key1: &LayerKey,
+        weight: f64,
+        total_weight: f64,
+        layout: &Layout,
+    ) -> Option<f64> {
+        self.metric
+            .individual_cost(key1, weight, total_weight, layout)
+    }
+
+    fn total_cost(
+        &self,
+        unigrams: &[(&LayerKey, f64)],
+        total_weight: Option<f64>,
+        layout: &Layout,
+    ) -> (f64, Option<String>) {
+        self.metric.total_cost(unigrams, total_weight, layout)
+    }
+}
+
+/// A bigram metric reporting its results under a different name.
+#[derive(Clone, Debug)]
+struct RenamedBigramMetric {
+    name: String,
+    metric: Box<dyn BigramMetric>,
+}
+
+impl BigramMetric for RenamedBigramMetric {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline(always)]
+    fn individual_cost(
+        &self,
+        key1: &LayerKey,
+        key2: &LayerKey,
+        weight: f64,
+        total_weight: f64,
+        layout: &Layout,
+    ) -> Option<f64> {
+        self.metric
+            .individual_cost(key1, key2, weight, total_weight, layout)
+    }
+
+    fn total_cost(
+        &self,
+        bigrams: &[((&LayerKey, &LayerKey), f64)],
+        total_weight: Option<f64>,
+        layout: &Layout,
+    ) -> (f64, Option<String>) {
+        self.metric.total_cost(bigrams, total_weight, layout)
+    }
+}
+
+/// A trigram metric reporting its results under a different name.
+#[derive(Clone, Debug)]
+struct RenamedTrigramMetric {
+    name: String,
+    metric: Box<dyn TrigramMetric>,
+}
+
+impl TrigramMetric for RenamedTrigramMetric {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline(always)]
+    fn individual_cost(
+        &self,
+        key1: &LayerKey,
+        key2: &LayerKey,
+        key3: &LayerKey,
+        weight: f64,
+        total_weight: f64,
+        layout: &Layout,
+    ) -> Option<f64> {
+        self.metric
+            .individual_cost(key1, key2, key3, weight, total_weight, layout)
+    }
+
+    fn total_cost(
+        &self,
+        trigrams: &[((&LayerKey, &LayerKey, &LayerKey), f64)],
+        total_weight: Option<f64>,
+        layout: &Layout,
+    ) -> (f64, Option<String>) {
+        self.metric.total_cost(trigrams, total_weight, layout)
+    }
+}
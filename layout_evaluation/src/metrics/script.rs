@@ -0,0 +1,264 @@
+//! The `script` module provides the building blocks of the scriptable metrics
+//! ([`UnigramScript`](super::unigram_metrics::unigram_script::UnigramScript),
+//! [`BigramScript`](super::bigram_metrics::bigram_script::BigramScript), and
+//! [`TrigramScript`](super::trigram_metrics::trigram_script::TrigramScript)). These metrics
+//! compute the cost of each ngram with a function `cost` written in the
+//! [Rhai](https://rhai.rs) scripting language.
+//!
+//! The function receives one argument per key of the ngram with the (read-only) properties
+//! `symbol`, `layer`, `hand` (`"Left"`, `"Right"`), `finger` (`"Thumb"`, `"Index"`, `"Middle"`,
+//! `"Ring"`, `"Pinky"`), `column`, `row`, `x`, `y`, `cost`, `is_fixed`, and `is_modifier`. It
+//! returns the cost per ngram weight (a number) or `()` if the ngram shall not be considered.
+//!
+//! Scripts are compiled once when the metric is constructed. Only the functions of a script are
+//! kept, top-level statements are not executed. The `cost` function is called once with sample
+//! keys at construction, such that errors in the script are reported right away. If it fails
+//! later on (for other keys), the error is logged and the ngram is counted with zero cost.
+
+use keyboard_layout::{
+    key::{Finger, Hand},
+    layout::LayerKey,
+};
+
+use anyhow::{anyhow, Result};
+use rhai::{Dynamic, Engine, FuncArgs, Scope, AST, FLOAT, INT};
+use serde::Deserialize;
+use std::{
+    fs,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// Name of the function that scripts need to define.
+const COST_FUNCTION: &str = "cost";
+
+/// The key (an index finger on the home row) the `cost` function is called with when the script
+/// is compiled.
+const SAMPLE_KEY: ScriptKey = ScriptKey {
+    symbol: 'a',
+    layer: 0,
+    hand: Hand::Left,
+    finger: Finger::Index,
+    column: 5,
+    row: 2,
+    x: 264.5,
+    y: 125.5,
+    cost: 3.0,
+    is_fixed: false,
+    is_modifier: false,
+};
+
+/// Source of a script, either given inline (`script`) or as path to a file (`script_file`).
+#[derive(Clone, Deserialize, Debug)]
+pub enum ScriptSource {
+    #[serde(rename = "script")]
+    Inline(String),
+    #[serde(rename = "script_file")]
+    File(String),
+}
+
+impl ScriptSource {
+    fn read(&self) -> Result<String> {
+        match self {
+            Self::Inline(script) => Ok(script.clone()),
+            Self::File(filename) => fs::read_to_string(filename)
+                .map_err(|e| anyhow!("Could not read script file '{}': {}", filename, e)),
+        }
+    }
+}
+
+/// The properties of a [`LayerKey`] that are exposed to scripts.
+#[derive(Clone, Copy, Debug)]
+pub struct ScriptKey {
+    symbol: char,
+    layer: u8,
+    hand: Hand,
+    finger: Finger,
+    column: u8,
+    row: u8,
+    x: f64,
+    y: f64,
+    cost: f64,
+    is_fixed: bool,
+    is_modifier: bool,
+}
+
+impl From<&LayerKey> for ScriptKey {
+    #[inline(always)]
+    fn from(key: &LayerKey) -> Self {
+        Self {
+            symbol: key.symbol,
+            layer: key.layer,
+            hand: key.key.hand,
+            finger: key.key.finger,
+            column: key.key.matrix_position.0,
+            row: key.key.matrix_position.1,
+            x: key.key.position.0,
+            y: key.key.position.1,
+            cost: key.key.cost,
+            is_fixed: key.is_fixed,
+            is_modifier: key.is_modifier.is_some(),
+        }
+    }
+}
+
+fn hand_name(hand: Hand) -> &'static str {
+    match hand {
+        Hand::Left => "Left",
+        Hand::Right => "Right",
+    }
+}
+
+fn finger_name(finger: Finger) -> &'static str {
+    match finger {
+        Finger::Thumb => "Thumb",
+        Finger::Index => "Index",
+        Finger::Middle => "Middle",
+        Finger::Ring => "Ring",
+        Finger::Pinky => "Pinky",
+    }
+}
+
+/// A script engine knowing the [`ScriptKey`] type.
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .register_type_with_name::<ScriptKey>("Key")
+        .register_get("symbol", |k: &mut ScriptKey| k.symbol)
+        .register_get("layer", |k: &mut ScriptKey| k.layer as INT)
+        .register_get("hand", |k: &mut ScriptKey| hand_name(k.hand).to_string())
+        .register_get("finger", |k: &mut ScriptKey| {
+            finger_name(k.finger).to_string()
+        })
+        .register_get("column", |k: &mut ScriptKey| k.column as INT)
+        .register_get("row", |k: &mut ScriptKey| k.row as INT)
+        .register_get("x", |k: &mut ScriptKey| k.x as FLOAT)
+        .register_get("y", |k: &mut ScriptKey| k.y as FLOAT)
+        .register_get("cost", |k: &mut ScriptKey| k.cost as FLOAT)
+        .register_get("is_fixed", |k: &mut ScriptKey| k.is_fixed)
+        .register_get("is_modifier", |k: &mut ScriptKey| k.is_modifier);
+
+    engine
+}
+
+/// A precompiled `cost` function of a script.
+#[derive(Clone, Debug)]
+pub struct CostScript {
+    name: String,
+    engine: Arc<Engine>,
+    ast: AST,
+    /// Whether the script failed during an evaluation (such that the error is logged only once)
+    failed: Arc<AtomicBool>,
+}
+
+impl CostScript {
+    /// Compile the script of the metric `name` and check that it defines a `cost` function with
+    /// the given number of parameters that returns a number (or `()`) for sample keys.
+    pub fn compile(name: &str, source: &ScriptSource, n_params: usize) -> Result<Self> {
+        let engine = engine();
+        let ast = engine
+            .compile(&source.read()?)
+            .map_err(|e| anyhow!("Could not compile the script of metric '{}': {}", name, e))?
+            .clone_functions_only();
+
+        if !ast
+            .iter_functions()
+            .any(|f| f.name == COST_FUNCTION && f.params.len() == n_params)
+        {
+            return Err(anyhow!(
+                "The script of metric '{}' does not define a function '{}' with {} parameter(s)",
+                name,
+                COST_FUNCTION,
+                n_params
+            ));
+        }
+
+        let script = Self {
+            name: name.to_string(),
+            engine: Arc::new(engine),
+            ast,
+            failed: Arc::new(AtomicBool::new(false)),
+        };
+        script.call(vec![SAMPLE_KEY; n_params])?;
+
+        Ok(script)
+    }
+
+    /// Call the script's `cost` function. Returns `None` if it returns `()`.
+    ///
+    /// If the script fails or returns something other than a number, the error is logged (once)
+    /// and the cost is zero.
+    #[inline(always)]
+    pub fn cost(&self, args: impl FuncArgs) -> Option<f64> {
+        self.call(args).unwrap_or_else(|e| {
+            if !self.failed.swap(true, Ordering::Relaxed) {
+                log::error!("{} Costs of failing ngrams are set to zero.", e);
+            }
+            Some(0.0)
+        })
+    }
+
+    fn call(&self, args: impl FuncArgs) -> Result<Option<f64>> {
+        let result: Dynamic = self
+            .engine
+            .call_fn(&mut Scope::new(), &self.ast, COST_FUNCTION, args)
+            .map_err(|e| anyhow!("Error in the script of metric '{}': {}", self.name, e))?;
+
+        if result.is_unit() {
+            Ok(None)
+        } else if let Ok(cost) = result.as_float() {
+            Ok(Some(cost))
+        } else if let Ok(cost) = result.as_int() {
+            Ok(Some(cost as f64))
+        } else {
+            Err(anyhow!(
+                "The script of metric '{}' returned a {} instead of a number",
+                self.name,
+                result.type_name()
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(script: &str, n_params: usize) -> Result<CostScript> {
+        CostScript::compile("Test", &ScriptSource::Inline(script.to_string()), n_params)
+    }
+
+    #[test]
+    fn valid_scripts_are_compiled() {
+        let script = compile(
+            "fn cost(k1, k2) { if k1.hand == k2.hand { 1 } else { () } }",
+            2,
+        );
+        assert_eq!(script.unwrap().cost((SAMPLE_KEY, SAMPLE_KEY)), Some(1.0));
+    }
+
+    #[test]
+    fn failing_scripts_are_rejected() {
+        // wrong number of parameters
+        assert!(compile("fn cost(key) { 1.0 }", 2).is_err());
+        // unknown property
+        assert!(compile("fn cost(key) { key.colum * 1.0 }", 1).is_err());
+        // no number
+        assert!(compile("fn cost(key) { key.hand }", 1).is_err());
+    }
+
+    #[test]
+    fn later_failures_count_as_zero_cost() {
+        let script = compile(
+            "fn cost(key) { if key.row == 2 { 1.0 } else { key.hand } }",
+            1,
+        );
+        let other_key = ScriptKey {
+            row: 1,
+            ..SAMPLE_KEY
+        };
+        assert_eq!(script.unwrap().cost((other_key,)), Some(0.0));
+    }
+}
@@ -0,0 +1,213 @@
+//! The `table` module provides the building blocks of the table-driven metrics
+//! ([`UnigramTable`](super::unigram_metrics::unigram_table::UnigramTable),
+//! [`BigramTable`](super::bigram_metrics::bigram_table::BigramTable), and
+//! [`TrigramTable`](super::trigram_metrics::trigram_table::TrigramTable)). These metrics
+//! assign costs to ngrams according to rules specified in the evaluation config, such that
+//! simple metrics can be prototyped without writing code.
+
+use keyboard_layout::{
+    key::{Finger, Hand},
+    layout::LayerKey,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Matches either a single value or any value of a list.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(untagged)]
+pub enum Matcher<T> {
+    One(T),
+    Any(Vec<T>),
+}
+
+impl<T: PartialEq> Matcher<T> {
+    pub fn matches(&self, value: &T) -> bool {
+        match self {
+            Self::One(v) => v == value,
+            Self::Any(values) => values.contains(value),
+        }
+    }
+}
+
+impl<T> Matcher<T> {
+    /// Whether the predicate holds for the value or for any value of the list.
+    pub fn any<F: Fn(&T) -> bool>(&self, f: F) -> bool {
+        match self {
+            Self::One(v) => f(v),
+            Self::Any(values) => values.iter().any(f),
+        }
+    }
+}
+
+/// Match an optional matcher. A missing matcher matches any value.
+#[inline(always)]
+pub fn matches<T: PartialEq>(matcher: &Option<Matcher<T>>, value: &T) -> bool {
+    matcher.as_ref().map_or(true, |m| m.matches(value))
+}
+
+/// Properties of a key that a rule can match.
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct KeyPattern {
+    /// Hand(s) of the key
+    #[serde(default)]
+    pub hand: Option<Matcher<Hand>>,
+    /// Finger(s) of the key
+    #[serde(default)]
+    pub finger: Option<Matcher<Finger>>,
+    /// Row(s) of the key (as in the matrix positions of the keyboard config)
+    #[serde(default)]
+    pub row: Option<Matcher<u8>>,
+    /// Column(s) of the key (as in the matrix positions of the keyboard config)
+    #[serde(default)]
+    pub column: Option<Matcher<u8>>,
+}
+
+impl KeyPattern {
+    pub fn matches(&self, key: &LayerKey) -> bool {
+        matches(&self.hand, &key.key.hand)
+            && matches(&self.finger, &key.key.finger)
+            && matches(&self.row, &key.key.matrix_position.1)
+            && matches(&self.column, &key.key.matrix_position.0)
+    }
+}
+
+/// Whether the key's symbol is a modifier (these appear in ngrams if modifiers are split off).
+#[inline(always)]
+pub fn is_modifier(key: &LayerKey) -> bool {
+    key.is_modifier.is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::{
+        bigram_metrics::{bigram_table, BigramMetric},
+        trigram_metrics::{trigram_table, TrigramMetric},
+        unigram_metrics::{unigram_table, UnigramMetric},
+    };
+
+    use keyboard_layout::{
+        config::LayoutConfig, keyboard::Keyboard, layout::Layout,
+        layout_generator::LayoutGenerator, neo_layout_generator::NeoLayoutGenerator,
+    };
+    use std::sync::Arc;
+
+    const LAYOUT: &str = "xvlcwkhgfqyßuiaeosnrtdüöäpzbm,.j";
+
+    fn layout() -> Layout {
+        let layout_config = LayoutConfig::from_yaml("../config/keyboard/standard.yml").unwrap();
+        let keyboard = Arc::new(Keyboard::from_yaml_object(layout_config.keyboard));
+        NeoLayoutGenerator::from_object(layout_config.base_layout, keyboard)
+            .generate(LAYOUT)
+            .unwrap()
+    }
+
+    /// The key of the base layer generating the symbol.
+    fn key(layout: &Layout, c: char) -> &super::LayerKey {
+        layout
+            .layerkeys
+            .iter()
+            .find(|k| k.layer == 0 && k.symbol == c)
+            .unwrap()
+    }
+
+    #[test]
+    fn unigram_rules_match_in_order() {
+        let params: unigram_table::Parameters = serde_yaml::from_str(
+            "
+table:
+  - key: {finger: Pinky, row: 2}
+    cost: 0.0
+  - key: {finger: Pinky}
+    cost: 1.0
+",
+        )
+        .unwrap();
+        let metric = unigram_table::UnigramTable::new(&params);
+        let layout = layout();
+        let cost = |c| metric.individual_cost(key(&layout, c), 2.0, 10.0, &layout);
+
+        assert_eq!(cost('u'), Some(0.0));
+        assert_eq!(cost('x'), Some(2.0));
+        assert_eq!(cost('e'), Some(0.0));
+    }
+
+    #[test]
+    fn bigram_rules_match_both_keys() {
+        let params: bigram_table::Parameters = serde_yaml::from_str(
+            "
+table:
+  - key1: {finger: Index}
+    key2: {finger: Index}
+    same_hand: true
+    abs_row_delta: [2, 3]
+    cost: 1.0
+",
+        )
+        .unwrap();
+        let metric = bigram_table::BigramTable::new(&params);
+        let layout = layout();
+        let cost =
+            |c1, c2| metric.individual_cost(key(&layout, c1), key(&layout, c2), 1.0, 10.0, &layout);
+
+        assert_eq!(cost('c', 'p'), Some(1.0));
+        assert_eq!(cost('p', 'c'), Some(1.0));
+        assert_eq!(cost('c', 'e'), Some(0.0));
+        assert_eq!(cost('c', 'm'), Some(0.0));
+    }
+
+    #[test]
+    fn trigram_rules_match_hand_patterns() {
+        let params: trigram_table::Parameters = serde_yaml::from_str(
+            "
+table:
+  - hands: [LLL, RRR]
+    key1: {finger: Pinky}
+    key3: {finger: Pinky}
+    cost: 1.0
+",
+        )
+        .unwrap();
+        let metric = trigram_table::TrigramTable::new(&params);
+        let layout = layout();
+        let cost = |c1, c2, c3| {
+            metric.individual_cost(
+                key(&layout, c1),
+                key(&layout, c2),
+                key(&layout, c3),
+                1.0,
+                10.0,
+                &layout,
+            )
+        };
+
+        assert_eq!(cost('x', 'e', 'u'), Some(1.0));
+        assert_eq!(cost('x', 'n', 'u'), Some(0.0));
+        assert_eq!(cost('x', 'e', 'a'), Some(0.0));
+    }
+
+    #[test]
+    fn misspelled_keys_are_rejected() {
+        assert!(serde_yaml::from_str::<unigram_table::Parameters>(
+            "table: [{key: {fingr: Pinky}, cost: 1.0}]"
+        )
+        .is_err());
+        // key properties need to be given in `key`
+        assert!(serde_yaml::from_str::<unigram_table::Parameters>(
+            "table: [{finger: Pinky, cost: 1.0}]"
+        )
+        .is_err());
+        assert!(serde_yaml::from_str::<bigram_table::Parameters>(
+            "table: [{key1: {finger: Index}, same_hnd: true, cost: 1.0}]"
+        )
+        .is_err());
+        assert!(serde_yaml::from_str::<trigram_table::Parameters>(
+            "table: [{key3: {rows: 2}, cost: 1.0}]"
+        )
+        .is_err());
+        assert!(serde_yaml::from_str::<trigram_table::Parameters>(
+            "ignore_modifier: true\ntable: []"
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,75 @@
+//! The trigram metric [`TrigramScript`] computes the cost of each trigram with a user-provided
+//! script (see the [`script`](crate::metrics::script) module for the interface). The script's
+//! result is multiplied with the trigram's weight.
+//!
+//! Example (penalize redirects, i.e. one-handed trigrams changing direction):
+//! ```yaml
+//! name: Redirects
+//! script: |
+//!   fn cost(k1, k2, k3) {
+//!     if k1.hand != k2.hand || k2.hand != k3.hand { return 0.0; }
+//!     if (k2.x - k1.x) * (k3.x - k2.x) < 0.0 { 1.0 } else { 0.0 }
+//!   }
+//! ```
+
+use super::TrigramMetric;
+use crate::metrics::script::{CostScript, ScriptKey, ScriptSource};
+
+use keyboard_layout::layout::{LayerKey, Layout};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+fn default_name() -> String {
+    "Trigram Script".to_string()
+}
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct Parameters {
+    /// Name of the metric in the evaluation results
+    #[serde(default = "default_name")]
+    pub name: String,
+    /// The script (`script`) or the path to a script file (`script_file`)
+    #[serde(flatten)]
+    pub source: ScriptSource,
+}
+
+#[derive(Clone, Debug)]
+pub struct TrigramScript {
+    name: String,
+    script: CostScript,
+}
+
+impl TrigramScript {
+    pub fn new(params: &Parameters) -> Result<Self> {
+        Ok(Self {
+            name: params.name.clone(),
+            script: CostScript::compile(&params.name, &params.source, 3)?,
+        })
+    }
+}
+
+impl TrigramMetric for TrigramScript {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline(always)]
+    fn individual_cost(
+        &self,
+        k1: &LayerKey,
+        k2: &LayerKey,
+        k3: &LayerKey,
+        weight: f64,
+        _total_weight: f64,
+        _layout: &Layout,
+    ) -> Option<f64> {
+        self.script
+            .cost((
+                ScriptKey::from(k1),
+                ScriptKey::from(k2),
+                ScriptKey::from(k3),
+            ))
+            .map(|cost| weight * cost)
+    }
+}
@@ -0,0 +1,128 @@
+//! The trigram metric [`TrigramTable`] assigns costs to trigrams according to a table of rules
+//! specified in the evaluation config. Each rule matches the three keys by hand, finger, row, and
+//! column and optionally by a hand pattern, e.g. `LRL` for a left-right-left sequence (`.` matches
+//! any hand). The cost of the first matching rule is multiplied with the trigram's weight.
+//!
+//! Example (penalize one-handed trigrams starting and ending with the pinky):
+//! ```yaml
+//! name: Pinky Sandwiches
+//! table:
+//!   - hands: [LLL, RRR]
+//!     key1:
+//!       finger: Pinky
+//!     key3:
+//!       finger: Pinky
+//!     cost: 1.0
+//! ```
+
+use super::TrigramMetric;
+use crate::metrics::table::{is_modifier, KeyPattern, Matcher};
+
+use keyboard_layout::{
+    key::Hand,
+    layout::{LayerKey, Layout},
+};
+
+use serde::Deserialize;
+
+fn default_name() -> String {
+    "Trigram Table".to_string()
+}
+
+/// Whether the hands of the three keys match a pattern like `LRL` (`.` matches any hand).
+fn hand_pattern_matches(pattern: &str, keys: [&LayerKey; 3]) -> bool {
+    pattern.chars().count() == 3
+        && pattern.chars().zip(keys.iter()).all(|(c, k)| match c {
+            'L' | 'l' => k.key.hand == Hand::Left,
+            'R' | 'r' => k.key.hand == Hand::Right,
+            _ => true,
+        })
+}
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Properties of the first key
+    #[serde(default)]
+    pub key1: KeyPattern,
+    /// Properties of the second key
+    #[serde(default)]
+    pub key2: KeyPattern,
+    /// Properties of the third key
+    #[serde(default)]
+    pub key3: KeyPattern,
+    /// Hand pattern(s) such as `LRL` or `RR.`
+    #[serde(default)]
+    pub hands: Option<Matcher<String>>,
+    /// Cost per trigram weight
+    pub cost: f64,
+}
+
+impl Rule {
+    fn matches(&self, k1: &LayerKey, k2: &LayerKey, k3: &LayerKey) -> bool {
+        self.hands.as_ref().map_or(true, |hands| {
+            hands.any(|pattern| hand_pattern_matches(pattern, [k1, k2, k3]))
+        }) && self.key1.matches(k1)
+            && self.key2.matches(k2)
+            && self.key3.matches(k3)
+    }
+}
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Parameters {
+    /// Name of the metric in the evaluation results
+    #[serde(default = "default_name")]
+    pub name: String,
+    /// Do not assign costs to trigrams containing modifier keys
+    #[serde(default)]
+    pub ignore_modifiers: bool,
+    /// Rules in order of precedence
+    pub table: Vec<Rule>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TrigramTable {
+    name: String,
+    ignore_modifiers: bool,
+    table: Vec<Rule>,
+}
+
+impl TrigramTable {
+    pub fn new(params: &Parameters) -> Self {
+        Self {
+            name: params.name.clone(),
+            ignore_modifiers: params.ignore_modifiers,
+            table: params.table.clone(),
+        }
+    }
+}
+
+impl TrigramMetric for TrigramTable {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline(always)]
+    fn individual_cost(
+        &self,
+        k1: &LayerKey,
+        k2: &LayerKey,
+        k3: &LayerKey,
+        weight: f64,
+        _total_weight: f64,
+        _layout: &Layout,
+    ) -> Option<f64> {
+        if self.ignore_modifiers && (is_modifier(k1) || is_modifier(k2) || is_modifier(k3)) {
+            return Some(0.0);
+        }
+
+        let cost = self
+            .table
+            .iter()
+            .find(|rule| rule.matches(k1, k2, k3))
+            .map_or(0.0, |rule| rule.cost);
+
+        Some(weight * cost)
+    }
+}
@@ -10,6 +10,9 @@ pub mod hand_disbalance;
 pub mod key_costs;
 pub mod modifier_usage;
 pub mod row_loads;
+#[cfg(feature = "script")]
+pub mod unigram_script;
+pub mod unigram_table;
 
 /// UnigramMetric is a trait for metrics that iterate over weighted unigrams.
 pub trait UnigramMetric: Send + Sync + UnigramMetricClone + fmt::Debug {
@@ -0,0 +1,68 @@
+//! The unigram metric [`UnigramScript`] computes the cost of each key with a user-provided
+//! script (see the [`script`](crate::metrics::script) module for the interface). The script's
+//! result is multiplied with the key's weight.
+//!
+//! Example (penalize pinky keys outside the home row):
+//! ```yaml
+//! name: Pinky off Home Row
+//! script: |
+//!   fn cost(key) {
+//!     if key.finger == "Pinky" && key.row != 2 { 1.0 } else { 0.0 }
+//!   }
+//! ```
+
+use super::UnigramMetric;
+use crate::metrics::script::{CostScript, ScriptKey, ScriptSource};
+
+use keyboard_layout::layout::{LayerKey, Layout};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+fn default_name() -> String {
+    "Unigram Script".to_string()
+}
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct Parameters {
+    /// Name of the metric in the evaluation results
+    #[serde(default = "default_name")]
+    pub name: String,
+    /// The script (`script`) or the path to a script file (`script_file`)
+    #[serde(flatten)]
+    pub source: ScriptSource,
+}
+
+#[derive(Clone, Debug)]
+pub struct UnigramScript {
+    name: String,
+    script: CostScript,
+}
+
+impl UnigramScript {
+    pub fn new(params: &Parameters) -> Result<Self> {
+        Ok(Self {
+            name: params.name.clone(),
+            script: CostScript::compile(&params.name, &params.source, 1)?,
+        })
+    }
+}
+
+impl UnigramMetric for UnigramScript {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline(always)]
+    fn individual_cost(
+        &self,
+        key: &LayerKey,
+        weight: f64,
+        _total_weight: f64,
+        _layout: &Layout,
+    ) -> Option<f64> {
+        self.script
+            .cost((ScriptKey::from(key),))
+            .map(|cost| weight * cost)
+    }
+}
@@ -0,0 +1,94 @@
+//! The unigram metric [`UnigramTable`] assigns costs to unigrams according to a table of rules
+//! specified in the evaluation config. Each rule matches keys by hand, finger, row, and column.
+//! The cost of the first matching rule is multiplied with the unigram's weight.
+//!
+//! Example (penalize pinky keys outside the home row):
+//! ```yaml
+//! name: Pinky off Home Row
+//! table:
+//!   - key:
+//!       finger: Pinky
+//!       row: 2
+//!     cost: 0.0
+//!   - key:
+//!       finger: Pinky
+//!     cost: 1.0
+//! ```
+
+use super::UnigramMetric;
+use crate::metrics::table::{is_modifier, KeyPattern};
+
+use keyboard_layout::layout::{LayerKey, Layout};
+
+use serde::Deserialize;
+
+fn default_name() -> String {
+    "Unigram Table".to_string()
+}
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Key properties the rule applies to
+    #[serde(default)]
+    pub key: KeyPattern,
+    /// Cost per unigram weight
+    pub cost: f64,
+}
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Parameters {
+    /// Name of the metric in the evaluation results
+    #[serde(default = "default_name")]
+    pub name: String,
+    /// Do not assign costs to modifier keys
+    #[serde(default)]
+    pub ignore_modifiers: bool,
+    /// Rules in order of precedence
+    pub table: Vec<Rule>,
+}
+
+#[derive(Clone, Debug)]
+pub struct UnigramTable {
+    name: String,
+    ignore_modifiers: bool,
+    table: Vec<Rule>,
+}
+
+impl UnigramTable {
+    pub fn new(params: &Parameters) -> Self {
+        Self {
+            name: params.name.clone(),
+            ignore_modifiers: params.ignore_modifiers,
+            table: params.table.clone(),
+        }
+    }
+}
+
+impl UnigramMetric for UnigramTable {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline(always)]
+    fn individual_cost(
+        &self,
+        key: &LayerKey,
+        weight: f64,
+        _total_weight: f64,
+        _layout: &Layout,
+    ) -> Option<f64> {
+        if self.ignore_modifiers && is_modifier(key) {
+            return Some(0.0);
+        }
+
+        let cost = self
+            .table
+            .iter()
+            .find(|rule| rule.key.matches(key))
+            .map_or(0.0, |rule| rule.cost);
+
+        Some(weight * cost)
+    }
+}
@@ -1,5 +1,7 @@
 //! The `results` module contains structs representing the results of metric evaluations.
 
+use crate::aggregation::{AggregationConfig, CombinationMethod};
+
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
 use std::{fmt, slice};
@@ -155,28 +157,163 @@ impl MetricResults {
 
     /// Compute the weighted and normalized total cost of all metrics.
     pub fn total_cost(&self) -> f64 {
-        self.aggregate_metric_costs(true, true)
+        self.metric_costs
+            .iter()
+            .fold(0.0, |acc, metric_cost| acc + metric_cost.weighted_cost)
     }
 
     /// Compute the weighted but not normalized total cost of all metrics.
     pub fn unnormalized_total_cost(&self) -> f64 {
         self.aggregate_metric_costs(false, true)
     }
+
+    /// Combine results of the same metrics (e.g. evaluated on different corpora) given with
+    /// weights into their weighted mean. The found and not found weights are expressed as
+    /// (weighted mean) fractions of the total weights.
+    pub fn weighted_mean(results: &[(f64, &MetricResults)]) -> Self {
+        let weight_sum: f64 = results.iter().map(|(w, _)| w).sum();
+        let mean = |f: &dyn Fn(&MetricResults) -> f64| -> f64 {
+            results.iter().map(|(w, r)| w * f(r)).sum::<f64>() / weight_sum
+        };
+
+        let first = results[0].1;
+        let metric_costs = first
+            .metric_costs
+            .iter()
+            .enumerate()
+            .map(|(i, metric_cost)| NormalizedMetricResult {
+                core: MetricResult {
+                    name: metric_cost.core.name.clone(),
+                    cost: mean(&|r| r.metric_costs[i].core.cost),
+                    message: None,
+                    weight: metric_cost.core.weight,
+                    normalization: metric_cost.core.normalization.clone(),
+                },
+                weighted_cost: mean(&|r| r.metric_costs[i].weighted_cost),
+                unweighted_cost: mean(&|r| r.metric_costs[i].unweighted_cost),
+            })
+            .collect();
+
+        Self {
+            metric_type: first.metric_type.clone(),
+            found_weight: mean(&|r| r.found_weight / (r.found_weight + r.not_found_weight)),
+            not_found_weight: mean(&|r| r.not_found_weight / (r.found_weight + r.not_found_weight)),
+            metric_costs,
+        }
+    }
+}
+
+/// Describes the costs of an evaluation relative to those of a reference layout.
+/// The costs are scaled such that the reference layout's costs correspond to `reference_value`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RelativeEvaluationResult {
+    /// Name of the reference layout.
+    pub reference_name: String,
+    /// The value corresponding to the reference layout's costs.
+    pub reference_value: f64,
+    /// The relative costs of the individual metrics (`None` if the reference layout has no costs).
+    pub metric_costs: Vec<(String, Option<f64>)>,
+    /// The relative total cost.
+    pub total_cost: f64,
+}
+
+impl fmt::Display for RelativeEvaluationResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = format!(
+            "Relative to {} (= {}):",
+            self.reference_name, self.reference_value
+        )
+        .bold();
+        writeln!(f, "{}", header)?;
+
+        for (name, cost) in self.metric_costs.iter() {
+            let cost = match cost {
+                Some(c) => format!("{:>7.1}", c),
+                None => format!("{:>7}", "-"),
+            };
+            writeln!(f, "  {} {}", cost.green(), name.bold())?;
+        }
+
+        writeln!(
+            f,
+            "Relative cost: {}",
+            format!("{:.1}", self.total_cost).green().bold(),
+        )
+    }
+}
+
+/// Describes the evaluation of a layout on one of several corpora or keyboards.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PartialResult {
+    /// Name of the corpus or keyboard.
+    pub name: String,
+    /// Weight of the corpus or keyboard when combining the costs.
+    pub weight: f64,
+    /// The evaluation on the corpus or keyboard.
+    pub result: EvaluationResult,
+}
+
+/// Weighted means of the individual metric results of several partial results. Returns no
+/// results if the partial results do not consist of the same metrics.
+fn mean_individual_results(parts: &[PartialResult]) -> Vec<MetricResults> {
+    let metric_names = |r: &EvaluationResult| -> Vec<String> {
+        r.iter()
+            .flat_map(|results| results.metric_costs.iter())
+            .map(|metric_cost| metric_cost.core.name.clone())
+            .collect()
+    };
+
+    let first = match parts.first() {
+        Some(first) => first,
+        None => return Vec::new(),
+    };
+    if parts
+        .iter()
+        .any(|p| metric_names(&p.result) != metric_names(&first.result))
+    {
+        return Vec::new();
+    }
+
+    (0..first.result.individual_results.len())
+        .map(|i| {
+            let results: Vec<(f64, &MetricResults)> = parts
+                .iter()
+                .map(|p| (p.weight, &p.result.individual_results[i]))
+                .collect();
+            MetricResults::weighted_mean(&results)
+        })
+        .collect()
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct EvaluationResult {
     layout: String,
     individual_results: Vec<MetricResults>,
+    #[serde(default, skip_serializing_if = "AggregationConfig::is_weighted_sum")]
+    aggregation: AggregationConfig,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    corpora: Vec<PartialResult>,
+    #[serde(default, skip_serializing_if = "CombinationMethod::is_weighted_sum")]
+    corpus_combination: CombinationMethod,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    keyboards: Vec<PartialResult>,
+    #[serde(default, skip_serializing_if = "CombinationMethod::is_weighted_sum")]
+    keyboard_combination: CombinationMethod,
 }
 
 impl fmt::Display for EvaluationResult {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.individual_results
-            .iter()
-            .fold(Ok(()), |acc, results| {
-                acc.and_then(|_| writeln!(f, "{}", results))
-            })?;
+        if !self.keyboards.is_empty() {
+            self.fmt_parts(f, "Keyboards:", &self.keyboards, self.keyboard_combination)?;
+        } else if !self.corpora.is_empty() {
+            self.fmt_parts(f, "Corpora:", &self.corpora, self.corpus_combination)?;
+        } else {
+            self.individual_results
+                .iter()
+                .fold(Ok(()), |acc, results| {
+                    acc.and_then(|_| writeln!(f, "{}", results))
+                })?;
+        }
 
         writeln!(
             f,
@@ -194,17 +331,137 @@ impl EvaluationResult {
         Self {
             layout,
             individual_results,
+            aggregation: AggregationConfig::default(),
+            corpora: Vec::new(),
+            corpus_combination: CombinationMethod::default(),
+            keyboards: Vec::new(),
+            keyboard_combination: CombinationMethod::default(),
         }
     }
 
-    pub fn total_cost(&self) -> f64 {
-        let mut cost = 0.0;
-        self.individual_results
+    /// Combine the evaluations of a layout on several corpora. The individual metric results
+    /// are the weighted means of the corpora's results, the total cost is determined by the
+    /// `corpus_combination`.
+    pub fn from_corpora(
+        layout: String,
+        corpora: Vec<PartialResult>,
+        corpus_combination: CombinationMethod,
+    ) -> Self {
+        let mut result = Self::new(layout, mean_individual_results(&corpora));
+        result.corpora = corpora;
+        result.corpus_combination = corpus_combination;
+
+        result
+    }
+
+    /// Combine the evaluations of a layout on several keyboards. The individual metric results
+    /// are the weighted means of the keyboards' results (if they all use the same metrics), the
+    /// total cost is determined by the `keyboard_combination`.
+    pub fn from_keyboards(
+        layout: String,
+        keyboards: Vec<PartialResult>,
+        keyboard_combination: CombinationMethod,
+    ) -> Self {
+        let mut result = Self::new(layout, mean_individual_results(&keyboards));
+        result.keyboards = keyboards;
+        result.keyboard_combination = keyboard_combination;
+
+        result
+    }
+
+    /// The evaluations on the individual corpora (empty if only one corpus was used).
+    pub fn corpora(&self) -> &[PartialResult] {
+        &self.corpora
+    }
+
+    /// The evaluations on the individual keyboards (empty if only one keyboard was used).
+    pub fn keyboards(&self) -> &[PartialResult] {
+        &self.keyboards
+    }
+
+    /// Print the weighted metric costs of all partial results side by side.
+    fn fmt_parts(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        title: &str,
+        parts: &[PartialResult],
+        combination: CombinationMethod,
+    ) -> fmt::Result {
+        let header: String = parts
+            .iter()
+            .map(|p| format!(" {:>14.14}", p.name))
+            .collect();
+        writeln!(
+            f,
+            "{}",
+            format!("{:<37}{} | {:>10}", title, header, "Combined").bold()
+        )?;
+        let weights: String = parts
+            .iter()
+            .map(|p| format!(" {:>14.2}", p.weight))
+            .collect();
+        writeln!(f, "  {:<35}{} |", "Weight", weights)?;
+
+        for (i, results) in self.individual_results.iter().enumerate() {
+            for (j, metric_cost) in results.metric_costs.iter().enumerate() {
+                let costs: String = parts
+                    .iter()
+                    .map(|p| {
+                        format!(
+                            " {:>14.2}",
+                            p.result.individual_results[i].metric_costs[j].weighted_cost
+                        )
+                    })
+                    .collect();
+                writeln!(
+                    f,
+                    "  {}{} | {}",
+                    format!("{:<35}", metric_cost.core.name).bold(),
+                    costs,
+                    format!("{:>10.2}", metric_cost.weighted_cost).green(),
+                )?;
+            }
+        }
+
+        let totals: String = parts
             .iter()
-            .filter(|mc| !mc.metric_costs.is_empty())
-            .for_each(|mc| cost += mc.total_cost());
+            .map(|p| format!(" {:>14.2}", p.result.total_cost()))
+            .collect();
+        let combination = match combination {
+            CombinationMethod::WeightedSum => "Total (weighted sum)",
+            CombinationMethod::Worst => "Total (worst)",
+        };
+        writeln!(
+            f,
+            "  {}{} | {}\n",
+            format!("{:<35}", combination).bold(),
+            totals,
+            format!("{:>10.2}", self.total_cost()).green().bold(),
+        )
+    }
+
+    /// Use the given aggregation of metric costs for computing the total cost.
+    pub fn with_aggregation(mut self, aggregation: AggregationConfig) -> Self {
+        self.aggregation = aggregation;
+        self
+    }
 
-        cost
+    pub fn total_cost(&self) -> f64 {
+        let combined_cost = |parts: &[PartialResult], combination: CombinationMethod| -> f64 {
+            let costs: Vec<(f64, f64)> = parts
+                .iter()
+                .map(|p| (p.weight, p.result.total_cost()))
+                .collect();
+            combination.total_cost(&costs)
+        };
+
+        if !self.keyboards.is_empty() {
+            combined_cost(&self.keyboards, self.keyboard_combination)
+        } else if !self.corpora.is_empty() {
+            combined_cost(&self.corpora, self.corpus_combination)
+        } else {
+            self.aggregation.total_cost(&self.individual_results)
+        }
     }
 
     pub fn optimization_score(&self) -> usize {
@@ -214,4 +471,48 @@ impl EvaluationResult {
     pub fn iter(&self) -> slice::Iter<'_, MetricResults> {
         self.individual_results.iter()
     }
+
+    /// Express the costs relative to those of a reference layout's evaluation, such that the
+    /// reference layout's costs correspond to `reference_value`.
+    pub fn relative_to(
+        &self,
+        reference: &EvaluationResult,
+        reference_name: &str,
+        reference_value: f64,
+    ) -> RelativeEvaluationResult {
+        let relative = |cost: f64, reference_cost: f64| -> Option<f64> {
+            if reference_cost != 0.0 {
+                Some(reference_value * cost / reference_cost)
+            } else {
+                None
+            }
+        };
+
+        let metric_costs = self
+            .iter()
+            .zip(reference.iter())
+            .flat_map(|(results, reference_results)| {
+                results
+                    .metric_costs
+                    .iter()
+                    .zip(reference_results.metric_costs.iter())
+            })
+            .map(|(metric_cost, reference_metric_cost)| {
+                (
+                    metric_cost.core.name.clone(),
+                    relative(
+                        metric_cost.weighted_cost,
+                        reference_metric_cost.weighted_cost,
+                    ),
+                )
+            })
+            .collect();
+
+        RelativeEvaluationResult {
+            reference_name: reference_name.to_string(),
+            reference_value,
+            metric_costs,
+            total_cost: relative(self.total_cost(), reference.total_cost()).unwrap_or(f64::NAN),
+        }
+    }
 }
@@ -0,0 +1,55 @@
+//! Saving and loading the state of long-running optimizations.
+//!
+//! Checkpoints are written as YAML files. A checkpoint is first written to a temporary file that
+//! then replaces the previous checkpoint, so that a crash while writing does not destroy it.
+
+use anyhow::{anyhow, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{fs, path::Path};
+
+/// Where and how often to save checkpoints.
+#[derive(Clone, Debug)]
+pub struct Checkpointing {
+    /// File to write the checkpoints to
+    pub path: String,
+    /// Write a checkpoint every this many iterations (or generations)
+    pub every: u64,
+    /// Continue the optimization from the checkpoint in `path`
+    pub resume: bool,
+}
+
+/// Path of the checkpoint file of the `i`-th of several optimization processes, e.g.
+/// `checkpoint_3.yml` for `checkpoint.yml`.
+pub fn indexed_path(path: &str, i: usize) -> String {
+    let p = Path::new(path);
+    let stem = p
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let file_name = match p.extension() {
+        Some(ext) => format!("{}_{}.{}", stem, i, ext.to_string_lossy()),
+        None => format!("{}_{}", stem, i),
+    };
+
+    p.with_file_name(file_name).to_string_lossy().to_string()
+}
+
+/// Write a checkpoint to the given file.
+pub fn save<T: Serialize>(path: &str, data: &T) -> Result<()> {
+    let tmp_path = format!("{}.tmp", path);
+    let f = fs::File::create(&tmp_path)
+        .map_err(|e| anyhow!("Could not create checkpoint file '{}': {}", tmp_path, e))?;
+    serde_yaml::to_writer(f, data)?;
+    fs::rename(&tmp_path, path)
+        .map_err(|e| anyhow!("Could not write checkpoint file '{}': {}", path, e))?;
+
+    Ok(())
+}
+
+/// Read a checkpoint from the given file.
+pub fn load<T: DeserializeOwned>(path: &str) -> Result<T> {
+    let f = fs::File::open(path)
+        .map_err(|e| anyhow!("Could not open checkpoint file '{}': {}", path, e))?;
+    serde_yaml::from_reader(f)
+        .map_err(|e| anyhow!("Could not read checkpoint file '{}': {}", path, e))
+}
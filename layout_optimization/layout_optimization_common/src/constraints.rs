@@ -0,0 +1,431 @@
+//! The `constraints` module provides hard constraints on the placement of characters that
+//! layouts generated during an optimization have to satisfy. Constraints are given in the
+//! optimization configs, e.g.
+//! ```yaml
+//! constraints:
+//!   # vowels on the left hand
+//!   - chars: aeiou
+//!     hand: Left
+//!   # "x" on the home row
+//!   - chars: x
+//!     row: 2
+//!   # these characters within columns 1 to 5
+//!   - chars: qjz
+//!     column: [1, 2, 3, 4, 5]
+//!   # "," and "." on neighboring keys
+//!   - adjacent: ",."
+//! ```
+
+use keyboard_layout::{
+    key::{Finger, Hand},
+    layout::Layout,
+};
+use layout_evaluation::metrics::table::{KeyPattern, Matcher};
+
+use anyhow::{anyhow, Result};
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of steps of the backtracking search for a random valid permutation.
+const MAX_SEARCH_STEPS: usize = 1_000_000;
+
+/// A hard constraint on the placement of characters. Unknown fields are rejected, such that a
+/// misspelled property does not silently disable a constraint.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum Constraint {
+    /// Each of the characters needs to be placed on a key matching all given properties (at
+    /// least one is required).
+    Placement {
+        chars: String,
+        #[serde(default)]
+        hand: Option<Matcher<Hand>>,
+        #[serde(default)]
+        finger: Option<Matcher<Finger>>,
+        #[serde(default)]
+        row: Option<Matcher<u8>>,
+        #[serde(default)]
+        column: Option<Matcher<u8>>,
+    },
+    /// Consecutive characters need to be placed on neighboring keys (same row and neighboring
+    /// columns or same column and neighboring rows).
+    Adjacent { adjacent: String },
+}
+
+/// A character of the layout, either permutable (index into the permutation) or fixed (its
+/// position in the layout string).
+#[derive(Clone, Copy, Debug)]
+enum Slot {
+    Permutable(usize),
+    Fixed(usize),
+}
+
+impl Slot {
+    #[inline(always)]
+    fn position(&self, permutation: &[usize]) -> usize {
+        match self {
+            Self::Permutable(i) => permutation[*i],
+            Self::Fixed(p) => *p,
+        }
+    }
+}
+
+/// Constraints resolved to positions in the layout string.
+#[derive(Clone, Debug, Default)]
+pub struct Constraints {
+    /// For each permutable character, the positions it may be placed at (`None` if
+    /// unconstrained)
+    allowed: Vec<Option<Vec<bool>>>,
+    /// Pairs of characters that need to be placed on neighboring keys
+    adjacent_pairs: Vec<(Slot, Slot)>,
+    /// Whether the keys at two positions are neighbors
+    neighbors: Vec<Vec<bool>>,
+}
+
+impl Constraints {
+    /// Resolve the constraints for the given layout (whose layout string is `layout_str`).
+    pub fn resolve(
+        constraints: &[Constraint],
+        layout_str: &str,
+        fixed_characters: &str,
+        layout: &Layout,
+    ) -> Result<Self> {
+        let chars: Vec<char> = layout_str.chars().collect();
+        // the keys of the base layer (a symbol may also be generated on a higher layer of another
+        // key, e.g. in the number block)
+        let keys = chars
+            .iter()
+            .map(|c| {
+                layout
+                    .layerkeys
+                    .iter()
+                    .find(|k| k.layer == 0 && k.symbol == *c)
+                    .ok_or_else(|| anyhow!("Character '{}' not found in the layout", c))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut n_permutable = 0;
+        let slots: Vec<Slot> = chars
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                if fixed_characters.contains(*c) {
+                    Slot::Fixed(i)
+                } else {
+                    n_permutable += 1;
+                    Slot::Permutable(n_permutable - 1)
+                }
+            })
+            .collect();
+        let slot = |c: char| -> Result<Slot> {
+            chars
+                .iter()
+                .position(|lc| *lc == c)
+                .map(|i| slots[i])
+                .ok_or_else(|| anyhow!("Constrained character '{}' not in the layout", c))
+        };
+
+        let mut allowed: Vec<Option<Vec<bool>>> = vec![None; n_permutable];
+        let mut adjacent_pairs = Vec::new();
+        for constraint in constraints {
+            match constraint {
+                Constraint::Placement {
+                    chars: cs,
+                    hand,
+                    finger,
+                    row,
+                    column,
+                } => {
+                    if hand.is_none() && finger.is_none() && row.is_none() && column.is_none() {
+                        return Err(anyhow!(
+                            "Placement constraint for '{}' without hand, finger, row, or column",
+                            cs
+                        ));
+                    }
+                    let pattern = KeyPattern {
+                        hand: hand.clone(),
+                        finger: finger.clone(),
+                        row: row.clone(),
+                        column: column.clone(),
+                    };
+                    let matching: Vec<bool> = keys.iter().map(|k| pattern.matches(k)).collect();
+                    for c in cs.chars() {
+                        match slot(c)? {
+                            Slot::Permutable(i) => {
+                                let allowed_positions =
+                                    allowed[i].get_or_insert_with(|| vec![true; chars.len()]);
+                                allowed_positions
+                                    .iter_mut()
+                                    .zip(matching.iter())
+                                    .for_each(|(a, m)| *a &= m);
+                            }
+                            Slot::Fixed(p) => {
+                                if !matching[p] {
+                                    return Err(anyhow!(
+                                        "Fixed character '{}' violates a placement constraint",
+                                        c
+                                    ));
+                                }
+                            }
+                        }
+                    }
+                }
+                Constraint::Adjacent { adjacent } => {
+                    let cs: Vec<char> = adjacent.chars().collect();
+                    for w in cs.windows(2) {
+                        adjacent_pairs.push((slot(w[0])?, slot(w[1])?));
+                    }
+                }
+            }
+        }
+
+        let neighbors = keys
+            .iter()
+            .map(|k1| {
+                keys.iter()
+                    .map(|k2| {
+                        let (c1, r1) = (k1.key.matrix_position.0, k1.key.matrix_position.1);
+                        let (c2, r2) = (k2.key.matrix_position.0, k2.key.matrix_position.1);
+                        (r1 == r2 && (c1 as i16 - c2 as i16).abs() == 1)
+                            || (c1 == c2 && (r1 as i16 - r2 as i16).abs() == 1)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Ok(Self {
+            allowed,
+            adjacent_pairs,
+            neighbors,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allowed.iter().all(|a| a.is_none()) && self.adjacent_pairs.is_empty()
+    }
+
+    /// Whether the permutation satisfies all constraints.
+    pub fn is_satisfied(&self, permutation: &[usize]) -> bool {
+        self.allowed
+            .iter()
+            .zip(permutation.iter())
+            .all(|(a, p)| a.as_ref().map_or(true, |a| a[*p]))
+            && self
+                .adjacent_pairs
+                .iter()
+                .all(|(s1, s2)| self.neighbors[s1.position(permutation)][s2.position(permutation)])
+    }
+
+    /// Try to restore the adjacency constraints of the characters `moved` (indices into the
+    /// permutation) by swapping their permutable partners onto neighboring keys.
+    pub fn repair_adjacency<R: Rng + ?Sized>(
+        &self,
+        permutation: &mut [usize],
+        moved: &[usize],
+        rng: &mut R,
+    ) {
+        for k in moved {
+            for pair in self.adjacent_pairs.iter() {
+                let partner = match pair {
+                    (Slot::Permutable(i), Slot::Permutable(q)) if i == k => *q,
+                    (Slot::Permutable(q), Slot::Permutable(i)) if i == k => *q,
+                    _ => continue,
+                };
+                if moved.contains(&partner) || self.neighbors[permutation[*k]][permutation[partner]]
+                {
+                    continue;
+                }
+
+                let targets: Vec<usize> = (0..permutation.len())
+                    .filter(|j| {
+                        !moved.contains(j)
+                            && *j != partner
+                            && self.neighbors[permutation[*k]][permutation[*j]]
+                    })
+                    .collect();
+                if let Some(j) = targets.choose(rng) {
+                    permutation.swap(partner, *j);
+                }
+            }
+        }
+    }
+
+    /// Generate a random permutation of `positions` satisfying the constraints using a
+    /// randomized backtracking search (most constrained characters first). Returns `None` if no
+    /// valid permutation was found.
+    pub fn generate_random<R: Rng + ?Sized>(
+        &self,
+        positions: &[usize],
+        rng: &mut R,
+    ) -> Option<Vec<usize>> {
+        let n = positions.len();
+        let candidates: Vec<Vec<usize>> = (0..n)
+            .map(|i| {
+                positions
+                    .iter()
+                    .filter(|p| {
+                        self.allowed
+                            .get(i)
+                            .and_then(|a| a.as_ref())
+                            .map_or(true, |a| a[**p])
+                    })
+                    .cloned()
+                    .collect()
+            })
+            .collect();
+
+        let is_adjacent = |i: usize| {
+            let is_key = |s: &Slot| matches!(s, Slot::Permutable(j) if *j == i);
+            self.adjacent_pairs
+                .iter()
+                .any(|(s1, s2)| is_key(s1) || is_key(s2))
+        };
+        let mut order: Vec<usize> = (0..n).collect();
+        order.shuffle(rng);
+        order.sort_by_key(|i| (!is_adjacent(*i), candidates[*i].len()));
+
+        let mut permutation = vec![usize::MAX; n];
+        let mut used = vec![false; self.neighbors.len()];
+        // candidates in random order of each level of the search (and the next one to try)
+        let mut stack: Vec<(Vec<usize>, usize)> = Vec::with_capacity(n);
+        let mut steps = 0;
+
+        while stack.len() < n {
+            let level = stack.len();
+            let key = order[level];
+            let mut key_candidates = candidates[key].clone();
+            key_candidates.shuffle(rng);
+            stack.push((key_candidates, 0));
+
+            // find the next consistent candidate, backtracking if there is none
+            loop {
+                steps += 1;
+                if steps > MAX_SEARCH_STEPS {
+                    return None;
+                }
+
+                let level = stack.len() - 1;
+                let key = order[level];
+                let (key_candidates, next) = stack.last_mut().unwrap();
+                if permutation[key] != usize::MAX {
+                    used[permutation[key]] = false;
+                    permutation[key] = usize::MAX;
+                }
+
+                match key_candidates[*next..]
+                    .iter()
+                    .position(|p| !used[*p] && self.is_consistent(&permutation, key, *p))
+                {
+                    Some(offset) => {
+                        let p = key_candidates[*next + offset];
+                        *next += offset + 1;
+                        permutation[key] = p;
+                        used[p] = true;
+                        break;
+                    }
+                    None => {
+                        stack.pop();
+                        if stack.is_empty() {
+                            return None;
+                        }
+                    }
+                }
+            }
+        }
+
+        Some(permutation)
+    }
+
+    /// Whether placing the permutable character `key` at `position` is consistent with the
+    /// adjacency constraints wrt. the already placed characters.
+    fn is_consistent(&self, permutation: &[usize], key: usize, position: usize) -> bool {
+        let placed_position = |s: &Slot| match s {
+            Slot::Permutable(i) if *i == key => Some(position),
+            Slot::Permutable(i) if permutation[*i] == usize::MAX => None,
+            s => Some(s.position(permutation)),
+        };
+
+        self.adjacent_pairs.iter().all(|(s1, s2)| {
+            match (placed_position(s1), placed_position(s2)) {
+                (Some(p1), Some(p2)) => self.neighbors[p1][p2],
+                _ => true,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use keyboard_layout::{
+        config::LayoutConfig, keyboard::Keyboard, layout_generator::LayoutGenerator,
+        neo_layout_generator::NeoLayoutGenerator,
+    };
+    use std::sync::Arc;
+
+    const LAYOUT: &str = "jduaxphlmwqßctieobnrsgfvüäöyz,.k";
+
+    fn layout() -> Layout {
+        let layout_config = LayoutConfig::from_yaml("../../config/keyboard/standard.yml").unwrap();
+        let keyboard = Arc::new(Keyboard::from_yaml_object(layout_config.keyboard));
+        NeoLayoutGenerator::from_object(layout_config.base_layout, keyboard)
+            .generate(LAYOUT)
+            .unwrap()
+    }
+
+    fn parse(yaml: &str) -> serde_yaml::Result<Vec<Constraint>> {
+        serde_yaml::from_str(yaml)
+    }
+
+    #[test]
+    fn constraints_are_parsed() {
+        let constraints = parse(
+            "
+- chars: x
+  row: 2
+- chars: aeiou
+  hand: Left
+  column: [1, 2, 3, 4, 5]
+- adjacent: ',.'
+",
+        )
+        .unwrap();
+
+        assert!(matches!(
+            &constraints[0],
+            Constraint::Placement { chars, row: Some(Matcher::One(2)), hand: None, .. } if chars == "x"
+        ));
+        assert!(matches!(
+            &constraints[1],
+            Constraint::Placement {
+                hand: Some(Matcher::One(Hand::Left)),
+                column: Some(Matcher::Any(_)),
+                ..
+            }
+        ));
+        assert!(matches!(&constraints[2], Constraint::Adjacent { adjacent } if adjacent == ",."));
+    }
+
+    #[test]
+    fn misspelled_properties_are_rejected() {
+        assert!(parse("- chars: x\n  rwo: 2\n").is_err());
+        assert!(parse("- adjacent: ',.'\n  row: 2\n").is_err());
+    }
+
+    #[test]
+    fn fixed_characters_violating_a_constraint_are_rejected() {
+        // "k" is on the bottom row
+        let constraints = parse("- chars: k\n  row: 2\n").unwrap();
+        assert!(Constraints::resolve(&constraints, LAYOUT, "k", &layout()).is_err());
+
+        let constraints = parse("- chars: k\n  row: 3\n").unwrap();
+        assert!(Constraints::resolve(&constraints, LAYOUT, "k", &layout()).is_ok());
+    }
+
+    #[test]
+    fn placement_without_properties_is_rejected() {
+        let constraints = parse("- chars: x\n").unwrap();
+        assert!(Constraints::resolve(&constraints, LAYOUT, "", &layout()).is_err());
+    }
+}
@@ -0,0 +1,227 @@
+//! The `learnability` module provides a budget for the number of keys that are moved with
+//! respect to a reference layout (e.g. the one users migrate from). Moved keys can either be
+//! penalized with a cost per key (optionally weighted by the key's frequency) or limited to a
+//! maximum number.
+
+use crate::LayoutPermutator;
+
+use keyboard_layout::{key::MatrixPosition, layout_generator::LayoutGenerator};
+use layout_evaluation::evaluation::Evaluator;
+
+use ahash::AHashMap;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
+pub struct Parameters {
+    /// Layout that moved keys are counted against (defaults to the start layout). Whitespace is
+    /// removed.
+    #[serde(default)]
+    pub reference_layout: Option<String>,
+    /// Cost added for each key that is not in the same place as in the reference layout
+    #[serde(default)]
+    pub cost_per_moved_key: f64,
+    /// Multiply the cost of a moved key with the relative frequency (in percent) of its symbols
+    #[serde(default)]
+    pub frequency_weighted: bool,
+    /// Maximum number of moved keys (hard constraint)
+    #[serde(default)]
+    pub max_moved_keys: Option<usize>,
+}
+
+impl Parameters {
+    pub fn enabled(&self) -> bool {
+        self.cost_per_moved_key != 0.0 || self.max_moved_keys.is_some()
+    }
+}
+
+/// Penalty and limit of moved keys with respect to a reference layout.
+#[derive(Clone, Debug, Default)]
+pub struct Learnability {
+    reference: Vec<char>,
+    /// Cost of moving the key of a character (of the reference layout)
+    costs: AHashMap<char, f64>,
+    max_moved_keys: Option<usize>,
+}
+
+impl Learnability {
+    /// Resolve the parameters. If no reference layout is configured, `layout_str` (the start
+    /// layout) is the reference.
+    pub fn new(
+        params: &Parameters,
+        layout_str: &str,
+        layout_generator: &dyn LayoutGenerator,
+        evaluator: &Evaluator,
+    ) -> Result<Self> {
+        let reference_str: String = match &params.reference_layout {
+            Some(reference) => reference.chars().filter(|c| !c.is_whitespace()).collect(),
+            None => layout_str.to_string(),
+        };
+        let reference_layout = layout_generator.generate(&reference_str)?;
+
+        let key_frequencies = match params.frequency_weighted {
+            true => {
+                let unigrams = evaluator.map_unigrams(&reference_layout);
+                let total_weight: f64 = unigrams.grams.iter().map(|(_, w)| w).sum();
+                if total_weight <= 0.0 {
+                    return Err(anyhow!(
+                        "No unigrams available for weighting moved keys by their frequency"
+                    ));
+                }
+                let mut frequencies: AHashMap<MatrixPosition, f64> = AHashMap::default();
+                unigrams.grams.iter().for_each(|(k, w)| {
+                    *frequencies.entry(k.key.matrix_position).or_insert(0.0) +=
+                        100.0 * w / total_weight
+                });
+                Some(frequencies)
+            }
+            false => None,
+        };
+
+        let costs = reference_str
+            .chars()
+            .map(|c| {
+                let weight = match (
+                    &key_frequencies,
+                    reference_layout.get_layerkey_for_symbol(&c),
+                ) {
+                    (Some(frequencies), Some(k)) => frequencies
+                        .get(&k.key.matrix_position)
+                        .cloned()
+                        .unwrap_or_default(),
+                    (Some(_), None) => 0.0,
+                    (None, _) => 1.0,
+                };
+                (c, params.cost_per_moved_key * weight)
+            })
+            .filter(|(_, cost)| *cost != 0.0)
+            .collect();
+
+        Ok(Self {
+            reference: reference_str.chars().collect(),
+            costs,
+            max_moved_keys: params.max_moved_keys,
+        })
+    }
+
+    /// Apply the maximum number of moved keys (if any) to the permutator.
+    pub fn restrict(&self, permutator: LayoutPermutator) -> Result<LayoutPermutator> {
+        match self.max_moved_keys {
+            Some(max_moved_keys) => {
+                permutator.with_max_moved_keys(&self.reference(), max_moved_keys)
+            }
+            None => Ok(permutator),
+        }
+    }
+
+    pub fn reference(&self) -> String {
+        self.reference.iter().collect()
+    }
+
+    /// Characters of the layout that are not in the same place as in the reference layout.
+    pub fn moved_keys(&self, layout_str: &str) -> Vec<char> {
+        layout_str
+            .chars()
+            .zip(self.reference.iter())
+            .filter(|(c, r)| c != *r)
+            .map(|(c, _)| c)
+            .collect()
+    }
+
+    /// Cost of the keys moved with respect to the reference layout.
+    pub fn penalty(&self, layout_str: &str) -> f64 {
+        if self.costs.is_empty() {
+            return 0.0;
+        }
+
+        self.moved_keys(layout_str).iter().fold(0.0, |penalty, c| {
+            penalty + self.costs.get(c).cloned().unwrap_or_default()
+        })
+    }
+
+    /// A summary of the keys moved with respect to the reference layout.
+    pub fn report(&self, layout_str: &str) -> String {
+        let moved_keys = self.moved_keys(layout_str);
+        format!(
+            "Moved keys with respect to {} ({}{}): {} (penalty: {:.1})",
+            self.reference(),
+            moved_keys.len(),
+            self.max_moved_keys
+                .map(|m| format!(" of max. {}", m))
+                .unwrap_or_default(),
+            moved_keys.iter().collect::<String>(),
+            self.penalty(layout_str),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use keyboard_layout::{
+        config::LayoutConfig, keyboard::Keyboard, neo_layout_generator::NeoLayoutGenerator,
+    };
+    use layout_evaluation::{
+        config::EvaluationParameters,
+        ngram_mapper::on_demand_ngram_mapper::OnDemandNgramMapper,
+        ngrams::{Bigrams, Trigrams, Unigrams},
+    };
+    use std::sync::Arc;
+
+    const LAYOUT: &str = "jduaxphlmwqßctieobnrsgfvüäöyz,.k";
+
+    fn layout_generator() -> Box<dyn LayoutGenerator> {
+        let layout_config = LayoutConfig::from_yaml("../../config/keyboard/standard.yml").unwrap();
+        let keyboard = Arc::new(Keyboard::from_yaml_object(layout_config.keyboard));
+        Box::new(NeoLayoutGenerator::from_object(
+            layout_config.base_layout,
+            keyboard,
+        ))
+    }
+
+    fn evaluator(text: &str) -> Evaluator {
+        let eval_params =
+            EvaluationParameters::from_yaml("../../config/evaluation/default.yml").unwrap();
+        let ngram_mapper = OnDemandNgramMapper::with_ngrams(
+            Unigrams::from_text(text).unwrap(),
+            Bigrams::from_text(text).unwrap(),
+            Trigrams::from_text(text).unwrap(),
+            eval_params.ngram_mapper,
+        );
+        Evaluator::default(Box::new(ngram_mapper))
+    }
+
+    fn params() -> Parameters {
+        Parameters {
+            cost_per_moved_key: 1.0,
+            frequency_weighted: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn frequencies_are_taken_from_the_first_keyboard() {
+        // like with `--keyboards`, the top-level evaluator has no ngrams of its own
+        let mut evaluator = evaluator("");
+        evaluator
+            .add_keyboard("standard", 1.0, layout_generator(), self::evaluator("jaja"))
+            .unwrap();
+        let learnability =
+            Learnability::new(&params(), LAYOUT, &*layout_generator(), &evaluator).unwrap();
+
+        // swap 'j' and 'd'
+        assert!((learnability.penalty("djuaxphlmwqßctieobnrsgfvüäöyz,.k") - 50.0).abs() < 1e-9);
+        // swap 'd' and 'u'
+        assert_eq!(
+            learnability.penalty("judaxphlmwqßctieobnrsgfvüäöyz,.k"),
+            0.0
+        );
+    }
+
+    #[test]
+    fn frequency_weighting_without_ngrams_is_rejected() {
+        let evaluator = evaluator("");
+        assert!(Learnability::new(&params(), LAYOUT, &*layout_generator(), &evaluator).is_err());
+    }
+}
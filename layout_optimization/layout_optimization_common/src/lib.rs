@@ -1,4 +1,18 @@
-use rand::{seq::SliceRandom, thread_rng};
+pub mod checkpoint;
+pub mod constraints;
+pub mod learnability;
+pub mod local_search;
+pub mod swap_suggestions;
+
+use constraints::{Constraint, Constraints};
+
+use keyboard_layout::layout_generator::LayoutGenerator;
+
+use anyhow::{anyhow, Result};
+use rand::{seq::SliceRandom, thread_rng, Rng};
+
+/// Maximum number of attempts to find a swap resulting in a valid layout.
+const MAX_SWAP_TRIES: usize = 1000;
 
 #[derive(Clone, Debug)]
 pub struct LayoutPermutator {
@@ -6,6 +20,9 @@ pub struct LayoutPermutator {
     perm_indices: Vec<usize>,
     fixed_keys: Vec<char>,
     fixed_indices: Vec<usize>,
+    constraints: Constraints,
+    /// Permutation of a reference layout and the maximum number of keys that may differ from it
+    moved_keys_limit: Option<(Vec<usize>, usize)>,
 }
 
 impl LayoutPermutator {
@@ -29,7 +46,100 @@ impl LayoutPermutator {
             perm_indices,
             fixed_keys,
             fixed_indices,
+            constraints: Constraints::default(),
+            moved_keys_limit: None,
+        }
+    }
+
+    /// Restrict the generated permutations to those satisfying the given constraints. Fails if
+    /// the constraints can not be resolved or satisfied.
+    pub fn with_constraints(
+        mut self,
+        constraints: &[Constraint],
+        layout_generator: &dyn LayoutGenerator,
+    ) -> Result<Self> {
+        if constraints.is_empty() {
+            return Ok(self);
+        }
+
+        let layout_str = self.generate_string(&self.perm_indices);
+        let fixed_characters: String = self.fixed_keys.iter().collect();
+        let layout = layout_generator.generate(&layout_str)?;
+        self.constraints =
+            Constraints::resolve(constraints, &layout_str, &fixed_characters, &layout)?;
+
+        if self
+            .constraints
+            .generate_random(&self.perm_indices, &mut thread_rng())
+            .is_none()
+        {
+            return Err(anyhow!("No layout satisfies the constraints"));
+        }
+
+        Ok(self)
+    }
+
+    /// Restrict the generated permutations to those differing from the reference layout in at
+    /// most `max_moved_keys` keys. Fails if the reference layout does not consist of the same
+    /// characters (with the fixed ones in the same places) or violates the constraints (which
+    /// therefore need to be set first).
+    pub fn with_max_moved_keys(
+        mut self,
+        reference_layout: &str,
+        max_moved_keys: usize,
+    ) -> Result<Self> {
+        let reference = self.permutation_of(reference_layout)?;
+        if !self.constraints.is_satisfied(&reference) {
+            return Err(anyhow!(
+                "The reference layout {} violates the constraints",
+                reference_layout
+            ));
+        }
+        self.moved_keys_limit = Some((reference, max_moved_keys));
+
+        Ok(self)
+    }
+
+    /// The permutation corresponding to a layout string consisting of the same characters (with
+    /// the fixed ones in the same places).
+    pub fn permutation_of(&self, layout_str: &str) -> Result<Vec<usize>> {
+        let chars: Vec<char> = layout_str.chars().collect();
+        let permutation =
+            self.perm_keys
+                .iter()
+                .map(|c| {
+                    chars.iter().position(|lc| lc == c).ok_or_else(|| {
+                        anyhow!("Character '{}' missing in layout {}", c, layout_str)
+                    })
+                })
+                .collect::<Result<Vec<usize>>>()?;
+
+        if chars.len() != self.perm_keys.len() + self.fixed_keys.len()
+            || self
+                .fixed_indices
+                .iter()
+                .zip(self.fixed_keys.iter())
+                .any(|(i, c)| chars[*i] != *c)
+        {
+            return Err(anyhow!(
+                "Layout {} does not match {}",
+                layout_str,
+                self.generate_string(&self.perm_indices)
+            ));
         }
+
+        Ok(permutation)
+    }
+
+    /// Whether the permutation satisfies the constraints.
+    pub fn is_valid(&self, permutation: &[usize]) -> bool {
+        self.constraints.is_satisfied(permutation)
+            && self
+                .moved_keys_limit
+                .as_ref()
+                .map_or(true, |(reference, max_moved_keys)| {
+                    n_differences(permutation, reference) <= *max_moved_keys
+                })
     }
 
     pub fn generate_string(&self, permutation: &[usize]) -> String {
@@ -49,25 +159,68 @@ impl LayoutPermutator {
     }
 
     pub fn generate_random(&self) -> Vec<usize> {
-        let mut indices: Vec<usize> = self.perm_indices.to_vec();
-        indices.shuffle(&mut thread_rng());
+        self.generate_random_with_rng(&mut thread_rng())
+    }
 
-        indices
+    /// Generates a random permutation satisfying the constraints.
+    pub fn generate_random_with_rng<R: Rng + ?Sized>(&self, rng: &mut R) -> Vec<usize> {
+        // with a limit of moved keys, random layouts are close to the reference layout
+        if let Some((reference, max_moved_keys)) = &self.moved_keys_limit {
+            return self.perform_n_swaps_with_rng(reference, *max_moved_keys, rng);
+        }
+
+        if self.constraints.is_empty() {
+            let mut indices: Vec<usize> = self.perm_indices.to_vec();
+            indices.shuffle(rng);
+
+            return indices;
+        }
+
+        self.constraints
+            .generate_random(&self.perm_indices, rng)
+            .expect("No layout satisfying the constraints found")
     }
 
     /// Takes in a Layout, switches [nr_switches] keys in that layout, then returns it.
     /// Layout, in this case, is a [Vec<usize>].
     pub fn perform_n_swaps(&self, permutation: &[usize], nr_switches: usize) -> Vec<usize> {
+        self.perform_n_swaps_with_rng(permutation, nr_switches, &mut thread_rng())
+    }
+
+    /// Like [`Self::perform_n_swaps`], but with the given random number generator. Only swaps
+    /// resulting in layouts satisfying the constraints are performed.
+    pub fn perform_n_swaps_with_rng<R: Rng + ?Sized>(
+        &self,
+        permutation: &[usize],
+        nr_switches: usize,
+        rng: &mut R,
+    ) -> Vec<usize> {
         let mut indices: Vec<usize> = permutation.to_vec();
         let vec: Vec<usize> = (0..permutation.len()).collect();
-        let rng = &mut thread_rng();
+        let constrained = !self.constraints.is_empty() || self.moved_keys_limit.is_some();
 
         // Perform nr_switches switches
         for _ in 0..nr_switches {
-            let mut sw = vec.choose_multiple(rng, 2);
-            let sw0 = sw.next().unwrap();
-            let sw1 = sw.next().unwrap();
-            indices.swap(*sw0, *sw1);
+            for _ in 0..MAX_SWAP_TRIES {
+                let mut sw = vec.choose_multiple(rng, 2);
+                let sw0 = sw.next().unwrap();
+                let sw1 = sw.next().unwrap();
+                indices.swap(*sw0, *sw1);
+
+                if !constrained || self.is_valid(&indices) {
+                    break;
+                }
+
+                // moving a character of an adjacent pair requires moving its partner as well
+                let mut repaired = indices.clone();
+                self.constraints
+                    .repair_adjacency(&mut repaired, &[*sw0, *sw1], rng);
+                if self.is_valid(&repaired) {
+                    indices = repaired;
+                    break;
+                }
+                indices.swap(*sw0, *sw1);
+            }
         }
 
         indices
@@ -93,12 +246,136 @@ impl LayoutPermutator {
     pub fn get_permutable_indices(&self) -> Vec<usize> {
         self.perm_indices.clone()
     }
+
+    /// The permutation of the original layout if it satisfies the constraints, otherwise a
+    /// random valid permutation.
+    pub fn get_valid_permutable_indices(&self) -> Vec<usize> {
+        if self.is_valid(&self.perm_indices) {
+            return self.perm_indices.clone();
+        }
+
+        log::warn!(
+            "The layout {} violates the constraints. Starting with a random valid layout instead.",
+            self.generate_string(&self.perm_indices),
+        );
+        self.generate_random()
+    }
+}
+
+/// Number of positions at which the permutations differ.
+fn n_differences(permutation: &[usize], other: &[usize]) -> usize {
+    permutation
+        .iter()
+        .zip(other.iter())
+        .filter(|(p1, p2)| p1 != p2)
+        .count()
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    use keyboard_layout::{
+        config::LayoutConfig, key::Hand, keyboard::Keyboard,
+        neo_layout_generator::NeoLayoutGenerator,
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::sync::Arc;
+
+    const LAYOUT: &str = "jduaxphlmwqßctieobnrsgfvüäöyz,.k";
+    const CONSTRAINTS: &str = "
+- chars: aeiou
+  hand: Left
+- chars: x
+  row: 2
+- adjacent: ',.'
+";
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    fn layout_generator() -> NeoLayoutGenerator {
+        let layout_config = LayoutConfig::from_yaml("../../config/keyboard/standard.yml").unwrap();
+        let keyboard = Arc::new(Keyboard::from_yaml_object(layout_config.keyboard));
+        NeoLayoutGenerator::from_object(layout_config.base_layout, keyboard)
+    }
+
+    fn permutator(constraints: &str, fixed: &str) -> Result<LayoutPermutator> {
+        let constraints: Vec<Constraint> = serde_yaml::from_str(constraints).unwrap();
+        LayoutPermutator::new(LAYOUT, fixed).with_constraints(&constraints, &layout_generator())
+    }
+
+    /// Checks the constraints of [`CONSTRAINTS`] on the generated layout.
+    fn assert_satisfies_constraints(layout_str: &str) {
+        let layout = layout_generator().generate(layout_str).unwrap();
+        // the key of the base layer (symbols may also be generated on higher layers of other keys)
+        let key = |c: char| {
+            &layout
+                .layerkeys
+                .iter()
+                .find(|k| k.layer == 0 && k.symbol == c)
+                .unwrap()
+                .key
+        };
+
+        for c in "aeiou".chars() {
+            assert_eq!(key(c).hand, Hand::Left, "'{}' in {}", c, layout_str);
+        }
+        assert_eq!(key('x').matrix_position.1, 2, "'x' in {}", layout_str);
+
+        let (p1, p2) = (key(',').matrix_position, key('.').matrix_position);
+        let distance = (
+            (p1.0 as i16 - p2.0 as i16).abs(),
+            (p1.1 as i16 - p2.1 as i16).abs(),
+        );
+        assert!(
+            distance == (1, 0) || distance == (0, 1),
+            "',' and '.' in {}",
+            layout_str
+        );
+    }
+
+    #[test]
+    fn generated_layouts_satisfy_the_constraints() {
+        let pm = permutator(CONSTRAINTS, "").unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for _ in 0..100 {
+            let permutation = pm.generate_random_with_rng(&mut rng);
+            assert!(pm.is_valid(&permutation));
+            assert_satisfies_constraints(&pm.generate_string(&permutation));
+        }
+    }
+
+    #[test]
+    fn swapped_layouts_satisfy_the_constraints() {
+        let pm = permutator(CONSTRAINTS, "").unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+        let start = pm.get_valid_permutable_indices();
+        let comma = LAYOUT.chars().position(|c| c == ',').unwrap();
+
+        let mut permutation = start.clone();
+        let mut comma_moved = false;
+        for _ in 0..1000 {
+            permutation = pm.perform_n_swaps_with_rng(&permutation, 2, &mut rng);
+            let layout_str = pm.generate_string(&permutation);
+            assert_satisfies_constraints(&layout_str);
+            comma_moved |= layout_str.chars().position(|c| c == ',') != Some(comma);
+        }
+
+        // adjacent characters are moved together
+        assert!(comma_moved);
+    }
+
+    #[test]
+    fn unsatisfiable_constraints_are_rejected() {
+        // eleven characters on the ten permutable keys of the home row
+        assert!(permutator("- chars: ctieobnrsgx\n  row: 2\n", "").is_err());
+        // contradicting placements
+        assert!(permutator("- chars: x\n  hand: Left\n- chars: x\n  hand: Right\n", "").is_err());
+        // "j" and "k" are fixed and not next to each other
+        assert!(permutator("- adjacent: jk\n", "jk").is_err());
+    }
 }
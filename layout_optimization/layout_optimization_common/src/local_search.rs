@@ -0,0 +1,339 @@
+//! A steepest-descent local search for polishing the results of other optimizers.
+//!
+//! In each step, all swaps of two permutable keys (and optionally all rotations of three keys)
+//! are evaluated in parallel and the best improvement is applied. The search stops in a local
+//! optimum, i.e. when no swap (or rotation) improves the layout. This is certified by the
+//! returned [`Certificate`].
+
+use crate::{constraints::Constraint, learnability::Learnability, LayoutPermutator};
+
+use keyboard_layout::{layout::Layout, layout_generator::LayoutGenerator};
+use layout_evaluation::{cache::Cache, evaluation::Evaluator};
+
+use colored::Colorize;
+use rayon::prelude::*;
+use std::fmt;
+
+/// A modification of a layout, given by indices into the permutable keys.
+#[derive(Clone, Copy, Debug)]
+enum Move {
+    /// Swap two keys
+    Swap(usize, usize),
+    /// Move the key at the first index to the second, the second to the third, and the third
+    /// to the first index
+    Rotation(usize, usize, usize),
+}
+
+impl Move {
+    fn apply(&self, param: &[usize]) -> Vec<usize> {
+        let mut res = param.to_vec();
+        match *self {
+            Move::Swap(i, j) => res.swap(i, j),
+            Move::Rotation(i, j, k) => {
+                res[j] = param[i];
+                res[k] = param[j];
+                res[i] = param[k];
+            }
+        }
+        res
+    }
+}
+
+/// All swaps of two out of `n` keys.
+fn swaps(n: usize) -> Vec<Move> {
+    (0..n)
+        .flat_map(|i| (i + 1..n).map(move |j| Move::Swap(i, j)))
+        .collect()
+}
+
+/// All rotations of three out of `n` keys (two directions for each triple).
+fn rotations(n: usize) -> Vec<Move> {
+    (0..n)
+        .flat_map(|i| {
+            (i + 1..n).flat_map(move |j| {
+                (j + 1..n).flat_map(move |k| vec![Move::Rotation(i, j, k), Move::Rotation(i, k, j)])
+            })
+        })
+        .collect()
+}
+
+/// Proof that a layout is a local optimum: no evaluated move of the given kinds improves it.
+#[derive(Clone, Debug)]
+pub struct Certificate {
+    /// Cost of the locally optimal layout
+    pub cost: f64,
+    /// Number of improving moves that were applied
+    pub n_improvements: usize,
+    /// Number of swaps that were checked in the local optimum
+    pub n_swaps: usize,
+    /// Number of 3-rotations that were checked in the local optimum (if rotations were considered)
+    pub n_rotations: Option<usize>,
+}
+
+impl fmt::Display for Certificate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Local optimum with cost {:.4} (after {} improvements): no improving swap exists ({} swaps checked)",
+            self.cost, self.n_improvements, self.n_swaps,
+        )?;
+        if let Some(n_rotations) = self.n_rotations {
+            write!(
+                f,
+                ", no improving 3-rotation exists ({} rotations checked)",
+                n_rotations
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The best of the given moves (lowest cost after applying the move).
+fn best_move<F: Fn(&[usize]) -> f64 + Sync>(
+    moves: &[Move],
+    param: &[usize],
+    cost: F,
+) -> Option<(Move, f64)> {
+    moves
+        .par_iter()
+        .map(|m| (*m, cost(&m.apply(param))))
+        .min_by(|(_, c1), (_, c2)| c1.partial_cmp(c2).unwrap())
+}
+
+/// Improves the given layout by steepest descent until it is a local optimum wrt. swaps of two
+/// keys (and rotations of three keys if `with_rotations` is set). Keys in `fixed_characters`
+/// stay in place and moves violating the `constraints` (or the limit of moved keys of
+/// `learnability`) are not considered. The penalty for moved keys is added to the cost. If the
+/// given layout violates the constraints, the search starts from a random valid layout.
+#[allow(clippy::too_many_arguments)]
+pub fn polish(
+    process_name: &str,
+    layout_str: &str,
+    fixed_characters: &str,
+    constraints: &[Constraint],
+    learnability: &Learnability,
+    layout_generator: &dyn LayoutGenerator,
+    evaluator: &Evaluator,
+    with_rotations: bool,
+    result_cache: Option<Cache<f64>>,
+) -> (String, Layout, Certificate) {
+    let pm = LayoutPermutator::new(layout_str, fixed_characters)
+        .with_constraints(constraints, layout_generator)
+        .and_then(|pm| learnability.restrict(pm))
+        .unwrap_or_else(|e| panic!("Invalid constraints: {:?}", e));
+
+    let evaluate_layout_str = |layout_str: &str| -> f64 {
+        let l = layout_generator.generate(layout_str).unwrap();
+        evaluator.evaluate_layout(&l).total_cost()
+    };
+    let cost = |param: &[usize]| -> f64 {
+        if !pm.is_valid(param) {
+            return f64::INFINITY;
+        }
+
+        let layout_string = pm.generate_string(param);
+        let cost = match &result_cache {
+            Some(result_cache) => result_cache
+                .get_or_insert_with(&layout_string, || evaluate_layout_str(&layout_string)),
+            None => evaluate_layout_str(&layout_string),
+        };
+        cost + learnability.penalty(&layout_string)
+    };
+
+    let mut param = pm.get_valid_permutable_indices();
+    let mut current_cost = cost(&param);
+
+    let swaps = swaps(param.len());
+    let rotations = match with_rotations {
+        true => rotations(param.len()),
+        false => Vec::new(),
+    };
+
+    log::info!(
+        "{} Starting local search from {} ({:>6.1}) with {} swaps and {} rotations per step",
+        format!("{}:", process_name).yellow().bold(),
+        pm.generate_string(&param),
+        current_cost,
+        swaps.len(),
+        rotations.len(),
+    );
+
+    let mut n_improvements = 0;
+    loop {
+        // rotations are only considered if no swap improves the layout
+        let improvement = best_move(&swaps, &param, cost)
+            .filter(|(_, c)| *c < current_cost)
+            .or_else(|| best_move(&rotations, &param, cost).filter(|(_, c)| *c < current_cost));
+
+        match improvement {
+            Some((m, c)) => {
+                param = m.apply(&param);
+                current_cost = c;
+                n_improvements += 1;
+                log::info!(
+                    "{} {} {} ({:>6.1}), {:?}",
+                    format!("{}:", process_name).yellow().bold(),
+                    "Improved:".green(),
+                    pm.generate_string(&param),
+                    current_cost,
+                    m,
+                );
+            }
+            None => break,
+        }
+    }
+
+    let certificate = Certificate {
+        cost: current_cost,
+        n_improvements,
+        n_swaps: swaps.len(),
+        n_rotations: match with_rotations {
+            true => Some(rotations.len()),
+            false => None,
+        },
+    };
+
+    let best_layout_str = pm.generate_string(&param);
+    let best_layout = layout_generator.generate(&best_layout_str).unwrap();
+
+    (best_layout_str, best_layout, certificate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::learnability;
+
+    use keyboard_layout::{
+        config::LayoutConfig, keyboard::Keyboard, neo_layout_generator::NeoLayoutGenerator,
+    };
+    use layout_evaluation::{
+        config::EvaluationParameters,
+        ngram_mapper::on_demand_ngram_mapper::OnDemandNgramMapper,
+        ngrams::{Bigrams, Trigrams, Unigrams},
+    };
+    use std::{collections::HashSet, sync::Arc};
+
+    const LAYOUT: &str = "jduaxphlmwqßctieobnrsgfvüäöyz,.k";
+    /// All keys except "jduaen" are fixed
+    const FIXED: &str = "xphlmwqßctiobrsgfvüäöyz,.k";
+    const TEXT: &str = "Die Tastaturbelegung wird so lange optimiert, bis keine bessere mehr \
+                        gefunden wird. The quick brown fox jumps over the lazy dog.";
+
+    fn layout_generator() -> Box<dyn LayoutGenerator> {
+        let layout_config = LayoutConfig::from_yaml("../../config/keyboard/standard.yml").unwrap();
+        let keyboard = Arc::new(Keyboard::from_yaml_object(layout_config.keyboard));
+        Box::new(NeoLayoutGenerator::from_object(
+            layout_config.base_layout,
+            keyboard,
+        ))
+    }
+
+    fn evaluator() -> Evaluator {
+        let eval_params =
+            EvaluationParameters::from_yaml("../../config/evaluation/default.yml").unwrap();
+        let ngram_mapper = OnDemandNgramMapper::with_ngrams(
+            Unigrams::from_text(TEXT).unwrap(),
+            Bigrams::from_text(TEXT).unwrap(),
+            Trigrams::from_text(TEXT).unwrap(),
+            eval_params.ngram_mapper.clone(),
+        );
+        Evaluator::default(Box::new(ngram_mapper)).default_metrics(&eval_params.metrics)
+    }
+
+    fn polish(constraints: &[Constraint]) -> (String, Certificate) {
+        let layout_generator = layout_generator();
+        let evaluator = evaluator();
+        let learnability = Learnability::new(
+            &learnability::Parameters::default(),
+            LAYOUT,
+            layout_generator.as_ref(),
+            &evaluator,
+        )
+        .unwrap();
+        let (layout_str, _, certificate) = super::polish(
+            "test",
+            LAYOUT,
+            FIXED,
+            constraints,
+            &learnability,
+            layout_generator.as_ref(),
+            &evaluator,
+            false,
+            None,
+        );
+
+        (layout_str, certificate)
+    }
+
+    #[test]
+    fn rotations_are_three_cycles() {
+        let param = vec![10, 11, 12, 13, 14];
+        let rotation = Move::Rotation(0, 2, 4);
+        let rotated = rotation.apply(&param);
+        assert_eq!(rotated, vec![14, 11, 10, 13, 12]);
+        assert_eq!(rotation.apply(&rotation.apply(&rotated)), param);
+        // the other direction is the inverse
+        assert_eq!(Move::Rotation(0, 4, 2).apply(&rotated), param);
+    }
+
+    #[test]
+    fn all_moves_are_enumerated() {
+        for n in 0..8 {
+            let identity: Vec<usize> = (0..n).collect();
+            let n_triples = n * n.saturating_sub(1) * n.saturating_sub(2) / 6;
+            let rotated: HashSet<Vec<usize>> =
+                rotations(n).iter().map(|m| m.apply(&identity)).collect();
+            assert_eq!(rotations(n).len(), 2 * n_triples);
+            // all rotations are distinct and move exactly three keys
+            assert_eq!(rotated.len(), 2 * n_triples);
+            assert!(rotated.iter().all(|p| p
+                .iter()
+                .zip(&identity)
+                .filter(|(a, b)| a != b)
+                .count()
+                == 3));
+
+            assert_eq!(swaps(n).len(), n * n.saturating_sub(1) / 2);
+        }
+    }
+
+    #[test]
+    fn polished_layouts_are_local_optima() {
+        let (layout_str, certificate) = polish(&[]);
+        assert!(certificate.cost.is_finite());
+        assert_eq!(certificate.n_swaps, 15);
+
+        let layout_generator = layout_generator();
+        let evaluator = evaluator();
+        let cost = |param: &[usize], pm: &LayoutPermutator| {
+            let layout = layout_generator
+                .generate(&pm.generate_string(param))
+                .unwrap();
+            evaluator.evaluate_layout(&layout).total_cost()
+        };
+        let pm = LayoutPermutator::new(&layout_str, FIXED);
+        let param = pm.get_permutable_indices();
+        assert!((cost(&param, &pm) - certificate.cost).abs() < 1e-9);
+        for m in swaps(param.len()) {
+            assert!(cost(&m.apply(&param), &pm) >= certificate.cost - 1e-9);
+        }
+    }
+
+    #[test]
+    fn invalid_layouts_are_replaced_by_valid_ones() {
+        // 'n' is the only permutable key of the right hand and 'e' needs to move to the top row,
+        // so no single swap results in a valid layout
+        let constraints: Vec<Constraint> =
+            serde_yaml::from_str("[{chars: j, hand: Right}, {chars: e, row: 1}]").unwrap();
+        let (layout_str, certificate) = polish(&constraints);
+
+        assert!(certificate.cost.is_finite());
+        let pm = LayoutPermutator::new(&layout_str, FIXED)
+            .with_constraints(&constraints, layout_generator().as_ref())
+            .unwrap();
+        assert!(pm.is_valid(&pm.get_permutable_indices()));
+    }
+}
@@ -0,0 +1,108 @@
+//! Suggestions of key swaps for hand-tweaking a layout.
+//!
+//! All swaps of two permutable keys are evaluated (in parallel) and the ones with the lowest
+//! resulting cost are reported together with the metrics that improve or worsen by the swap.
+
+use crate::LayoutPermutator;
+
+use keyboard_layout::layout_generator::LayoutGenerator;
+use layout_evaluation::{evaluation::Evaluator, results::EvaluationResult};
+
+use anyhow::Result;
+use rayon::prelude::*;
+use serde::Serialize;
+
+/// The change of a single metric's (weighted) cost caused by a swap.
+#[derive(Serialize, Clone, Debug)]
+pub struct MetricChange {
+    pub metric: String,
+    pub delta: f64,
+}
+
+/// A swap of two keys and its effect on the layout's cost.
+#[derive(Serialize, Clone, Debug)]
+pub struct SwapSuggestion {
+    /// The swapped keys
+    pub keys: (char, char),
+    /// The layout after the swap
+    pub layout: String,
+    /// Total cost of the layout after the swap
+    pub cost: f64,
+    /// Change of the total cost (negative values are improvements)
+    pub cost_delta: f64,
+    /// Metrics whose costs decrease, strongest improvement first
+    pub improved_metrics: Vec<MetricChange>,
+    /// Metrics whose costs increase, strongest deterioration first
+    pub worsened_metrics: Vec<MetricChange>,
+}
+
+/// The weighted costs of all metrics of an evaluation result.
+fn metric_costs(result: &EvaluationResult) -> Vec<(String, f64)> {
+    result
+        .iter()
+        .flat_map(|metric_results| metric_results.metric_costs.iter())
+        .map(|metric_cost| (metric_cost.core.name.clone(), metric_cost.weighted_cost))
+        .collect()
+}
+
+/// Evaluates all swaps of two keys of the layout that are not contained in `fixed_characters`
+/// and returns the `n` swaps leading to the lowest costs (best first).
+pub fn suggest_swaps(
+    layout_str: &str,
+    fixed_characters: &str,
+    layout_generator: &dyn LayoutGenerator,
+    evaluator: &Evaluator,
+    n: usize,
+) -> Result<Vec<SwapSuggestion>> {
+    let pm = LayoutPermutator::new(layout_str, fixed_characters);
+    let param = pm.get_permutable_indices();
+
+    let layout = layout_generator.generate(layout_str)?;
+    let result = evaluator.evaluate_layout(&layout);
+    let cost = result.total_cost();
+    let costs = metric_costs(&result);
+
+    let keys: Vec<char> = layout_str.chars().collect();
+    let swaps: Vec<(usize, usize)> = (0..param.len())
+        .flat_map(|i| (i + 1..param.len()).map(move |j| (i, j)))
+        .collect();
+
+    let mut suggestions = swaps
+        .par_iter()
+        .map(|(i, j)| {
+            let mut swapped = param.clone();
+            swapped.swap(*i, *j);
+            let swapped_layout_str = pm.generate_string(&swapped);
+            let swapped_layout = layout_generator.generate(&swapped_layout_str)?;
+            let swapped_result = evaluator.evaluate_layout(&swapped_layout);
+            let swapped_cost = swapped_result.total_cost();
+
+            let (mut improved_metrics, mut worsened_metrics): (Vec<MetricChange>, Vec<_>) =
+                metric_costs(&swapped_result)
+                    .into_iter()
+                    .zip(costs.iter())
+                    .map(|((metric, c), (_, base_c))| MetricChange {
+                        metric,
+                        delta: c - base_c,
+                    })
+                    .filter(|change| change.delta != 0.0)
+                    .partition(|change| change.delta < 0.0);
+            improved_metrics.sort_by(|c1, c2| c1.delta.partial_cmp(&c2.delta).unwrap());
+            worsened_metrics.sort_by(|c1, c2| c2.delta.partial_cmp(&c1.delta).unwrap());
+
+            Ok(SwapSuggestion {
+                keys: (keys[param[*i]], keys[param[*j]]),
+                layout: swapped_layout_str,
+                cost: swapped_cost,
+                cost_delta: swapped_cost - cost,
+                improved_metrics,
+                worsened_metrics,
+            })
+        })
+        .collect::<Result<Vec<SwapSuggestion>>>()?;
+
+    suggestions.sort_by(|s1, s2| s1.cost.partial_cmp(&s2.cost).unwrap());
+    suggestions.truncate(n);
+
+    Ok(suggestions)
+}
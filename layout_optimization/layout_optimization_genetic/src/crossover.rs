@@ -0,0 +1,316 @@
+//! The `crossover` module provides crossovers for permutations. A genome holds the position (in
+//! the layout string) of each permutable key, so children need to be permutations of the
+//! parents' positions. Fixed keys are not part of the genome and therefore stay in place.
+
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+
+/// Crossover operators for permutations.
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub enum Crossover {
+    /// Children are copies of the parents (mutation-only optimization)
+    NoOp,
+    /// Cycle crossover (CX): the cycles of the parents' positions are alternately taken from
+    /// the first and the second parent
+    Cycle,
+    /// Order crossover (OX1): a segment of the first parent, the rest in the order of the second
+    Order,
+    /// Partially mapped crossover (PMX): a segment of the first parent, the rest at the places of
+    /// the second parent (resolving conflicts by the mapping of the segment)
+    PartiallyMapped,
+    /// Keys at the same place in both parents keep it, the others take their place from a
+    /// random parent if it is still free and fill the remaining places randomly otherwise
+    PositionBased,
+}
+
+impl Default for Crossover {
+    fn default() -> Self {
+        Crossover::NoOp
+    }
+}
+
+impl Crossover {
+    /// Generate a child of the two parents.
+    pub fn apply<R: Rng + ?Sized>(&self, p1: &[usize], p2: &[usize], rng: &mut R) -> Vec<usize> {
+        match self {
+            Crossover::NoOp => p1.to_vec(),
+            Crossover::Cycle => cycle(p1, p2, rng),
+            Crossover::Order => order(p1, p2, rng),
+            Crossover::PartiallyMapped => partially_mapped(p1, p2, rng),
+            Crossover::PositionBased => position_based(p1, p2, rng),
+        }
+    }
+}
+
+/// For each value of the permutation, its index.
+fn indices_of_values(permutation: &[usize]) -> Vec<usize> {
+    let max_value = permutation.iter().max().map_or(0, |m| m + 1);
+    let mut indices = vec![usize::MAX; max_value];
+    permutation
+        .iter()
+        .enumerate()
+        .for_each(|(i, v)| indices[*v] = i);
+
+    indices
+}
+
+/// Two random cut points `a < b` (the segment `a..b` is never empty).
+fn cut_points<R: Rng + ?Sized>(len: usize, rng: &mut R) -> (usize, usize) {
+    let a = rng.gen_range(0..len);
+    let b = rng.gen_range(a + 1..=len);
+
+    (a, b)
+}
+
+/// Cycle crossover (CX).
+pub fn cycle<R: Rng + ?Sized>(p1: &[usize], p2: &[usize], rng: &mut R) -> Vec<usize> {
+    let indices_in_p1 = indices_of_values(p1);
+    let mut child: Vec<Option<usize>> = vec![None; p1.len()];
+
+    let mut from_p1 = rng.gen::<bool>();
+    for start in 0..p1.len() {
+        if child[start].is_some() {
+            continue;
+        }
+
+        // the values at the indices of a cycle are the same in both parents
+        let mut i = start;
+        loop {
+            child[i] = Some(if from_p1 { p1[i] } else { p2[i] });
+            i = indices_in_p1[p2[i]];
+            if i == start {
+                break;
+            }
+        }
+        from_p1 = !from_p1;
+    }
+
+    child.into_iter().map(|v| v.unwrap()).collect()
+}
+
+/// Order crossover (OX1).
+pub fn order<R: Rng + ?Sized>(p1: &[usize], p2: &[usize], rng: &mut R) -> Vec<usize> {
+    let len = p1.len();
+    if len == 0 {
+        return Vec::new();
+    }
+
+    let (a, b) = cut_points(len, rng);
+    let mut child = p1.to_vec();
+    let mut used = vec![false; indices_of_values(p1).len()];
+    p1[a..b].iter().for_each(|v| used[*v] = true);
+
+    // fill the places after the segment (wrapping around) in the order of the second parent
+    let values = (b..len).chain(0..b).map(|i| p2[i]).filter(|v| !used[*v]);
+    (b..len)
+        .chain(0..a)
+        .zip(values)
+        .for_each(|(i, v)| child[i] = v);
+
+    child
+}
+
+/// Partially mapped crossover (PMX).
+pub fn partially_mapped<R: Rng + ?Sized>(p1: &[usize], p2: &[usize], rng: &mut R) -> Vec<usize> {
+    let len = p1.len();
+    if len == 0 {
+        return Vec::new();
+    }
+
+    let (a, b) = cut_points(len, rng);
+    let indices_in_p2 = indices_of_values(p2);
+    let mut child: Vec<Option<usize>> = (0..len)
+        .map(|i| match (a..b).contains(&i) {
+            true => Some(p1[i]),
+            false => None,
+        })
+        .collect();
+    let mut used = vec![false; indices_in_p2.len()];
+    p1[a..b].iter().for_each(|v| used[*v] = true);
+
+    // values of the second parent's segment that are missing in the child are placed by
+    // following the mapping of the segment until a place outside of it is found
+    for (i, &v) in p2.iter().enumerate().take(b).skip(a) {
+        if used[v] {
+            continue;
+        }
+
+        let mut j = i;
+        while (a..b).contains(&j) {
+            j = indices_in_p2[p1[j]];
+        }
+        child[j] = Some(v);
+        used[v] = true;
+    }
+
+    child
+        .into_iter()
+        .zip(p2.iter())
+        .map(|(c, v)| c.unwrap_or(*v))
+        .collect()
+}
+
+/// Position-based crossover.
+pub fn position_based<R: Rng + ?Sized>(p1: &[usize], p2: &[usize], rng: &mut R) -> Vec<usize> {
+    let len = p1.len();
+    let mut child: Vec<Option<usize>> = vec![None; len];
+    let mut used = vec![false; indices_of_values(p1).len()];
+
+    // keys at the same place in both parents keep it
+    for i in 0..len {
+        if p1[i] == p2[i] {
+            child[i] = Some(p1[i]);
+            used[p1[i]] = true;
+        }
+    }
+
+    // the other keys take their place from a random parent (or the other one) if it is free
+    let mut keys: Vec<usize> = (0..len).filter(|i| child[*i].is_none()).collect();
+    keys.shuffle(rng);
+    for i in keys {
+        let (first, second) = match rng.gen::<bool>() {
+            true => (p1[i], p2[i]),
+            false => (p2[i], p1[i]),
+        };
+        if let Some(v) = [first, second].iter().find(|v| !used[**v]) {
+            child[i] = Some(*v);
+            used[*v] = true;
+        }
+    }
+
+    // the remaining keys fill the remaining places randomly
+    let mut free: Vec<usize> = p1.iter().filter(|v| !used[**v]).cloned().collect();
+    free.shuffle(rng);
+    child
+        .iter_mut()
+        .filter(|c| c.is_none())
+        .zip(free)
+        .for_each(|(c, v)| *c = Some(v));
+
+    child.into_iter().map(|v| v.unwrap()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::optimization::LayoutCrossover;
+
+    use genevo::operator::CrossoverOp;
+    use keyboard_layout::{
+        config::LayoutConfig, keyboard::Keyboard, neo_layout_generator::NeoLayoutGenerator,
+    };
+    use layout_optimization_common::{constraints::Constraint, LayoutPermutator};
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::sync::Arc;
+
+    const LAYOUT: &str = "jduaxphlmwqßctieobnrsgfvüäöyz,.k";
+    const FIXED: &str = ",.k";
+    const CROSSOVERS: [Crossover; 5] = [
+        Crossover::NoOp,
+        Crossover::Cycle,
+        Crossover::Order,
+        Crossover::PartiallyMapped,
+        Crossover::PositionBased,
+    ];
+
+    fn is_permutation_of(child: &[usize], parent: &[usize]) -> bool {
+        let mut child = child.to_vec();
+        let mut parent = parent.to_vec();
+        child.sort_unstable();
+        parent.sort_unstable();
+        child == parent
+    }
+
+    #[test]
+    fn children_are_permutations_of_the_parents() {
+        let pm = LayoutPermutator::new(LAYOUT, FIXED);
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for crossover in CROSSOVERS.iter() {
+            for _ in 0..1000 {
+                let p1 = pm.generate_random_with_rng(&mut rng);
+                let p2 = pm.generate_random_with_rng(&mut rng);
+                let child = crossover.apply(&p1, &p2, &mut rng);
+
+                assert!(
+                    is_permutation_of(&child, &p1),
+                    "{:?}: {:?} is no permutation of {:?}",
+                    crossover,
+                    child,
+                    p1
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn layout_crossover_falls_back_to_parents_violating_constraints() {
+        let layout_config = LayoutConfig::from_yaml("../../config/keyboard/standard.yml").unwrap();
+        let keyboard = Arc::new(Keyboard::from_yaml_object(layout_config.keyboard));
+        let layout_generator = NeoLayoutGenerator::from_object(layout_config.base_layout, keyboard);
+        let constraints: Vec<Constraint> = serde_yaml::from_str(
+            "[{chars: aeiou, hand: Left}, {chars: x, row: 2}, {adjacent: jq}]",
+        )
+        .unwrap();
+        let pm = LayoutPermutator::new(LAYOUT, FIXED)
+            .with_constraints(&constraints, &layout_generator)
+            .unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+
+        for crossover in CROSSOVERS.iter() {
+            let layout_crossover = LayoutCrossover::new(*crossover, &pm);
+            let mut n_invalid = 0;
+            for _ in 0..100 {
+                let parents = vec![
+                    pm.generate_random_with_rng(&mut rng),
+                    pm.generate_random_with_rng(&mut rng),
+                ];
+                let children = layout_crossover.crossover(parents.clone(), &mut rng);
+                assert_eq!(children.len(), parents.len());
+                for (child, parent) in children.iter().zip(parents.iter()) {
+                    assert!(pm.is_valid(child), "{:?}: {:?}", crossover, child);
+                    assert!(is_permutation_of(child, parent), "{:?}", crossover);
+                }
+
+                let child = crossover.apply(&parents[0], &parents[1], &mut rng);
+                if !pm.is_valid(&child) {
+                    n_invalid += 1;
+                }
+            }
+
+            // the plain crossovers do generate invalid children that need to be replaced
+            if *crossover != Crossover::NoOp {
+                assert!(n_invalid > 0, "{:?}", crossover);
+            }
+        }
+    }
+
+    #[test]
+    fn identical_parents_give_identical_children() {
+        let pm = LayoutPermutator::new(LAYOUT, FIXED);
+        let mut rng = StdRng::seed_from_u64(2);
+
+        for crossover in CROSSOVERS.iter() {
+            let p = pm.generate_random_with_rng(&mut rng);
+            assert_eq!(crossover.apply(&p, &p, &mut rng), p, "{:?}", crossover);
+        }
+    }
+
+    #[test]
+    fn cycle_crossover_children_inherit_each_place_from_a_parent() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let p1: Vec<usize> = (0..20).collect();
+        let mut p2 = p1.clone();
+
+        for _ in 0..100 {
+            p2.shuffle(&mut rng);
+            let child = cycle(&p1, &p2, &mut rng);
+            assert!(
+                (0..child.len()).all(|i| child[i] == p1[i] || child[i] == p2[i]),
+                "{:?}",
+                child
+            );
+        }
+    }
+}
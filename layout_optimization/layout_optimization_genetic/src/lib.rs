@@ -1,3 +1,4 @@
+pub mod crossover;
 pub mod optimization;
 
 #[cfg(test)]
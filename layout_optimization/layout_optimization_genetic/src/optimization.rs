@@ -1,19 +1,37 @@
+use crate::crossover::Crossover;
+
 use keyboard_layout::{layout::Layout, layout_generator::LayoutGenerator};
 use layout_evaluation::{cache::Cache, evaluation::Evaluator};
 
-use layout_optimization_common::LayoutPermutator;
+use layout_optimization_common::{
+    checkpoint::{self, Checkpointing},
+    constraints::Constraint,
+    learnability::{self, Learnability},
+    LayoutPermutator,
+};
 
+use ahash::AHashMap;
 use anyhow::Result;
 use colored::Colorize;
+use ordered_float::OrderedFloat;
+use rand_xoshiro::{
+    rand_core::{RngCore, SeedableRng},
+    Xoshiro256PlusPlus,
+};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::{fs::File, sync::Arc};
+use std::{
+    cmp::Ordering,
+    fs::File,
+    sync::{Arc, Mutex},
+};
 
 use genevo::{
-    genetic::{Children, FitnessFunction, Parents},
-    operator::{prelude::*, CrossoverOp, GeneticOperator},
+    algorithm::EvaluatedPopulation,
+    genetic::{Children, Fitness, FitnessFunction, Offspring, Parents},
+    operator::{prelude::*, CrossoverOp, GeneticOperator, MutationOp, ReinsertionOp},
     population::Population,
     prelude::*,
-    random::SliceRandom,
     simulation::simulator::Simulator,
     types::fmt::Display,
 };
@@ -26,6 +44,22 @@ pub struct Parameters {
     pub selection_ratio: f64,
     pub mutation_rate: f64,
     pub reinsertion_ratio: f64,
+    /// Crossover of the selected parents
+    #[serde(default)]
+    pub crossover: Crossover,
+    /// How offspring replace individuals of the population
+    #[serde(default)]
+    pub reinsertion: Reinsertion,
+    /// With elitist reinsertion, take the best offspring first and fill up with the best
+    /// individuals of the population (instead of keeping the best of both)
+    #[serde(default)]
+    pub offspring_has_precedence: bool,
+    /// Hard constraints on the placement of characters
+    #[serde(default)]
+    pub constraints: Vec<Constraint>,
+    /// Penalty or limit for keys moved with respect to a reference layout
+    #[serde(default)]
+    pub learnability: learnability::Parameters,
 }
 
 impl Default for Parameters {
@@ -37,10 +71,30 @@ impl Default for Parameters {
             selection_ratio: 0.7,
             mutation_rate: 0.1,
             reinsertion_ratio: 0.7,
+            crossover: Crossover::default(),
+            reinsertion: Reinsertion::default(),
+            offspring_has_precedence: false,
+            constraints: Vec::new(),
+            learnability: learnability::Parameters::default(),
         }
     }
 }
 
+/// Reinsertion strategies of offspring into the population.
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub enum Reinsertion {
+    /// Offspring replace randomly chosen individuals
+    Uniform,
+    /// Only the best of the offspring and the population survive
+    Elitist,
+}
+
+impl Default for Reinsertion {
+    fn default() -> Self {
+        Reinsertion::Uniform
+    }
+}
+
 impl Parameters {
     pub fn from_yaml(filename: &str) -> Result<Self> {
         let f = File::open(filename)?;
@@ -51,50 +105,108 @@ impl Parameters {
 // The genotype
 type Genotype = Vec<usize>;
 
+/// The fitness of a layout is its cost (including the penalty for moved keys). Lower costs are
+/// fitter, so the ordering is reversed.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub struct LayoutFitness(OrderedFloat<f64>);
+
+impl LayoutFitness {
+    pub fn new(cost: f64) -> Self {
+        LayoutFitness(OrderedFloat(cost))
+    }
+
+    pub fn cost(&self) -> f64 {
+        self.0.into_inner()
+    }
+}
+
+impl PartialOrd for LayoutFitness {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for LayoutFitness {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.0.cmp(&self.0)
+    }
+}
+
+impl Fitness for LayoutFitness {
+    fn zero() -> Self {
+        LayoutFitness::new(0.0)
+    }
+
+    fn abs_diff(&self, other: &Self) -> Self {
+        LayoutFitness::new((self.cost() - other.cost()).abs())
+    }
+}
+
 /// The fitness function for [`Genotype`]s.
 #[derive(Clone, Debug)]
 pub struct FitnessCalc {
     evaluator: Arc<Evaluator>,
     permutator: LayoutPermutator,
     layout_generator: Box<dyn LayoutGenerator>,
-    result_cache: Option<Cache<usize>>,
+    learnability: Learnability,
+    result_cache: Option<Cache<f64>>,
+    /// Costs of the individuals that survived the last elitist reinsertion (they were evaluated
+    /// there already and need not be evaluated again in the following generation)
+    survivors: Arc<Mutex<AHashMap<Genotype, f64>>>,
 }
 
-impl FitnessFunction<Genotype, usize> for FitnessCalc {
-    fn fitness_of(&self, genome: &Genotype) -> usize {
+impl FitnessCalc {
+    /// Evaluate the genomes in parallel.
+    fn fitness_of_all(&self, genomes: &[Genotype]) -> Vec<LayoutFitness> {
+        genomes
+            .par_iter()
+            .map(|genome| self.fitness_of(genome))
+            .collect()
+    }
+}
+
+impl FitnessFunction<Genotype, LayoutFitness> for FitnessCalc {
+    fn fitness_of(&self, genome: &Genotype) -> LayoutFitness {
+        if let Some(cost) = self.survivors.lock().unwrap().get(genome) {
+            return LayoutFitness::new(*cost);
+        }
+
         let layout_str = self.permutator.generate_string(genome);
-        let l = self.layout_generator.generate(&layout_str).unwrap();
+        let cost = || {
+            let l = self.layout_generator.generate(&layout_str).unwrap();
+            self.evaluator.evaluate_layout(&l).total_cost() + self.learnability.penalty(&layout_str)
+        };
 
         // Get & return the evaluation-result
-        match &self.result_cache {
-            Some(result_cache) => result_cache.get_or_insert_with(&layout_str, || {
-                self.evaluator.evaluate_layout(&l).optimization_score()
-            }),
-            None => self.evaluator.evaluate_layout(&l).optimization_score(),
-        }
+        LayoutFitness::new(match &self.result_cache {
+            Some(result_cache) => result_cache.get_or_insert_with(&layout_str, cost),
+            None => cost(),
+        })
     }
 
-    fn average(&self, fitness_values: &[usize]) -> usize {
-        fitness_values.iter().sum::<usize>() / fitness_values.len()
+    fn average(&self, fitness_values: &[LayoutFitness]) -> LayoutFitness {
+        LayoutFitness::new(
+            fitness_values.iter().map(|f| f.cost()).sum::<f64>() / fitness_values.len() as f64,
+        )
     }
 
-    fn highest_possible_fitness(&self) -> usize {
-        100
+    fn highest_possible_fitness(&self) -> LayoutFitness {
+        LayoutFitness::new(0.0)
     }
 
-    fn lowest_possible_fitness(&self) -> usize {
-        0
+    fn lowest_possible_fitness(&self) -> LayoutFitness {
+        LayoutFitness::new(f64::INFINITY)
     }
 }
 
 struct LayoutBuilder {
-    indices: Vec<usize>,
+    permutator: LayoutPermutator,
 }
 
 impl LayoutBuilder {
     fn with_permutable_layout(layout_prototype: &LayoutPermutator) -> Self {
         Self {
-            indices: layout_prototype.get_permutable_indices(),
+            permutator: layout_prototype.clone(),
         }
     }
 }
@@ -104,9 +216,7 @@ impl GenomeBuilder<Vec<usize>> for LayoutBuilder {
     where
         R: Rng + Sized,
     {
-        let mut s: Vec<usize> = self.indices.clone();
-        s.shuffle(rng);
-        s
+        self.permutator.generate_random_with_rng(rng)
     }
 }
 
@@ -117,7 +227,7 @@ struct FromGivenLayoutBuilder {
 impl FromGivenLayoutBuilder {
     fn with_permutable_layout(layout_prototype: &LayoutPermutator) -> Self {
         Self {
-            indices: layout_prototype.get_permutable_indices(),
+            indices: layout_prototype.get_valid_permutable_indices(),
         }
     }
 }
@@ -132,75 +242,172 @@ impl GenomeBuilder<Vec<usize>> for FromGivenLayoutBuilder {
     }
 }
 
-#[derive(Default, Clone, Debug, PartialEq, Eq)]
-pub struct NoOpCrossover {}
-impl NoOpCrossover {
-    pub fn new() -> Self {
-        NoOpCrossover {}
+/// Applies a [`Crossover`] to each parent and the next one. Children violating the constraints
+/// of the [`LayoutPermutator`] are replaced by the first parent.
+#[derive(Clone, Debug)]
+pub struct LayoutCrossover {
+    crossover: Crossover,
+    permutator: LayoutPermutator,
+}
+impl LayoutCrossover {
+    pub fn new(crossover: Crossover, permutator: &LayoutPermutator) -> Self {
+        LayoutCrossover {
+            crossover,
+            permutator: permutator.clone(),
+        }
     }
 }
-impl GeneticOperator for NoOpCrossover {
+impl GeneticOperator for LayoutCrossover {
     fn name() -> String {
-        "No-Op-Crossover".to_string()
+        "Layout-Crossover".to_string()
     }
 }
-impl CrossoverOp<Vec<usize>> for NoOpCrossover {
-    fn crossover<R>(&self, parents: Parents<Vec<usize>>, _rng: &mut R) -> Children<Vec<usize>>
+impl CrossoverOp<Vec<usize>> for LayoutCrossover {
+    fn crossover<R>(&self, parents: Parents<Vec<usize>>, rng: &mut R) -> Children<Vec<usize>>
     where
         R: Rng + Sized,
     {
+        if self.crossover == Crossover::NoOp {
+            return parents;
+        }
+
         parents
+            .iter()
+            .zip(parents.iter().skip(1).cycle())
+            .map(|(p1, p2)| {
+                let child = self.crossover.apply(p1, p2, rng);
+                match self.permutator.is_valid(&child) {
+                    true => child,
+                    false => p1.clone(),
+                }
+            })
+            .collect()
     }
 }
 
-// Crossover method as used in https://github.com/Coletronix/Genetic-Keyboard-Generator
-#[derive(Default, Clone, Debug, PartialEq, Eq)]
-pub struct MyCrossover {}
-impl MyCrossover {
-    pub fn new() -> Self {
-        MyCrossover {}
+/// Swaps keys like the `SwapOrderMutator`, but only performs swaps resulting in layouts that
+/// satisfy the constraints of the [`LayoutPermutator`].
+#[derive(Clone, Debug)]
+pub struct LayoutMutator {
+    mutation_rate: f64,
+    permutator: LayoutPermutator,
+}
+impl LayoutMutator {
+    pub fn new(mutation_rate: f64, permutator: &LayoutPermutator) -> Self {
+        LayoutMutator {
+            mutation_rate,
+            permutator: permutator.clone(),
+        }
     }
 }
-impl GeneticOperator for MyCrossover {
+impl GeneticOperator for LayoutMutator {
     fn name() -> String {
-        "My-Crossover".to_string()
+        "Layout-Mutator".to_string()
     }
 }
-impl CrossoverOp<Vec<usize>> for MyCrossover {
-    fn crossover<R>(&self, parents: Parents<Vec<usize>>, rng: &mut R) -> Children<Vec<usize>>
+impl MutationOp<Vec<usize>> for LayoutMutator {
+    fn mutate<R>(&self, genome: Vec<usize>, rng: &mut R) -> Vec<usize>
     where
         R: Rng + Sized,
     {
-        parents
-            .iter()
-            .zip(parents.iter().skip(1).cycle())
-            .map(|(p1, p2)| {
-                let len = p1.len();
-                let mut offspring: Vec<Option<usize>> = vec![None; len];
-
-                // determine cycle and take from parent 1
-                let start_idx = rng.gen_range(0..len);
-                offspring[start_idx] = Some(p1[start_idx]);
-
-                // find index in p1 of value that sits in p2 at start_idx
-                let mut idx = p1.iter().position(|v| *v == p2[start_idx]).unwrap();
-                while idx != start_idx {
-                    offspring[idx] = Some(p1[idx]);
-
-                    // find index in p1 of value that sits in p2 at idx
-                    idx = p1.iter().position(|v| *v == p2[idx]).unwrap();
-                }
-
-                // rest is copied from parent 2
-                p2.iter()
-                    .zip(offspring.iter_mut())
-                    .for_each(|(p_val, o_val)| {
-                        if o_val.is_none() {
-                            *o_val = Some(*p_val);
-                        }
-                    });
+        // on average, `mutation_rate` swaps per key
+        let n_swaps = (genome.len() as f64 * self.mutation_rate + rng.gen::<f64>()) as usize;
+        self.permutator
+            .perform_n_swaps_with_rng(&genome, n_swaps, rng)
+    }
+}
 
-                offspring.iter().map(|val| val.unwrap()).collect()
+/// Reinserts offspring into the population, either uniformly at random or elitist. For the
+/// elitist strategy, the offspring are evaluated in parallel. The costs of the survivors are
+/// handed over to the [`FitnessCalc`], such that they are not evaluated again in the following
+/// generation.
+#[derive(Clone, Debug)]
+pub enum LayoutReinserter {
+    Uniform(UniformReinserter),
+    Elitist {
+        fitness_calc: Box<FitnessCalc>,
+        offspring_has_precedence: bool,
+        replace_ratio: f64,
+    },
+}
+impl LayoutReinserter {
+    pub fn new(params: &Parameters, fitness_calc: &FitnessCalc) -> Self {
+        match params.reinsertion {
+            Reinsertion::Uniform => {
+                LayoutReinserter::Uniform(UniformReinserter::new(params.reinsertion_ratio))
+            }
+            Reinsertion::Elitist => LayoutReinserter::Elitist {
+                fitness_calc: Box::new(fitness_calc.clone()),
+                offspring_has_precedence: params.offspring_has_precedence,
+                replace_ratio: params.reinsertion_ratio,
+            },
+        }
+    }
+}
+impl GeneticOperator for LayoutReinserter {
+    fn name() -> String {
+        "Layout-Reinserter".to_string()
+    }
+}
+impl ReinsertionOp<Genotype, LayoutFitness> for LayoutReinserter {
+    fn combine<R>(
+        &self,
+        offspring: &mut Offspring<Genotype>,
+        population: &EvaluatedPopulation<Genotype, LayoutFitness>,
+        rng: &mut R,
+    ) -> Vec<Genotype>
+    where
+        R: Rng + Sized,
+    {
+        let (fitness_calc, offspring_has_precedence, replace_ratio) = match self {
+            LayoutReinserter::Uniform(reinserter) => {
+                return reinserter.combine(offspring, population, rng)
+            }
+            LayoutReinserter::Elitist {
+                fitness_calc,
+                offspring_has_precedence,
+                replace_ratio,
+            } => (fitness_calc, *offspring_has_precedence, *replace_ratio),
+        };
+
+        let population_size = population.individuals().len();
+        let n_offspring =
+            ((population_size as f64 * replace_ratio + 0.5) as usize).min(offspring.len());
+
+        // best offspring first
+        let mut evaluated_offspring: Vec<(LayoutFitness, Genotype)> = fitness_calc
+            .fitness_of_all(offspring)
+            .into_iter()
+            .zip(offspring.drain(..))
+            .collect();
+        evaluated_offspring.sort_by(|(f1, _), (f2, _)| f2.cmp(f1));
+        evaluated_offspring.truncate(n_offspring);
+
+        // best individuals of the population first
+        let mut evaluated_population: Vec<(LayoutFitness, Genotype)> = population
+            .fitness_values()
+            .iter()
+            .cloned()
+            .zip(population.individuals().iter().cloned())
+            .collect();
+        evaluated_population.sort_by(|(f1, _), (f2, _)| f2.cmp(f1));
+
+        let mut candidates: Vec<(LayoutFitness, Genotype)> = evaluated_offspring
+            .into_iter()
+            .chain(evaluated_population)
+            .collect();
+        if !offspring_has_precedence {
+            candidates.sort_by(|(f1, _), (f2, _)| f2.cmp(f1));
+        }
+        candidates.truncate(population_size);
+
+        let mut survivors = fitness_calc.survivors.lock().unwrap();
+        survivors.clear();
+        candidates
+            .into_iter()
+            .map(|(fitness, genome)| {
+                survivors.insert(genome.clone(), fitness.cost());
+                genome
             })
             .collect()
     }
@@ -209,72 +416,141 @@ impl CrossoverOp<Vec<usize>> for MyCrossover {
 pub type MySimulator = Simulator<
     GeneticAlgorithm<
         Vec<usize>,
-        usize,
+        LayoutFitness,
         FitnessCalc,
         MaximizeSelector,
-        // PartiallyMappedCrossover,
-        // MyCrossover,
-        NoOpCrossover,
-        SwapOrderMutator,
-        UniformReinserter, // we do not use an elitist reinserter due to performance reasons (non-parallelized evaluation)
+        LayoutCrossover,
+        LayoutMutator,
+        LayoutReinserter,
     >,
     GenerationLimit,
 >;
 
-pub fn init_optimization(
+/// The contents of a checkpoint file.
+#[derive(Serialize, Deserialize)]
+struct CheckpointData {
+    layout_str: String,
+    fixed_characters: String,
+    /// Number of completed generations
+    generation: u64,
+    population: Vec<Genotype>,
+    all_time_best: Option<(LayoutFitness, Genotype)>,
+    /// Generates the seeds of the simulators of the following segments
+    rng: Xoshiro256PlusPlus,
+}
+
+fn initial_population(
     params: &Parameters,
-    evaluator: &Evaluator,
-    layout_str: &str,
-    layout_generator: &Box<dyn LayoutGenerator>,
-    fixed_characters: &str,
+    pm: &LayoutPermutator,
     start_with_layout: bool,
-    cache_results: bool,
-) -> (MySimulator, LayoutPermutator) {
-    let pm = LayoutPermutator::new(layout_str, fixed_characters);
-    let initial_population: Population<Genotype> = if start_with_layout {
+    rng: &mut Xoshiro256PlusPlus,
+) -> Population<Genotype> {
+    let mut seed = [0; 32];
+    rng.fill_bytes(&mut seed);
+    if start_with_layout {
         build_population()
-            .with_genome_builder(FromGivenLayoutBuilder::with_permutable_layout(&pm))
+            .with_genome_builder(FromGivenLayoutBuilder::with_permutable_layout(pm))
             .of_size(params.population_size)
-            .uniform_at_random()
+            .using_seed(seed)
     } else {
         build_population()
-            .with_genome_builder(LayoutBuilder::with_permutable_layout(&pm))
+            .with_genome_builder(LayoutBuilder::with_permutable_layout(pm))
             .of_size(params.population_size)
-            .uniform_at_random()
-    };
-
-    let result_cache = if cache_results {
-        Some(Cache::new())
-    } else {
-        None
-    };
+            .using_seed(seed)
+    }
+}
 
-    let sim = simulate(
+fn build_simulator(
+    params: &Parameters,
+    pm: &LayoutPermutator,
+    fitness_calc: FitnessCalc,
+    initial_population: Population<Genotype>,
+    generation_limit: u64,
+    seed: Option<[u8; 32]>,
+) -> MySimulator {
+    let reinserter = LayoutReinserter::new(params, &fitness_calc);
+    let builder = simulate(
         genetic_algorithm()
-            .with_evaluation(FitnessCalc {
-                evaluator: Arc::new(evaluator.clone()),
-                permutator: pm.clone(),
-                layout_generator: layout_generator.clone(),
-                result_cache,
-            })
+            .with_evaluation(fitness_calc)
             .with_selection(MaximizeSelector::new(
                 params.selection_ratio,
                 params.num_individuals_per_parents,
             ))
-            // .with_crossover(PartiallyMappedCrossover::new())
-            // .with_crossover(MyCrossover::new())
-            .with_crossover(NoOpCrossover::new())
-            .with_mutation(SwapOrderMutator::new(params.mutation_rate))
-            .with_reinsertion(UniformReinserter::new(params.reinsertion_ratio))
+            .with_crossover(LayoutCrossover::new(params.crossover, pm))
+            .with_mutation(LayoutMutator::new(params.mutation_rate, pm))
+            .with_reinsertion(reinserter)
             .with_initial_population(initial_population)
             .build(),
     )
-    .until(GenerationLimit::new(params.generation_limit))
-    .build();
+    .until(GenerationLimit::new(generation_limit));
+
+    match seed {
+        Some(seed) => builder.build_with_seed(seed),
+        None => builder.build(),
+    }
+}
+
+pub fn init_optimization(
+    params: &Parameters,
+    evaluator: &Evaluator,
+    layout_str: &str,
+    layout_generator: &Box<dyn LayoutGenerator>,
+    fixed_characters: &str,
+    start_with_layout: bool,
+    cache_results: bool,
+) -> (MySimulator, LayoutPermutator) {
+    let learnability = Learnability::new(
+        &params.learnability,
+        layout_str,
+        layout_generator.as_ref(),
+        evaluator,
+    )
+    .unwrap_or_else(|e| panic!("Invalid learnability parameters: {:?}", e));
+    let pm = LayoutPermutator::new(layout_str, fixed_characters)
+        .with_constraints(&params.constraints, layout_generator.as_ref())
+        .and_then(|pm| learnability.restrict(pm))
+        .unwrap_or_else(|e| panic!("Invalid constraints: {:?}", e));
+    let initial_population = initial_population(
+        params,
+        &pm,
+        start_with_layout,
+        &mut Xoshiro256PlusPlus::from_entropy(),
+    );
+
+    let result_cache = if cache_results {
+        Some(Cache::new())
+    } else {
+        None
+    };
+
+    let sim = build_simulator(
+        params,
+        &pm,
+        FitnessCalc {
+            evaluator: Arc::new(evaluator.clone()),
+            permutator: pm.clone(),
+            layout_generator: layout_generator.clone(),
+            learnability,
+            result_cache,
+            survivors: Arc::default(),
+        },
+        initial_population,
+        params.generation_limit,
+        None,
+    );
 
     (sim, pm)
 }
 
+/// Performs one run of the genetic algorithm, then returns the best layout found together with
+/// the learnability penalty it was optimized with (e.g. for reporting moved keys).
+///
+/// If `checkpointing` is given, the optimization runs in segments of `checkpointing.every`
+/// generations. Each segment is simulated with a seed from a random number generator that is
+/// saved in the checkpoint together with the population at the end of the segment. This way, a
+/// resumed optimization continues exactly as the original one would have. When resuming, the
+/// layout and fixed characters are taken from the checkpoint.
+#[allow(clippy::too_many_arguments)]
 pub fn optimize(
     params: &Parameters,
     evaluator: &Evaluator,
@@ -283,83 +559,215 @@ pub fn optimize(
     fixed_characters: &str,
     start_with_layout: bool,
     cache_results: bool,
-) -> (String, Layout) {
-    let (mut sim, pm) = init_optimization(
+    checkpointing: Option<Checkpointing>,
+) -> (String, Layout, Learnability) {
+    optimize_with_rng(
         params,
         evaluator,
         layout_str,
-        layout_generator,
+        layout_generator.as_ref(),
         fixed_characters,
         start_with_layout,
         cache_results,
-    );
+        checkpointing,
+        Xoshiro256PlusPlus::from_entropy(),
+        |_, _| {},
+    )
+}
+
+/// Like [`optimize`], but the initial population and the seeds of the simulators are drawn from
+/// `rng` (when resuming, the generator is taken from the checkpoint instead). `observe` is called
+/// with the number and the evaluated population of each generation.
+#[allow(clippy::too_many_arguments)]
+fn optimize_with_rng(
+    params: &Parameters,
+    evaluator: &Evaluator,
+    layout_str: &str,
+    layout_generator: &dyn LayoutGenerator,
+    fixed_characters: &str,
+    start_with_layout: bool,
+    cache_results: bool,
+    checkpointing: Option<Checkpointing>,
+    rng: Xoshiro256PlusPlus,
+    mut observe: impl FnMut(u64, &EvaluatedPopulation<Genotype, LayoutFitness>),
+) -> (String, Layout, Learnability) {
+    let resumed: Option<CheckpointData> = checkpointing
+        .as_ref()
+        .filter(|c| c.resume)
+        .map(|c| checkpoint::load(&c.path).unwrap_or_else(|e| panic!("{:?}", e)));
+
+    let (layout_str, fixed_characters) = match &resumed {
+        Some(data) => (data.layout_str.clone(), data.fixed_characters.clone()),
+        None => (layout_str.to_string(), fixed_characters.to_string()),
+    };
+    let learnability = Learnability::new(
+        &params.learnability,
+        &layout_str,
+        layout_generator,
+        evaluator,
+    )
+    .unwrap_or_else(|e| panic!("Invalid learnability parameters: {:?}", e));
+    let pm = LayoutPermutator::new(&layout_str, &fixed_characters)
+        .with_constraints(&params.constraints, layout_generator)
+        .and_then(|pm| learnability.restrict(pm))
+        .unwrap_or_else(|e| panic!("Invalid constraints: {:?}", e));
+    let fitness_calc = FitnessCalc {
+        evaluator: Arc::new(evaluator.clone()),
+        permutator: pm.clone(),
+        layout_generator: layout_generator.clone_box(),
+        learnability: learnability.clone(),
+        result_cache: if cache_results {
+            Some(Cache::new())
+        } else {
+            None
+        },
+        survivors: Arc::default(),
+    };
+
+    let (mut population, mut generation, mut all_time_best, mut rng) = match resumed {
+        Some(data) => {
+            log::info!(
+                "Resuming optimization at generation {} with: {:?}",
+                data.generation,
+                params
+            );
+            (
+                Population::with_individuals(data.population),
+                data.generation,
+                data.all_time_best,
+                data.rng,
+            )
+        }
+        None => {
+            log::info!("Starting optimization with: {:?}", params);
+            let mut rng = rng;
+            (
+                initial_population(params, &pm, start_with_layout, &mut rng),
+                0,
+                None,
+                rng,
+            )
+        }
+    };
 
-    log::info!("Starting optimization with: {:?}", params);
-    let mut all_time_best: Option<(usize, Genotype)> = None;
-
-    loop {
-        let result = sim.step();
-        match result {
-            Ok(SimResult::Intermediate(step)) => {
-                let evaluated_population = step.result.evaluated_population;
-                let best_solution = step.result.best_solution;
-                if let Some(king) = &all_time_best {
-                    if best_solution.solution.fitness > king.0 {
-                        let layout_str = pm.generate_string(&best_solution.solution.genome);
-                        let layout = layout_generator.generate(&layout_str).unwrap();
-
-                        let evaluation_result = evaluator.evaluate_layout(&layout);
-                        println!(
-                            "{}: {} (score: {})\n{}",
-                            format!("New best in generation {}:", step.iteration)
-                                .yellow()
-                                .bold(),
-                            layout_str,
-                            format!("{}", evaluation_result.total_cost()).yellow(),
-                            layout.plot(),
-                        );
+    'segments: loop {
+        let remaining = params.generation_limit.saturating_sub(generation);
+        if remaining == 0 && all_time_best.is_some() {
+            break;
+        }
 
+        // Without checkpointing, the whole optimization runs in a single segment.
+        let segment_length = match &checkpointing {
+            Some(c) if c.every > 0 => c.every.min(remaining),
+            _ => remaining,
+        };
+        let is_last_segment = segment_length == remaining;
+
+        let mut seed = [0; 32];
+        rng.fill_bytes(&mut seed);
+        // The last generation of an intermediate segment is the first one of the next segment.
+        let mut sim = build_simulator(
+            params,
+            &pm,
+            fitness_calc.clone(),
+            population,
+            match is_last_segment {
+                true => segment_length,
+                false => segment_length + 1,
+            },
+            Some(seed),
+        );
+
+        loop {
+            let result = sim.step();
+            match result {
+                Ok(SimResult::Intermediate(step)) => {
+                    generation += 1;
+                    let evaluated_population = step.result.evaluated_population;
+                    let best_solution = step.result.best_solution;
+                    observe(generation, &evaluated_population);
+                    if let Some(king) = &all_time_best {
+                        if best_solution.solution.fitness > king.0 {
+                            let layout_str = pm.generate_string(&best_solution.solution.genome);
+                            let layout = layout_generator.generate(&layout_str).unwrap();
+
+                            let evaluation_result = evaluator.evaluate_layout(&layout);
+                            println!(
+                                "{}: {} (score: {})\n{}",
+                                format!("New best in generation {}:", generation)
+                                    .yellow()
+                                    .bold(),
+                                layout_str,
+                                format!("{}", evaluation_result.total_cost()).yellow(),
+                                layout.plot(),
+                            );
+
+                            all_time_best = Some((
+                                best_solution.solution.fitness,
+                                best_solution.solution.genome.clone(),
+                            ));
+                        }
+                    } else {
                         all_time_best = Some((
                             best_solution.solution.fitness,
                             best_solution.solution.genome.clone(),
                         ));
                     }
-                } else {
-                    all_time_best = Some((
-                        best_solution.solution.fitness,
-                        best_solution.solution.genome.clone(),
-                    ));
+                    log::info!(
+                        "{}, average cost: {:.2}, \
+                         best cost: {:.2}, all time best: {:.2}, duration: {}, processing_time: {}, generation's best: {}",
+                        format!("Generation {}:", generation).yellow().bold(),
+                        evaluated_population.average_fitness().cost(),
+                        best_solution.solution.fitness.cost(),
+                        all_time_best.as_ref().unwrap().0.cost(),
+                        step.duration.fmt(),
+                        step.processing_time.fmt(),
+                        pm.generate_string(&best_solution.solution.genome)
+                    );
+                }
+                Ok(SimResult::Final(step, processing_time, duration, _stop_reason)) => {
+                    if !is_last_segment {
+                        let individuals = step.result.evaluated_population.individuals().to_vec();
+                        if let Some(checkpointing) = &checkpointing {
+                            let data = CheckpointData {
+                                layout_str: layout_str.clone(),
+                                fixed_characters: fixed_characters.clone(),
+                                generation,
+                                population: individuals.clone(),
+                                all_time_best: all_time_best.clone(),
+                                rng: rng.clone(),
+                            };
+                            match checkpoint::save(&checkpointing.path, &data) {
+                                Ok(()) => log::info!(
+                                    "Saved checkpoint after generation {} to {}",
+                                    generation,
+                                    checkpointing.path
+                                ),
+                                Err(e) => log::error!("{:?}", e),
+                            }
+                        }
+                        population = Population::with_individuals(individuals);
+                        break;
+                    }
+
+                    let layout_str = pm.generate_string(&all_time_best.as_ref().unwrap().1);
+                    let layout = layout_generator.generate(&layout_str).unwrap();
+                    println!(
+                        "{} after generation {}, duration {}, processing time {}\n\n{}\n\n{}\n{}",
+                        "Final result".green().bold(),
+                        generation + 1,
+                        duration.fmt(),
+                        processing_time.fmt(),
+                        layout_str,
+                        layout.plot_compact(),
+                        layout.plot()
+                    );
+                    break 'segments;
+                }
+                Err(error) => {
+                    println!("{}", error);
+                    break 'segments;
                 }
-                log::info!(
-                    "{}, average_fitness: {}, \
-                     best fitness: {}, all time best: {}, duration: {}, processing_time: {}, generation's best: {}",
-                    format!("Generation {}:", step.iteration).yellow().bold(),
-                    evaluated_population.average_fitness(),
-                    best_solution.solution.fitness,
-                    all_time_best.as_ref().unwrap().0,
-                    step.duration.fmt(),
-                    step.processing_time.fmt(),
-                    pm.generate_string(&best_solution.solution.genome)
-                );
-            }
-            Ok(SimResult::Final(step, processing_time, duration, _stop_reason)) => {
-                let layout_str = pm.generate_string(&all_time_best.as_ref().unwrap().1);
-                let layout = layout_generator.generate(&layout_str).unwrap();
-                println!(
-                    "{} after generation {}, duration {}, processing time {}\n\n{}\n\n{}\n{}",
-                    "Final result".green().bold(),
-                    step.iteration,
-                    duration.fmt(),
-                    processing_time.fmt(),
-                    layout_str,
-                    layout.plot_compact(),
-                    layout.plot()
-                );
-                break;
-            }
-            Err(error) => {
-                println!("{}", error);
-                break;
             }
         }
     }
@@ -367,5 +775,142 @@ pub fn optimize(
     let best_layout_str = pm.generate_string(&all_time_best.as_ref().unwrap().1);
     let best_layout = layout_generator.generate(&best_layout_str).unwrap();
 
-    (best_layout_str, best_layout)
+    (best_layout_str, best_layout, learnability)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use keyboard_layout::{
+        config::LayoutConfig, keyboard::Keyboard, neo_layout_generator::NeoLayoutGenerator,
+    };
+    use layout_evaluation::{
+        config::EvaluationParameters,
+        ngram_mapper::on_demand_ngram_mapper::OnDemandNgramMapper,
+        ngrams::{Bigrams, Trigrams, Unigrams},
+    };
+
+    const LAYOUT: &str = "jduaxphlmwqßctieobnrsgfvüäöyz,.k";
+    const FIXED: &str = ",.";
+    // all characters of the layout occur in the text, such that no swap leaves the cost unchanged
+    // (ties would be decided by rounding errors)
+    const TEXT: &str = "Die Tastaturbelegung wird so lange optimiert, bis keine bessere mehr \
+                        gefunden wird. The quick brown fox jumps over the lazy dog. Größere \
+                        Übungen ärgern weiße Äffchen.";
+
+    type Trajectory = Vec<(u64, Vec<Genotype>, f64)>;
+
+    fn init() -> (Box<dyn LayoutGenerator>, Evaluator) {
+        let layout_config = LayoutConfig::from_yaml("../../config/keyboard/standard.yml").unwrap();
+        let keyboard = Arc::new(Keyboard::from_yaml_object(layout_config.keyboard));
+        let layout_generator: Box<dyn LayoutGenerator> = Box::new(NeoLayoutGenerator::from_object(
+            layout_config.base_layout,
+            keyboard,
+        ));
+
+        let eval_params =
+            EvaluationParameters::from_yaml("../../config/evaluation/default.yml").unwrap();
+        let ngram_mapper = OnDemandNgramMapper::with_ngrams(
+            Unigrams::from_text(TEXT).unwrap(),
+            Bigrams::from_text(TEXT).unwrap(),
+            Trigrams::from_text(TEXT).unwrap(),
+            eval_params.ngram_mapper.clone(),
+        );
+        let evaluator = Evaluator::default(Box::new(ngram_mapper))
+            .default_metrics(&eval_params.metrics)
+            .with_aggregation(&eval_params.aggregation);
+
+        (layout_generator, evaluator)
+    }
+
+    /// Records the population and its average cost in each generation.
+    fn run(
+        generation_limit: u64,
+        checkpointing: Checkpointing,
+        layout_generator: &dyn LayoutGenerator,
+        evaluator: &Evaluator,
+    ) -> Trajectory {
+        let params = Parameters {
+            population_size: 10,
+            generation_limit,
+            reinsertion: Reinsertion::Elitist,
+            ..Default::default()
+        };
+        let mut trajectory = Trajectory::new();
+        optimize_with_rng(
+            &params,
+            evaluator,
+            LAYOUT,
+            layout_generator,
+            FIXED,
+            false,
+            false,
+            Some(checkpointing),
+            Xoshiro256PlusPlus::seed_from_u64(0),
+            |generation, population| {
+                trajectory.push((
+                    generation,
+                    population.individuals().to_vec(),
+                    population.average_fitness().cost(),
+                ))
+            },
+        );
+
+        trajectory
+    }
+
+    #[test]
+    fn resumed_run_continues_the_interrupted_run() {
+        let (layout_generator, evaluator) = init();
+        let path = std::env::temp_dir()
+            .join(format!("genetic_checkpoint_{}.yml", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        let checkpointing = |resume| Checkpointing {
+            path: path.clone(),
+            every: 4,
+            resume,
+        };
+
+        let uninterrupted = run(
+            12,
+            checkpointing(false),
+            layout_generator.as_ref(),
+            &evaluator,
+        );
+        // the run is interrupted within the second segment, the checkpoint is the one after
+        // the first segment
+        let interrupted = run(
+            6,
+            checkpointing(false),
+            layout_generator.as_ref(),
+            &evaluator,
+        );
+        let resumed = run(
+            12,
+            checkpointing(true),
+            layout_generator.as_ref(),
+            &evaluator,
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        // the last generation of a run is not observed
+        assert_eq!(uninterrupted.len(), 11);
+        assert_eq!(interrupted.len(), 5);
+        assert_eq!(resumed.len(), 7);
+        assert_eq!(resumed[0].0, 5);
+        // the costs may differ in the last digits due to the summation order in the evaluation
+        for (generation, population, cost) in interrupted.iter().chain(resumed.iter()) {
+            let (expected_generation, expected_population, expected_cost) =
+                &uninterrupted[*generation as usize - 1];
+            assert_eq!(generation, expected_generation);
+            assert_eq!(population, expected_population, "generation {}", generation);
+            assert!(
+                (cost - expected_cost).abs() < 1e-9,
+                "generation {}",
+                generation
+            );
+        }
+    }
 }
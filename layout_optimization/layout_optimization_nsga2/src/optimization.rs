@@ -0,0 +1,464 @@
+use keyboard_layout::layout_generator::LayoutGenerator;
+use layout_evaluation::{cache::Cache, evaluation::Evaluator, results::EvaluationResult};
+
+use layout_optimization_common::LayoutPermutator;
+
+use anyhow::Result;
+use colored::Colorize;
+use rand::{seq::SliceRandom, thread_rng, Rng};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, fs::File};
+
+/// An objective of the optimization: the sum of the weighted costs of the given metrics.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ObjectiveConfig {
+    /// Name of the objective
+    pub name: String,
+    /// Names of the metrics (as they appear in the evaluation results) belonging to the objective.
+    /// If not given, the objective contains all metrics that are not part of any other objective.
+    #[serde(default)]
+    pub metrics: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Parameters {
+    /// Number of layouts in the population
+    pub population_size: usize,
+    /// Number of generations to evolve
+    pub generation_limit: usize,
+    /// In each mutation of a layout, swap this many key-pairs
+    pub key_switches: usize,
+    /// The objectives to minimize simultaneously
+    pub objectives: Vec<ObjectiveConfig>,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Parameters {
+            population_size: 100,
+            generation_limit: 500,
+            key_switches: 1,
+            objectives: vec![ObjectiveConfig {
+                name: "Total".to_string(),
+                metrics: None,
+            }],
+        }
+    }
+}
+
+impl Parameters {
+    pub fn from_yaml(filename: &str) -> Result<Self> {
+        let f = File::open(filename)?;
+        Ok(serde_yaml::from_reader(f)?)
+    }
+}
+
+/// A layout of the Pareto front together with its objective values.
+#[derive(Serialize, Clone, Debug)]
+pub struct ParetoLayout {
+    pub layout: String,
+    pub objectives: Vec<f64>,
+    pub total_cost: f64,
+}
+
+/// Maps evaluation results to objective values.
+#[derive(Clone, Debug)]
+struct Objectives {
+    configs: Vec<ObjectiveConfig>,
+}
+
+impl Objectives {
+    /// Index of the objective that a metric belongs to.
+    fn objective_index(&self, metric_name: &str) -> Option<usize> {
+        self.configs
+            .iter()
+            .position(|config| {
+                config
+                    .metrics
+                    .as_ref()
+                    .map(|metrics| metrics.iter().any(|m| m == metric_name))
+                    .unwrap_or(false)
+            })
+            .or_else(|| {
+                self.configs
+                    .iter()
+                    .position(|config| config.metrics.is_none())
+            })
+    }
+
+    fn values(&self, evaluation_result: &EvaluationResult) -> Vec<f64> {
+        let mut values = vec![0.0; self.configs.len()];
+        evaluation_result
+            .iter()
+            .flat_map(|metric_results| metric_results.metric_costs.iter())
+            .for_each(|metric_cost| {
+                if let Some(idx) = self.objective_index(&metric_cost.core.name) {
+                    values[idx] += metric_cost.weighted_cost;
+                }
+            });
+
+        values
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Individual {
+    permutation: Vec<usize>,
+    layout: String,
+    objectives: Vec<f64>,
+    total_cost: f64,
+    rank: usize,
+    crowding_distance: f64,
+}
+
+impl Individual {
+    /// If `self` is at least as good as `other` in all objectives and better in at least one.
+    fn dominates(&self, other: &Individual) -> bool {
+        let mut better = false;
+        for (a, b) in self.objectives.iter().zip(other.objectives.iter()) {
+            if a > b {
+                return false;
+            }
+            if a < b {
+                better = true;
+            }
+        }
+
+        better
+    }
+
+    /// Crowded-comparison operator: lower rank first, then larger crowding distance.
+    fn crowded_cmp(&self, other: &Individual) -> Ordering {
+        self.rank.cmp(&other.rank).then_with(|| {
+            other
+                .crowding_distance
+                .partial_cmp(&self.crowding_distance)
+                .unwrap_or(Ordering::Equal)
+        })
+    }
+}
+
+/// Sorts the population into fronts of non-dominated individuals (sets `rank`) and returns
+/// the indices of the individuals in each front.
+fn non_dominated_sort(population: &mut [Individual]) -> Vec<Vec<usize>> {
+    let n = population.len();
+    let mut dominated_by: Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut domination_count = vec![0; n];
+    let mut fronts: Vec<Vec<usize>> = vec![Vec::new()];
+
+    for p in 0..n {
+        for q in 0..n {
+            if population[p].dominates(&population[q]) {
+                dominated_by[p].push(q);
+            } else if population[q].dominates(&population[p]) {
+                domination_count[p] += 1;
+            }
+        }
+        if domination_count[p] == 0 {
+            population[p].rank = 0;
+            fronts[0].push(p);
+        }
+    }
+
+    let mut i = 0;
+    while !fronts[i].is_empty() {
+        let mut next_front = Vec::new();
+        for p in fronts[i].iter() {
+            for q in dominated_by[*p].iter() {
+                domination_count[*q] -= 1;
+                if domination_count[*q] == 0 {
+                    population[*q].rank = i + 1;
+                    next_front.push(*q);
+                }
+            }
+        }
+        i += 1;
+        fronts.push(next_front);
+    }
+    fronts.pop();
+
+    fronts
+}
+
+/// Sets the crowding distance of the individuals in the given front.
+fn assign_crowding_distance(population: &mut [Individual], front: &[usize]) {
+    front
+        .iter()
+        .for_each(|i| population[*i].crowding_distance = 0.0);
+
+    let n_objectives = population[front[0]].objectives.len();
+    for m in 0..n_objectives {
+        let mut sorted = front.to_vec();
+        sorted.sort_by(|a, b| {
+            population[*a].objectives[m]
+                .partial_cmp(&population[*b].objectives[m])
+                .unwrap()
+        });
+
+        let min = population[sorted[0]].objectives[m];
+        let max = population[sorted[sorted.len() - 1]].objectives[m];
+        population[sorted[0]].crowding_distance = f64::INFINITY;
+        population[sorted[sorted.len() - 1]].crowding_distance = f64::INFINITY;
+        if max - min <= 0.0 {
+            continue;
+        }
+
+        for k in 1..sorted.len().saturating_sub(1) {
+            let distance = (population[sorted[k + 1]].objectives[m]
+                - population[sorted[k - 1]].objectives[m])
+                / (max - min);
+            population[sorted[k]].crowding_distance += distance;
+        }
+    }
+}
+
+/// Binary tournament selection using the crowded-comparison operator.
+fn tournament<'a, R: Rng>(population: &'a [Individual], rng: &mut R) -> &'a Individual {
+    let a = &population[rng.gen_range(0..population.len())];
+    let b = &population[rng.gen_range(0..population.len())];
+    match a.crowded_cmp(b) {
+        Ordering::Greater => b,
+        _ => a,
+    }
+}
+
+struct Problem<'a> {
+    permutator: LayoutPermutator,
+    layout_generator: &'a dyn LayoutGenerator,
+    evaluator: &'a Evaluator,
+    objectives: Objectives,
+    result_cache: Option<Cache<(Vec<f64>, f64)>>,
+}
+
+impl<'a> Problem<'a> {
+    fn evaluate(&self, permutation: Vec<usize>) -> Individual {
+        let layout = self.permutator.generate_string(&permutation);
+        let evaluate_layout_str = || -> (Vec<f64>, f64) {
+            let l = self.layout_generator.generate(&layout).unwrap();
+            let evaluation_result = self.evaluator.evaluate_layout(&l);
+            (
+                self.objectives.values(&evaluation_result),
+                evaluation_result.total_cost(),
+            )
+        };
+
+        let (objectives, total_cost) = match &self.result_cache {
+            Some(result_cache) => result_cache.get_or_insert_with(&layout, evaluate_layout_str),
+            None => evaluate_layout_str(),
+        };
+
+        Individual {
+            permutation,
+            layout,
+            objectives,
+            total_cost,
+            rank: 0,
+            crowding_distance: 0.0,
+        }
+    }
+}
+
+/// Selects the next population from the union of parents and offspring (NSGA-II survival).
+fn select_survivors(mut population: Vec<Individual>, size: usize) -> Vec<Individual> {
+    let fronts = non_dominated_sort(&mut population);
+    for front in fronts.iter() {
+        assign_crowding_distance(&mut population, front);
+    }
+
+    population.sort_by(|a, b| a.crowded_cmp(b));
+    population.truncate(size);
+
+    population
+}
+
+/// Performs an NSGA-II optimization and returns the (unique) layouts of the resulting Pareto front.
+#[allow(clippy::too_many_arguments)]
+pub fn optimize(
+    params: &Parameters,
+    layout_str: &str,
+    fixed_characters: &str,
+    layout_generator: &dyn LayoutGenerator,
+    start_with_layout: bool,
+    evaluator: &Evaluator,
+    log_every: usize,
+    result_cache: Option<Cache<(Vec<f64>, f64)>>,
+) -> Vec<ParetoLayout> {
+    let pm = LayoutPermutator::new(layout_str, fixed_characters);
+    let problem = Problem {
+        permutator: pm.clone(),
+        layout_generator,
+        evaluator,
+        objectives: Objectives {
+            configs: params.objectives.clone(),
+        },
+        result_cache,
+    };
+
+    // initial population
+    let mut initial_permutations: Vec<Vec<usize>> = (0..params.population_size)
+        .map(|_| pm.generate_random())
+        .collect();
+    if start_with_layout && !initial_permutations.is_empty() {
+        initial_permutations[0] = pm.get_permutable_indices();
+    }
+    let population: Vec<Individual> = initial_permutations
+        .into_par_iter()
+        .map(|permutation| problem.evaluate(permutation))
+        .collect();
+    let mut population = select_survivors(population, params.population_size);
+
+    log::info!(
+        "Starting optimization with {:?}",
+        params
+            .objectives
+            .iter()
+            .map(|o| o.name.as_str())
+            .collect::<Vec<&str>>()
+    );
+
+    for generation in 0..params.generation_limit {
+        // generate offspring by selection and mutation
+        let rng = &mut thread_rng();
+        let offspring_permutations: Vec<Vec<usize>> = (0..params.population_size)
+            .map(|_| {
+                let parent = tournament(&population, rng);
+                pm.perform_n_swaps(&parent.permutation, params.key_switches)
+            })
+            .collect();
+        let offspring: Vec<Individual> = offspring_permutations
+            .into_par_iter()
+            .map(|permutation| problem.evaluate(permutation))
+            .collect();
+
+        // remove duplicate layouts to keep the population diverse
+        population.extend(offspring);
+        population.sort_by(|a, b| a.layout.cmp(&b.layout));
+        population.dedup_by(|a, b| a.layout == b.layout);
+        population.shuffle(rng);
+
+        population = select_survivors(population, params.population_size);
+
+        if log_every > 0 && (generation + 1) % log_every == 0 {
+            let front_size = population.iter().filter(|i| i.rank == 0).count();
+            let best: Vec<String> = (0..params.objectives.len())
+                .map(|m| {
+                    let min = population
+                        .iter()
+                        .map(|i| i.objectives[m])
+                        .fold(f64::INFINITY, f64::min);
+                    format!("{}: {:.2}", params.objectives[m].name, min)
+                })
+                .collect();
+            log::info!(
+                "{} {:>4}, {} {:>3}, {} {}",
+                "Generation:".bold(),
+                generation + 1,
+                "Pareto front size:".bold(),
+                front_size,
+                "best:".bold(),
+                best.join(", ")
+            );
+        }
+    }
+
+    let mut front: Vec<ParetoLayout> = population
+        .into_iter()
+        .filter(|i| i.rank == 0)
+        .map(|i| ParetoLayout {
+            layout: i.layout,
+            objectives: i.objectives,
+            total_cost: i.total_cost,
+        })
+        .collect();
+    front.sort_by(|a, b| a.objectives.partial_cmp(&b.objectives).unwrap());
+
+    front
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn individual(objectives: &[f64]) -> Individual {
+        Individual {
+            permutation: Vec::new(),
+            layout: String::new(),
+            objectives: objectives.to_vec(),
+            total_cost: objectives.iter().sum(),
+            rank: 0,
+            crowding_distance: 0.0,
+        }
+    }
+
+    #[test]
+    fn domination_requires_an_improvement() {
+        let a = individual(&[1.0, 2.0]);
+        assert!(a.dominates(&individual(&[1.0, 3.0])));
+        assert!(a.dominates(&individual(&[2.0, 3.0])));
+        assert!(!a.dominates(&individual(&[1.0, 2.0])));
+        assert!(!a.dominates(&individual(&[0.0, 3.0])));
+    }
+
+    #[test]
+    fn population_is_sorted_into_fronts() {
+        let mut population: Vec<Individual> = [
+            [1.0, 4.0],
+            [2.0, 2.0],
+            [4.0, 1.0],
+            [2.0, 4.0],
+            [3.0, 3.0],
+            [4.0, 4.0],
+            [1.0, 4.0],
+        ]
+        .iter()
+        .map(|o| individual(o))
+        .collect();
+
+        let mut fronts = non_dominated_sort(&mut population);
+        fronts.iter_mut().for_each(|front| front.sort_unstable());
+
+        assert_eq!(fronts, vec![vec![0, 1, 2, 6], vec![3, 4], vec![5]]);
+        let ranks: Vec<usize> = population.iter().map(|i| i.rank).collect();
+        assert_eq!(ranks, vec![0, 0, 0, 1, 1, 2, 0]);
+    }
+
+    #[test]
+    fn crowding_distance_favors_boundary_and_isolated_individuals() {
+        let mut population: Vec<Individual> = [[0.0, 4.0], [1.0, 3.0], [3.0, 1.0], [4.0, 0.0]]
+            .iter()
+            .map(|o| individual(o))
+            .collect();
+
+        assign_crowding_distance(&mut population, &[0, 1, 2, 3]);
+
+        let distances: Vec<f64> = population.iter().map(|i| i.crowding_distance).collect();
+        assert_eq!(distances[0], f64::INFINITY);
+        assert_eq!(distances[3], f64::INFINITY);
+        // (3 - 0) / 4 for each of the two objectives
+        assert!((distances[1] - 1.5).abs() < 1e-12);
+        assert!((distances[2] - 1.5).abs() < 1e-12);
+
+        // in a front of identical individuals, the boundary ones are kept
+        let mut population = vec![individual(&[1.0, 1.0]); 3];
+        assign_crowding_distance(&mut population, &[0, 1, 2]);
+        assert_eq!(population[1].crowding_distance, 0.0);
+    }
+
+    #[test]
+    fn survivors_are_selected_by_rank_and_crowding_distance() {
+        let population: Vec<Individual> =
+            [[0.0, 4.0], [1.0, 3.0], [1.1, 2.9], [4.0, 0.0], [5.0, 5.0]]
+                .iter()
+                .map(|o| individual(o))
+                .collect();
+
+        let survivors = select_survivors(population, 3);
+
+        let objectives: Vec<Vec<f64>> = survivors.iter().map(|i| i.objectives.clone()).collect();
+        assert_eq!(objectives.len(), 3);
+        assert!(survivors.iter().all(|i| i.rank == 0));
+        assert!(objectives.contains(&vec![0.0, 4.0]));
+        assert!(objectives.contains(&vec![4.0, 0.0]));
+    }
+}
@@ -1,4 +1,5 @@
 pub mod optimization;
+pub mod parallel_tempering;
 
 #[cfg(test)]
 mod tests {
@@ -1,16 +1,28 @@
 use keyboard_layout::{layout::Layout, layout_generator::LayoutGenerator};
 use layout_evaluation::{cache::Cache, evaluation::Evaluator};
 
-use layout_optimization_common::LayoutPermutator;
+use layout_optimization_common::{
+    checkpoint::{self, Checkpointing},
+    constraints::Constraint,
+    learnability::{self, Learnability},
+    LayoutPermutator,
+};
 
 use anyhow::Result;
 use colored::Colorize;
-use rand_xoshiro::{rand_core::SeedableRng, Xoshiro256PlusPlus};
-use serde::Deserialize;
-use std::{fs::File, sync::Arc};
+use rand_xoshiro::{
+    rand_core::{RngCore, SeedableRng},
+    Xoshiro256PlusPlus,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    sync::{Arc, Mutex},
+};
 
 use argmin::{
     core::{
+        checkpointing::{Checkpoint, CheckpointingFrequency},
         observers::{Observe, ObserverMode},
         CostFunction, Error, Executor, IterState, State, KV,
     },
@@ -32,6 +44,14 @@ pub struct Parameters {
     // Parameters for the [Executor].
     /// Set maximum number of iterations (defaults to `std::u64::MAX`)
     pub max_iters: u64,
+
+    /// Hard constraints on the placement of characters
+    #[serde(default)]
+    pub constraints: Vec<Constraint>,
+
+    /// Penalty or limit for keys moved with respect to a reference layout
+    #[serde(default)]
+    pub learnability: learnability::Parameters,
 }
 
 impl Default for Parameters {
@@ -43,6 +63,8 @@ impl Default for Parameters {
             stall_accepted: 5000,
             // Parameters for the [Executor].
             max_iters: 100_000,
+            constraints: Vec::new(),
+            learnability: learnability::Parameters::default(),
         }
     }
 }
@@ -71,7 +93,10 @@ pub struct AnnealingStruct {
     permutator: LayoutPermutator,
     layout_generator: Box<dyn LayoutGenerator>,
     key_switches: usize,
+    learnability: Learnability,
     result_cache: Option<Cache<f64>>,
+    /// Random number generator for the neighbors of a layout (shared with the checkpoint)
+    rng: Arc<Mutex<Xoshiro256PlusPlus>>,
 }
 
 impl CostFunction for AnnealingStruct {
@@ -92,7 +117,7 @@ impl CostFunction for AnnealingStruct {
             None => evaluate_layout_str(&layout_string),
         };
 
-        Ok(evaluation_result)
+        Ok(evaluation_result + self.learnability.penalty(&layout_string))
     }
 }
 
@@ -103,12 +128,75 @@ impl Anneal for AnnealingStruct {
 
     /// Anneal a parameter vector, slightly changing it.
     fn anneal(&self, param: &Self::Param, _temp: f64) -> Result<Self::Output, Error> {
-        Ok(self.permutator.perform_n_swaps(param, self.key_switches))
+        let mut rng = self.rng.lock().unwrap();
+        Ok(self
+            .permutator
+            .perform_n_swaps_with_rng(param, self.key_switches, &mut *rng))
     }
 }
 
 pub type SaIterState = IterState<Vec<usize>, (), (), (), f64>;
 
+type SaSolver = SimulatedAnnealing<f64, Xoshiro256PlusPlus>;
+
+/// The contents of a checkpoint file (for writing).
+#[derive(Serialize)]
+struct CheckpointRef<'a> {
+    layout_str: &'a str,
+    fixed_characters: &'a str,
+    solver: &'a SaSolver,
+    state: &'a SaIterState,
+    rng: &'a Xoshiro256PlusPlus,
+}
+
+/// The contents of a checkpoint file (for reading).
+#[derive(Deserialize)]
+struct CheckpointData {
+    layout_str: String,
+    fixed_characters: String,
+    solver: SaSolver,
+    state: SaIterState,
+    rng: Xoshiro256PlusPlus,
+}
+
+/// Periodically writes the solver (temperature, random number generator, stall counters), its
+/// state (current and best layout, iteration) and the random number generator for the neighbors
+/// of a layout to a checkpoint file.
+struct LayoutCheckpoint {
+    checkpointing: Checkpointing,
+    layout_str: String,
+    fixed_characters: String,
+    rng: Arc<Mutex<Xoshiro256PlusPlus>>,
+}
+
+impl Checkpoint<SaSolver, SaIterState> for LayoutCheckpoint {
+    fn save(&self, solver: &SaSolver, state: &SaIterState) -> Result<(), Error> {
+        checkpoint::save(
+            &self.checkpointing.path,
+            &CheckpointRef {
+                layout_str: &self.layout_str,
+                fixed_characters: &self.fixed_characters,
+                solver,
+                state,
+                rng: &self.rng.lock().unwrap(),
+            },
+        )
+    }
+
+    /// Checkpoints are loaded before the executor is set up (the layout needs to be known
+    /// beforehand), so there is nothing to load here.
+    fn load(&self) -> Result<Option<(SaSolver, SaIterState)>, Error> {
+        Ok(None)
+    }
+
+    fn frequency(&self) -> CheckpointingFrequency {
+        match self.checkpointing.every {
+            0 => CheckpointingFrequency::Never,
+            every => CheckpointingFrequency::Every(every),
+        }
+    }
+}
+
 /// An observer that outputs important information in a more human-readable format than `Argmin`'s original implementation.
 struct BestObserver {
     id: String,
@@ -228,8 +316,9 @@ fn get_cost_sd(
     initial_indices: &[usize],
     evaluator: Arc<Evaluator>,
     permutator: &LayoutPermutator,
-    layout_generator: &Box<dyn LayoutGenerator>,
+    layout_generator: &dyn LayoutGenerator,
     key_pair_switches: usize,
+    rng: &mut Xoshiro256PlusPlus,
 ) -> f64 {
     const USED_NEIGHBORS: u16 = 100;
 
@@ -244,7 +333,8 @@ fn get_cost_sd(
             .unwrap();
         let evaluation_result = evaluator.evaluate_layout(&layout);
         costs.push(evaluation_result.total_cost());
-        current_indices = permutator.perform_n_swaps(&current_indices, key_pair_switches);
+        current_indices =
+            permutator.perform_n_swaps_with_rng(&current_indices, key_pair_switches, rng);
     }
     let average: f64 = mean(&costs);
 
@@ -257,7 +347,11 @@ fn get_cost_sd(
     sd
 }
 
-/// Performs one run of Simulated Annealing, then returns the best layout found.
+/// Performs one run of Simulated Annealing, then returns the best layout found together with
+/// the learnability penalty it was optimized with (e.g. for reporting moved keys).
+///
+/// If `checkpointing` is given, the state of the optimization is saved periodically. When
+/// resuming from a checkpoint, the layout and fixed characters are taken from the checkpoint.
 #[allow(clippy::too_many_arguments)]
 pub fn optimize(
     process_name: &str,
@@ -270,12 +364,66 @@ pub fn optimize(
     log_everything: bool,
     result_cache: Option<Cache<f64>>,
     custom_observer: Option<CustomObserver>,
-) -> (String, Layout) {
-    let pm = LayoutPermutator::new(layout_str, fixed_characters);
+    checkpointing: Option<Checkpointing>,
+) -> (String, Layout, Learnability) {
+    optimize_with_rng(
+        process_name,
+        params,
+        layout_str,
+        fixed_characters,
+        layout_generator.as_ref(),
+        start_with_layout,
+        evaluator,
+        log_everything,
+        result_cache,
+        custom_observer,
+        checkpointing,
+        Xoshiro256PlusPlus::from_entropy(),
+    )
+}
+
+/// Like [`optimize`], but all random numbers are drawn from generators seeded by `rng`. When
+/// resuming, the generators are taken from the checkpoint instead.
+#[allow(clippy::too_many_arguments)]
+fn optimize_with_rng(
+    process_name: &str,
+    params: &Parameters,
+    layout_str: &str,
+    fixed_characters: &str,
+    layout_generator: &dyn LayoutGenerator,
+    start_with_layout: bool,
+    evaluator: &Evaluator,
+    log_everything: bool,
+    result_cache: Option<Cache<f64>>,
+    custom_observer: Option<CustomObserver>,
+    checkpointing: Option<Checkpointing>,
+    mut rng: Xoshiro256PlusPlus,
+) -> (String, Layout, Learnability) {
+    let resumed: Option<CheckpointData> = checkpointing
+        .as_ref()
+        .filter(|c| c.resume)
+        .map(|c| checkpoint::load(&c.path).unwrap_or_else(|e| panic!("{:?}", e)));
+    let resumed_iter = resumed.as_ref().map(|data| data.state.iter);
+    let (layout_str, fixed_characters) = match &resumed {
+        Some(data) => (data.layout_str.clone(), data.fixed_characters.clone()),
+        None => (layout_str.to_string(), fixed_characters.to_string()),
+    };
+
+    let learnability = Learnability::new(
+        &params.learnability,
+        &layout_str,
+        layout_generator,
+        evaluator,
+    )
+    .unwrap_or_else(|e| panic!("Invalid learnability parameters: {:?}", e));
+    let pm = LayoutPermutator::new(&layout_str, &fixed_characters)
+        .with_constraints(&params.constraints, layout_generator)
+        .and_then(|pm| learnability.restrict(pm))
+        .unwrap_or_else(|e| panic!("Invalid constraints: {:?}", e));
     // Get initial Layout.
     let initial_indices = match start_with_layout {
-        true => pm.get_permutable_indices(),
-        false => pm.generate_random(),
+        true => pm.get_valid_permutable_indices(),
+        false => pm.generate_random_with_rng(&mut rng),
     };
 
     /* // Test 10_000 Layouts to get a good default initial temperature.
@@ -291,6 +439,7 @@ pub fn optimize(
 
     let init_temp = match params.init_temp {
         Some(t) => t,
+        None if resumed.is_some() => f64::MIN_POSITIVE, // replaced by the solver from the checkpoint
         None => {
             log::info!(
                 "{} Calculating initial temperature",
@@ -302,6 +451,7 @@ pub fn optimize(
                 &pm,
                 layout_generator,
                 params.key_switches,
+                &mut rng,
             );
             log::info!(
                 "{} Initial temperature = {}°",
@@ -311,18 +461,11 @@ pub fn optimize(
             init_temp
         }
     };
-    let problem = AnnealingStruct {
-        evaluator: Arc::new(evaluator.clone()),
-        permutator: pm.clone(),
-        layout_generator: layout_generator.clone(),
-        key_switches: params.key_switches,
-        result_cache,
-    };
 
     // Create new SA solver with some parameters (see docs for details)
     // This essentially just prepares the SA solver. It is not run yet, nor does it know anything about the problem it is about to solve.
-    let rng = Xoshiro256PlusPlus::from_entropy();
-    let solver = SimulatedAnnealing::new_with_rng(init_temp, rng)
+    let solver_rng = Xoshiro256PlusPlus::seed_from_u64(rng.next_u64());
+    let solver = SimulatedAnnealing::new_with_rng(init_temp, solver_rng)
         .unwrap()
         // Optional: Define temperature function (defaults to `SATempFunc::TemperatureFast`)
         .with_temp_func(SATempFunc::Exponential(0.998))
@@ -332,16 +475,44 @@ pub fn optimize(
         // Optional: stop if there was no accepted solution after [params.stall_accepted] iterations
         .with_stall_accepted(params.stall_accepted);
 
+    // When resuming, continue with the solver, state and random number generator from the
+    // checkpoint.
+    let (solver, resumed_state, rng) = match resumed {
+        Some(data) => (data.solver, Some(data.state), data.rng),
+        None => (solver, None, rng),
+    };
+    let rng = Arc::new(Mutex::new(rng));
+
+    let problem = AnnealingStruct {
+        evaluator: Arc::new(evaluator.clone()),
+        permutator: pm.clone(),
+        layout_generator: layout_generator.clone_box(),
+        key_switches: params.key_switches,
+        learnability: learnability.clone(),
+        result_cache,
+        rng: rng.clone(),
+    };
+
     // Create and run the executor, which will apply the solver to the problem, given a starting point (`init_param`)
     let mut executor = Executor::new(problem, solver)
-        .configure(|state| {
-            state
+        .configure(|state| match resumed_state {
+            // the maximum number of iterations may have been raised to continue a finished run
+            Some(resumed_state) => resumed_state.max_iters(params.max_iters),
+            None => state
                 // Set initial starting-param (~staring layout)
                 .param(initial_indices)
                 // Optional: Set maximum number of iterations (defaults to `std::u64::MAX`)
-                .max_iters(params.max_iters)
+                .max_iters(params.max_iters),
         })
         .timer(false);
+    if let Some(checkpointing) = &checkpointing {
+        executor = executor.checkpointing(LayoutCheckpoint {
+            checkpointing: checkpointing.clone(),
+            layout_str,
+            fixed_characters,
+            rng,
+        });
+    }
     match custom_observer {
         // If a custom Observer was supplied, only use that Observer.
         Some(observer) => {
@@ -370,17 +541,157 @@ pub fn optimize(
         }
     }
 
-    log::info!(
-        "{} Starting optimization with: initial_temperature: {:.2}°, {:?}",
-        format!("{}:", process_name).yellow().bold(),
-        init_temp,
-        params,
-    );
+    match resumed_iter {
+        Some(iter) => log::info!(
+            "{} Resuming optimization at iteration {}",
+            format!("{}:", process_name).yellow().bold(),
+            iter,
+        ),
+        None => log::info!(
+            "{} Starting optimization with: initial_temperature: {:.2}°, {:?}",
+            format!("{}:", process_name).yellow().bold(),
+            init_temp,
+            params,
+        ),
+    }
     let res = executor.run().unwrap();
 
     let best_layout_param = res.state().get_best_param().unwrap();
     let best_layout_str = pm.generate_string(best_layout_param);
     let best_layout = layout_generator.generate(&best_layout_str).unwrap();
 
-    (best_layout_str, best_layout)
+    (best_layout_str, best_layout, learnability)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use keyboard_layout::{
+        config::LayoutConfig, keyboard::Keyboard, neo_layout_generator::NeoLayoutGenerator,
+    };
+    use layout_evaluation::{
+        config::EvaluationParameters,
+        ngram_mapper::on_demand_ngram_mapper::OnDemandNgramMapper,
+        ngrams::{Bigrams, Trigrams, Unigrams},
+    };
+
+    const LAYOUT: &str = "jduaxphlmwqßctieobnrsgfvüäöyz,.k";
+    const FIXED: &str = ",.";
+    // all characters of the layout occur in the text, such that no swap leaves the cost unchanged
+    // (ties would be decided by rounding errors)
+    const TEXT: &str = "Die Tastaturbelegung wird so lange optimiert, bis keine bessere mehr \
+                        gefunden wird. The quick brown fox jumps over the lazy dog. Größere \
+                        Übungen ärgern weiße Äffchen.";
+
+    type Trajectory = Arc<Mutex<Vec<(u64, Vec<usize>, f64)>>>;
+
+    /// Records the current layout and cost of each iteration.
+    struct TrajectoryObserver(Trajectory);
+
+    impl Observe<SaIterState> for TrajectoryObserver {
+        fn observe_iter(&mut self, state: &SaIterState, _kv: &KV) -> Result<(), Error> {
+            self.0
+                .lock()
+                .unwrap()
+                .push((state.iter, state.param.clone().unwrap(), state.cost));
+            Ok(())
+        }
+    }
+
+    fn init() -> (Box<dyn LayoutGenerator>, Evaluator) {
+        let layout_config = LayoutConfig::from_yaml("../../config/keyboard/standard.yml").unwrap();
+        let keyboard = Arc::new(Keyboard::from_yaml_object(layout_config.keyboard));
+        let layout_generator: Box<dyn LayoutGenerator> = Box::new(NeoLayoutGenerator::from_object(
+            layout_config.base_layout,
+            keyboard,
+        ));
+
+        let eval_params =
+            EvaluationParameters::from_yaml("../../config/evaluation/default.yml").unwrap();
+        let ngram_mapper = OnDemandNgramMapper::with_ngrams(
+            Unigrams::from_text(TEXT).unwrap(),
+            Bigrams::from_text(TEXT).unwrap(),
+            Trigrams::from_text(TEXT).unwrap(),
+            eval_params.ngram_mapper.clone(),
+        );
+        let evaluator = Evaluator::default(Box::new(ngram_mapper))
+            .default_metrics(&eval_params.metrics)
+            .with_aggregation(&eval_params.aggregation);
+
+        (layout_generator, evaluator)
+    }
+
+    fn run(
+        max_iters: u64,
+        checkpointing: Option<Checkpointing>,
+        layout_generator: &dyn LayoutGenerator,
+        evaluator: &Evaluator,
+    ) -> Vec<(u64, Vec<usize>, f64)> {
+        let params = Parameters {
+            max_iters,
+            stall_accepted: 1000,
+            ..Default::default()
+        };
+        let trajectory = Trajectory::default();
+        optimize_with_rng(
+            "test",
+            &params,
+            LAYOUT,
+            FIXED,
+            layout_generator,
+            false,
+            evaluator,
+            false,
+            None,
+            Some(CustomObserver(Box::new(TrajectoryObserver(
+                trajectory.clone(),
+            )))),
+            checkpointing,
+            Xoshiro256PlusPlus::seed_from_u64(0),
+        );
+
+        let trajectory = trajectory.lock().unwrap().clone();
+        trajectory
+    }
+
+    #[test]
+    fn resumed_run_continues_the_interrupted_run() {
+        let (layout_generator, evaluator) = init();
+        let path = std::env::temp_dir()
+            .join(format!("sa_checkpoint_{}.yml", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        let checkpointing = |resume| Checkpointing {
+            path: path.clone(),
+            every: 50,
+            resume,
+        };
+
+        let uninterrupted = run(200, None, layout_generator.as_ref(), &evaluator);
+        let mut interrupted = run(
+            100,
+            Some(checkpointing(false)),
+            layout_generator.as_ref(),
+            &evaluator,
+        );
+        let resumed = run(
+            200,
+            Some(checkpointing(true)),
+            layout_generator.as_ref(),
+            &evaluator,
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(uninterrupted.len(), 200);
+        assert_eq!(interrupted.len(), 100);
+        interrupted.extend(resumed);
+        // the costs may differ in the last digits due to the summation order in the evaluation
+        for ((iter, param, cost), (expected_iter, expected_param, expected_cost)) in
+            interrupted.iter().zip(uninterrupted.iter())
+        {
+            assert_eq!((iter, param), (expected_iter, expected_param));
+            assert!((cost - expected_cost).abs() < 1e-9, "iteration {}", iter);
+        }
+    }
 }
@@ -0,0 +1,364 @@
+//! The `parallel_tempering` module provides a parallel tempering (replica exchange) optimizer.
+//!
+//! Several Metropolis chains run at fixed temperatures on separate threads. After a number of
+//! iterations, the states of chains with neighboring temperatures are exchanged according to
+//! the Metropolis criterion. This way, good layouts found by the "hot" chains that explore the
+//! search space move down to the "cold" chains that refine them.
+
+use keyboard_layout::{layout::Layout, layout_generator::LayoutGenerator};
+use layout_evaluation::{cache::Cache, evaluation::Evaluator};
+
+use layout_optimization_common::LayoutPermutator;
+
+use anyhow::Result;
+use colored::Colorize;
+use rand::Rng;
+use rand_xoshiro::{rand_core::SeedableRng, Xoshiro256PlusPlus};
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::fs::File;
+
+#[derive(Deserialize, Debug)]
+pub struct Parameters {
+    /// Number of chains (each running at its own temperature)
+    pub n_chains: usize,
+
+    /// Temperature of the coldest chain
+    pub min_temp: f64,
+
+    /// Temperature of the hottest chain. The temperatures in between are spaced geometrically.
+    pub max_temp: f64,
+
+    /// In each modification of the layout, swap this many key-pairs.
+    pub key_switches: usize,
+
+    /// Number of iterations of each chain between two exchange rounds
+    pub exchange_interval: u64,
+
+    /// Stop if the best solution did not improve for this many exchange rounds
+    pub stall_rounds: u64,
+
+    /// Maximum number of iterations of each chain
+    pub max_iters: u64,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Parameters {
+            n_chains: 8,
+            min_temp: 0.5,
+            max_temp: 20.0,
+            key_switches: 1,
+            exchange_interval: 100,
+            stall_rounds: 50,
+            max_iters: 100_000,
+        }
+    }
+}
+
+impl Parameters {
+    pub fn from_yaml(filename: &str) -> Result<Self> {
+        let f = File::open(filename)?;
+        Ok(serde_yaml::from_reader(f)?)
+    }
+
+    /// The (geometrically spaced) temperatures of the chains in ascending order.
+    pub fn temperatures(&self) -> Vec<f64> {
+        let n_chains = self.n_chains.max(1);
+        if n_chains == 1 {
+            return vec![self.min_temp];
+        }
+
+        let ratio = (self.max_temp / self.min_temp).powf(1.0 / (n_chains - 1) as f64);
+        (0..n_chains)
+            .map(|i| self.min_temp * ratio.powi(i as i32))
+            .collect()
+    }
+}
+
+/// The cost function shared by all chains.
+struct Problem<'a> {
+    evaluator: &'a Evaluator,
+    permutator: &'a LayoutPermutator,
+    layout_generator: &'a dyn LayoutGenerator,
+    key_switches: usize,
+    result_cache: Option<Cache<f64>>,
+}
+
+impl<'a> Problem<'a> {
+    fn cost(&self, param: &[usize]) -> f64 {
+        let evaluate_layout_str = |layout_str: &str| -> f64 {
+            let l = self.layout_generator.generate(layout_str).unwrap();
+            self.evaluator.evaluate_layout(&l).total_cost()
+        };
+
+        let layout_string = self.permutator.generate_string(param);
+        match &self.result_cache {
+            Some(result_cache) => result_cache
+                .get_or_insert_with(&layout_string, || evaluate_layout_str(&layout_string)),
+            None => evaluate_layout_str(&layout_string),
+        }
+    }
+}
+
+/// A Metropolis chain at a fixed temperature.
+struct Chain {
+    temperature: f64,
+    param: Vec<usize>,
+    cost: f64,
+    best_param: Vec<usize>,
+    best_cost: f64,
+    accepted: u64,
+    iterations: u64,
+    rng: Xoshiro256PlusPlus,
+}
+
+impl Chain {
+    fn new(temperature: f64, param: Vec<usize>, cost: f64) -> Self {
+        Self {
+            temperature,
+            best_param: param.clone(),
+            best_cost: cost,
+            param,
+            cost,
+            accepted: 0,
+            iterations: 0,
+            rng: Xoshiro256PlusPlus::from_entropy(),
+        }
+    }
+
+    /// Perform `n_iters` Metropolis steps.
+    fn run(&mut self, problem: &Problem, n_iters: u64) {
+        for _ in 0..n_iters {
+            let candidate = problem
+                .permutator
+                .perform_n_swaps(&self.param, problem.key_switches);
+            let candidate_cost = problem.cost(&candidate);
+
+            let delta = candidate_cost - self.cost;
+            if delta <= 0.0 || self.rng.gen::<f64>() < (-delta / self.temperature).exp() {
+                self.param = candidate;
+                self.cost = candidate_cost;
+                self.accepted += 1;
+
+                if self.cost < self.best_cost {
+                    self.best_cost = self.cost;
+                    self.best_param = self.param.clone();
+                }
+            }
+            self.iterations += 1;
+        }
+    }
+}
+
+/// Attempt exchanges of the states between neighboring chains (every other pair, alternating
+/// with `round`). Returns the number of performed exchanges.
+fn exchange(chains: &mut [Chain], round: u64, rng: &mut Xoshiro256PlusPlus) -> usize {
+    let mut n_exchanges = 0;
+    let mut i = (round % 2) as usize;
+    while i + 1 < chains.len() {
+        let (cold, hot) = (&chains[i], &chains[i + 1]);
+        // Metropolis criterion for exchanging the states of two chains
+        let log_acceptance =
+            (1.0 / cold.temperature - 1.0 / hot.temperature) * (cold.cost - hot.cost);
+        if log_acceptance >= 0.0 || rng.gen::<f64>() < log_acceptance.exp() {
+            let (left, right) = chains.split_at_mut(i + 1);
+            let (cold, hot) = (&mut left[i], &mut right[0]);
+            std::mem::swap(&mut cold.param, &mut hot.param);
+            std::mem::swap(&mut cold.cost, &mut hot.cost);
+            n_exchanges += 1;
+        }
+        i += 2;
+    }
+
+    n_exchanges
+}
+
+/// Performs one run of parallel tempering, then returns the best layout found.
+#[allow(clippy::too_many_arguments)]
+pub fn optimize(
+    process_name: &str,
+    params: &Parameters,
+    layout_str: &str,
+    fixed_characters: &str,
+    layout_generator: &dyn LayoutGenerator,
+    start_with_layout: bool,
+    evaluator: &Evaluator,
+    result_cache: Option<Cache<f64>>,
+) -> (String, Layout) {
+    let pm = LayoutPermutator::new(layout_str, fixed_characters);
+    let problem = Problem {
+        evaluator,
+        permutator: &pm,
+        layout_generator,
+        key_switches: params.key_switches,
+        result_cache,
+    };
+
+    let temperatures = params.temperatures();
+    let mut chains: Vec<Chain> = temperatures
+        .par_iter()
+        .map(|temperature| {
+            let initial_indices = match start_with_layout {
+                true => pm.get_permutable_indices(),
+                false => pm.generate_random(),
+            };
+            let cost = problem.cost(&initial_indices);
+            Chain::new(*temperature, initial_indices, cost)
+        })
+        .collect();
+
+    log::info!(
+        "{} Starting optimization with temperatures [{}], {:?}",
+        format!("{}:", process_name).yellow().bold(),
+        temperatures
+            .iter()
+            .map(|t| format!("{:.2}°", t))
+            .collect::<Vec<String>>()
+            .join(", "),
+        params,
+    );
+
+    let mut rng = Xoshiro256PlusPlus::from_entropy();
+    let exchange_interval = params.exchange_interval.max(1);
+    let n_rounds = (params.max_iters + exchange_interval - 1) / exchange_interval;
+    let mut best_cost = f64::INFINITY;
+    let mut best_param = chains[0].param.clone();
+    let mut stall_rounds = 0;
+    let mut n_exchanges = 0;
+
+    for round in 0..n_rounds {
+        chains
+            .par_iter_mut()
+            .for_each(|chain| chain.run(&problem, exchange_interval));
+
+        n_exchanges += exchange(&mut chains, round, &mut rng);
+
+        // the chains keep track of their best states (exchanges only move current states)
+        let round_best = chains
+            .iter()
+            .min_by(|c1, c2| c1.best_cost.partial_cmp(&c2.best_cost).unwrap())
+            .unwrap();
+        if round_best.best_cost < best_cost {
+            best_cost = round_best.best_cost;
+            best_param = round_best.best_param.clone();
+            stall_rounds = 0;
+            log::info!(
+                "{} {} {} ({:>6.1})",
+                format!("{}:", process_name).yellow().bold(),
+                "New best:".green(),
+                pm.generate_string(&best_param),
+                best_cost,
+            );
+        } else {
+            stall_rounds += 1;
+        }
+
+        log::info!(
+            "{} {} {:>3}, {} {:>6.1}, {} [{}], {} {}",
+            format!("{}:", process_name).yellow().bold(),
+            "round:".bold(),
+            round,
+            "best:".bold(),
+            best_cost,
+            "costs (acc.):".bold(),
+            chains
+                .iter()
+                .map(|c| format!(
+                    "{:.1} ({:.0}%)",
+                    c.cost,
+                    100.0 * c.accepted as f64 / c.iterations.max(1) as f64
+                ))
+                .collect::<Vec<String>>()
+                .join(", "),
+            "exchanges:".bold(),
+            n_exchanges,
+        );
+
+        if stall_rounds >= params.stall_rounds {
+            log::info!(
+                "{} Stopping after {} rounds without improvement",
+                format!("{}:", process_name).yellow().bold(),
+                stall_rounds,
+            );
+            break;
+        }
+    }
+
+    let best_layout_str = pm.generate_string(&best_param);
+    let best_layout = layout_generator.generate(&best_layout_str).unwrap();
+
+    (best_layout_str, best_layout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temperatures_are_spaced_geometrically() {
+        let params = Parameters {
+            n_chains: 4,
+            min_temp: 1.0,
+            max_temp: 8.0,
+            ..Default::default()
+        };
+        let temperatures = params.temperatures();
+        assert_eq!(temperatures.len(), 4);
+        for (temperature, expected) in temperatures.iter().zip([1.0, 2.0, 4.0, 8.0]) {
+            assert!((temperature - expected).abs() < 1e-9);
+        }
+
+        for n_chains in [0, 1] {
+            let params = Parameters {
+                n_chains,
+                min_temp: 1.0,
+                max_temp: 8.0,
+                ..Default::default()
+            };
+            assert_eq!(params.temperatures(), vec![1.0]);
+        }
+    }
+
+    /// Chains with the given temperatures and costs, the params identify the initial chain.
+    fn chains(temperatures: &[f64], costs: &[f64]) -> Vec<Chain> {
+        temperatures
+            .iter()
+            .zip(costs)
+            .enumerate()
+            .map(|(i, (temperature, cost))| Chain::new(*temperature, vec![i], *cost))
+            .collect()
+    }
+
+    fn params(chains: &[Chain]) -> Vec<usize> {
+        chains.iter().map(|c| c.param[0]).collect()
+    }
+
+    #[test]
+    fn exchanges_alternate_between_neighboring_pairs() {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+        // the colder chain always has the higher cost, so every attempted exchange happens
+        let mut chains = chains(&[1.0, 2.0, 4.0, 8.0], &[4.0, 3.0, 2.0, 1.0]);
+
+        assert_eq!(exchange(&mut chains, 0, &mut rng), 2);
+        assert_eq!(params(&chains), vec![1, 0, 3, 2]);
+
+        assert_eq!(exchange(&mut chains, 1, &mut rng), 1);
+        assert_eq!(params(&chains), vec![1, 3, 0, 2]);
+
+        let costs: Vec<f64> = chains.iter().map(|c| c.cost).collect();
+        assert_eq!(costs, vec![3.0, 1.0, 4.0, 2.0]);
+        // the temperatures stay with the chains
+        let temperatures: Vec<f64> = chains.iter().map(|c| c.temperature).collect();
+        assert_eq!(temperatures, vec![1.0, 2.0, 4.0, 8.0]);
+    }
+
+    #[test]
+    fn much_better_cold_chains_are_kept() {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+        let mut chains = chains(&[1.0, 2.0], &[0.0, 1000.0]);
+
+        assert_eq!(exchange(&mut chains, 0, &mut rng), 0);
+        assert_eq!(params(&chains), vec![0, 1]);
+    }
+}
@@ -0,0 +1,266 @@
+//! A tabu search over the swaps of two keys.
+//!
+//! In each iteration, all swaps of two permutable keys are evaluated (in parallel) and the best
+//! one is performed, even if it worsens the layout. Swapping a key pair back is forbidden ("tabu")
+//! for a number of iterations, unless it leads to a layout better than all layouts found before
+//! ("aspiration").
+
+use keyboard_layout::{layout::Layout, layout_generator::LayoutGenerator};
+use layout_evaluation::{cache::Cache, evaluation::Evaluator};
+
+use layout_optimization_common::LayoutPermutator;
+
+use anyhow::Result;
+use colored::Colorize;
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::fs::File;
+
+#[derive(Deserialize, Debug)]
+pub struct Parameters {
+    /// Number of iterations a swapped key pair may not be swapped again
+    pub tabu_tenure: u64,
+
+    /// Stop if the best solution did not improve for this many iterations
+    pub stall_iters: u64,
+
+    /// Maximum number of iterations
+    pub max_iters: u64,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Parameters {
+            tabu_tenure: 20,
+            stall_iters: 50,
+            max_iters: 1000,
+        }
+    }
+}
+
+impl Parameters {
+    pub fn from_yaml(filename: &str) -> Result<Self> {
+        let f = File::open(filename)?;
+        Ok(serde_yaml::from_reader(f)?)
+    }
+}
+
+/// Remembers until which iteration the swap of a key pair is tabu.
+struct TabuList {
+    tabu_until: Vec<Vec<u64>>,
+}
+
+impl TabuList {
+    fn new(n_keys: usize) -> Self {
+        Self {
+            tabu_until: vec![vec![0; n_keys]; n_keys],
+        }
+    }
+
+    fn is_tabu(&self, i: usize, j: usize, iteration: u64) -> bool {
+        self.tabu_until[i][j] > iteration
+    }
+
+    fn insert(&mut self, i: usize, j: usize, until: u64) {
+        self.tabu_until[i][j] = until;
+        self.tabu_until[j][i] = until;
+    }
+
+    fn len(&self, iteration: u64) -> usize {
+        self.tabu_until
+            .iter()
+            .map(|row| row.iter().filter(|until| **until > iteration).count())
+            .sum::<usize>()
+            / 2
+    }
+}
+
+/// The best swap that is either not tabu or leads to a layout better than `best_cost`
+/// ("aspiration").
+fn best_move(
+    neighbors: &[((usize, usize), f64)],
+    tabu_list: &TabuList,
+    iteration: u64,
+    best_cost: f64,
+) -> Option<((usize, usize), f64)> {
+    neighbors
+        .iter()
+        .filter(|((i, j), c)| !tabu_list.is_tabu(*i, *j, iteration) || *c < best_cost)
+        .min_by(|(_, c1), (_, c2)| c1.partial_cmp(c2).unwrap())
+        .cloned()
+}
+
+/// Performs one run of the tabu search, then returns the best layout found.
+#[allow(clippy::too_many_arguments)]
+pub fn optimize(
+    process_name: &str,
+    params: &Parameters,
+    layout_str: &str,
+    fixed_characters: &str,
+    layout_generator: &dyn LayoutGenerator,
+    start_with_layout: bool,
+    evaluator: &Evaluator,
+    result_cache: Option<Cache<f64>>,
+) -> (String, Layout) {
+    let pm = LayoutPermutator::new(layout_str, fixed_characters);
+
+    let evaluate_layout_str = |layout_str: &str| -> f64 {
+        let l = layout_generator.generate(layout_str).unwrap();
+        evaluator.evaluate_layout(&l).total_cost()
+    };
+    let cost = |param: &[usize]| -> f64 {
+        let layout_string = pm.generate_string(param);
+        match &result_cache {
+            Some(result_cache) => result_cache
+                .get_or_insert_with(&layout_string, || evaluate_layout_str(&layout_string)),
+            None => evaluate_layout_str(&layout_string),
+        }
+    };
+
+    let mut param = match start_with_layout {
+        true => pm.get_permutable_indices(),
+        false => pm.generate_random(),
+    };
+    let mut current_cost = cost(&param);
+    let mut best_param = param.clone();
+    let mut best_cost = current_cost;
+
+    let n_keys = param.len();
+    let swaps: Vec<(usize, usize)> = (0..n_keys)
+        .flat_map(|i| (i + 1..n_keys).map(move |j| (i, j)))
+        .collect();
+    let mut tabu_list = TabuList::new(n_keys);
+
+    log::info!(
+        "{} Starting optimization with {} swaps per iteration, {:?}",
+        format!("{}:", process_name).yellow().bold(),
+        swaps.len(),
+        params,
+    );
+
+    let mut stall_iters = 0;
+    for iteration in 0..params.max_iters {
+        let neighbors: Vec<((usize, usize), f64)> = swaps
+            .par_iter()
+            .map(|(i, j)| {
+                let mut neighbor = param.clone();
+                neighbor.swap(*i, *j);
+                ((*i, *j), cost(&neighbor))
+            })
+            .collect();
+
+        let ((i, j), neighbor_cost) = match best_move(&neighbors, &tabu_list, iteration, best_cost)
+        {
+            Some(m) => m,
+            None => {
+                log::info!(
+                    "{} Stopping because all swaps are tabu",
+                    format!("{}:", process_name).yellow().bold(),
+                );
+                break;
+            }
+        };
+
+        param.swap(i, j);
+        current_cost = neighbor_cost;
+        tabu_list.insert(i, j, iteration + 1 + params.tabu_tenure);
+
+        if current_cost < best_cost {
+            best_cost = current_cost;
+            best_param = param.clone();
+            stall_iters = 0;
+            log::info!(
+                "{} {} {} ({:>6.1})",
+                format!("{}:", process_name).yellow().bold(),
+                "New best:".green(),
+                pm.generate_string(&best_param),
+                best_cost,
+            );
+        } else {
+            stall_iters += 1;
+        }
+
+        log::info!(
+            "{} {} {:>4}, {} {:>6.1}, {} {:>6.1}, {} {}",
+            format!("{}:", process_name).yellow().bold(),
+            "iteration:".bold(),
+            iteration,
+            "current:".bold(),
+            current_cost,
+            "best:".bold(),
+            best_cost,
+            "tabu swaps:".bold(),
+            tabu_list.len(iteration + 1),
+        );
+
+        if stall_iters >= params.stall_iters {
+            log::info!(
+                "{} Stopping after {} iterations without improvement",
+                format!("{}:", process_name).yellow().bold(),
+                stall_iters,
+            );
+            break;
+        }
+    }
+
+    let best_layout_str = pm.generate_string(&best_param);
+    let best_layout = layout_generator.generate(&best_layout_str).unwrap();
+
+    (best_layout_str, best_layout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tabu_swaps_expire_after_their_tenure() {
+        let mut tabu_list = TabuList::new(4);
+        tabu_list.insert(1, 2, 5);
+
+        for iteration in 0..5 {
+            assert!(tabu_list.is_tabu(1, 2, iteration));
+            assert_eq!(tabu_list.len(iteration), 1);
+        }
+        assert!(!tabu_list.is_tabu(1, 2, 5));
+        assert_eq!(tabu_list.len(5), 0);
+        assert!(!tabu_list.is_tabu(0, 1, 0));
+    }
+
+    #[test]
+    fn tabu_swaps_are_symmetric() {
+        let mut tabu_list = TabuList::new(4);
+        tabu_list.insert(3, 0, 2);
+
+        assert!(tabu_list.is_tabu(0, 3, 1));
+        assert!(tabu_list.is_tabu(3, 0, 1));
+        assert_eq!(tabu_list.len(1), 1);
+    }
+
+    #[test]
+    fn tabu_swaps_are_only_taken_if_they_improve_the_best_layout() {
+        let mut tabu_list = TabuList::new(3);
+        tabu_list.insert(0, 1, 10);
+        let neighbors = vec![((0, 1), 5.0), ((0, 2), 7.0), ((1, 2), 8.0)];
+
+        // the tabu swap is the best one, but does not lead to a new best layout
+        assert_eq!(
+            best_move(&neighbors, &tabu_list, 0, 4.0),
+            Some(((0, 2), 7.0))
+        );
+        // aspiration: the tabu swap leads to a new best layout
+        assert_eq!(
+            best_move(&neighbors, &tabu_list, 0, 6.0),
+            Some(((0, 1), 5.0))
+        );
+        // the tabu swap has expired
+        assert_eq!(
+            best_move(&neighbors, &tabu_list, 10, 4.0),
+            Some(((0, 1), 5.0))
+        );
+
+        tabu_list.insert(0, 2, 10);
+        tabu_list.insert(1, 2, 10);
+        assert_eq!(best_move(&neighbors, &tabu_list, 0, 4.0), None);
+    }
+}
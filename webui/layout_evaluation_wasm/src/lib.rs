@@ -8,7 +8,7 @@ use std::{str::FromStr, sync::Arc};
 use wasm_bindgen::prelude::*;
 
 use keyboard_layout::{
-    config::LayoutConfig, keyboard::Keyboard, layout::Layout, layout_generator::LayoutGenerator,
+    config::LayoutConfig, keyboard::Keyboard, layout_generator::LayoutGenerator,
     neo_layout_generator::NeoLayoutGenerator,
 };
 
@@ -16,12 +16,13 @@ use layout_evaluation::{
     cache::Cache,
     config::EvaluationParameters,
     evaluation::Evaluator,
+    metrics::registry::MetricRegistry,
     ngram_mapper::on_demand_ngram_mapper::OnDemandNgramMapper,
     ngrams::{Bigrams, Trigrams, Unigrams},
     results::EvaluationResult,
 };
 
-use layout_optimization_common::LayoutPermutator;
+use layout_optimization_common::{swap_suggestions, LayoutPermutator};
 use layout_optimization_genetic::optimization as genevo_optimization;
 use layout_optimization_sa::optimization::{
     self as sa_optimization, CustomObserver as SaCustomObserver, SaIterState,
@@ -178,7 +179,9 @@ impl LayoutEvaluator {
             .map_err(|e| format!("Could not read evaluation parameters: {:?}", e))?;
 
         let evaluator = Evaluator::default(Box::new(ngram_provider.ngram_provider.clone()))
-            .default_metrics(&eval_params.metrics);
+            .with_metrics(&eval_params.metrics, &MetricRegistry::default())
+            .map_err(|e| format!("Could not initialize metrics: {:?}", e))?
+            .with_aggregation(&eval_params.aggregation);
 
         Ok(LayoutEvaluator {
             layout_generator,
@@ -217,6 +220,24 @@ impl LayoutEvaluator {
         let permutable_keys = self.layout_generator.permutable_keys();
         JsValue::from_serde(&permutable_keys).unwrap()
     }
+
+    pub fn suggest_swaps(
+        &self,
+        layout_str: &str,
+        fixed_characters: &str,
+        n: usize,
+    ) -> Result<JsValue, JsValue> {
+        let layout_str: String = layout_str.chars().filter(|c| !c.is_whitespace()).collect();
+        let suggestions = swap_suggestions::suggest_swaps(
+            &layout_str,
+            fixed_characters,
+            &self.layout_generator,
+            &self.evaluator,
+            n,
+        )
+        .map_err(|e| format!("Could not evaluate the swaps: {:?}", e))?;
+        Ok(JsValue::from_serde(&suggestions).unwrap())
+    }
 }
 
 #[wasm_bindgen]
@@ -225,7 +246,7 @@ pub struct LayoutOptimizer {
     simulator: genevo_optimization::MySimulator,
     permutator: LayoutPermutator,
     layout_generator: Box<dyn LayoutGenerator>,
-    all_time_best: Option<(usize, Vec<usize>)>,
+    all_time_best: Option<(genevo_optimization::LayoutFitness, Vec<usize>)>,
     parameters: genevo_optimization::Parameters,
 }
 
@@ -396,7 +417,7 @@ pub fn sa_optimize(
 
     let layout_str: String = layout_str.chars().filter(|c| !c.is_whitespace()).collect();
 
-    let _: (String, Layout) = sa_optimization::optimize(
+    let _ = sa_optimization::optimize(
         /* Thread_name: */ "Web optimization",
         &parameters,
         &layout_str,
@@ -407,6 +428,7 @@ pub fn sa_optimize(
         /* log_everything: */ false,
         Some(Cache::new()),
         Some(SaCustomObserver(Box::new(observer))),
+        /* checkpointing: */ None,
     );
     let minus_one = JsValue::from(-1);
     let _ = update_callback.call1(&this, &minus_one);
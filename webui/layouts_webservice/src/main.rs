@@ -112,8 +112,9 @@ fn rocket() -> _ {
     let ngram_mapper =
         OnDemandNgramMapper::with_ngrams(unigrams, bigrams, trigrams, ngram_mapper_config);
 
-    let evaluator =
-        Evaluator::default(Box::new(ngram_mapper)).default_metrics(&eval_params.metrics);
+    let evaluator = Evaluator::default(Box::new(ngram_mapper))
+        .default_metrics(&eval_params.metrics)
+        .with_aggregation(&eval_params.aggregation);
 
     rocket
         .manage(evaluator)